@@ -0,0 +1,453 @@
+use std::{
+    io::{self, BufWriter, Read, Write},
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use rayon::ThreadPool;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::{JoinError, JoinSet};
+
+use crate::cancel::{self, CancelToken, Cancellable, PartFile};
+use crate::compressor::{self, CompressReport, Compressor, FileResult, Stats, ZstdCompressor};
+use crate::error::{CompressorError, PathContext, Result};
+use crate::journal::Journal;
+use crate::manifest::{self, Manifest, ManifestEntry};
+use crate::options::Filters;
+use crate::overwrite::OverwritePolicy;
+use crate::progress::{NoProgress, ProgressReader, ProgressSink};
+
+/// Bytes moved per message between the runtime and a rayon job
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Messages buffered in each direction before the faster side waits
+const CHANNEL_DEPTH: usize = 8;
+
+/// Compress `input` into `output` without blocking the runtime
+///
+/// The compressor runs on `pool`, or the global rayon pool without one, while this task only
+/// copies bytes between the streams and the job. `original_size` and `compressed_size` of
+/// the returned stats are the bytes read and written.
+pub async fn compress_stream<R, W>(
+    input: &mut R,
+    output: &mut W,
+    compressor: Arc<dyn Compressor>,
+    level: i32,
+    pool: Option<&ThreadPool>,
+) -> Result<Stats>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let start = Instant::now();
+    let ((), read, written) = pipe(input, output, pool, move |input, output| compressor.compress(input, output, level)).await?;
+    Ok(Stats { original_size: read, compressed_size: written, duration_secs: start.elapsed().as_secs_f64(), ..Default::default() })
+}
+
+/// Decompress `input` into `output` without blocking the runtime, like [`compress_stream`]
+///
+/// Damaged input fails with `CorruptData`. Here `compressed_size` counts the bytes read and
+/// `original_size` the bytes written.
+pub async fn decompress_stream<R, W>(
+    input: &mut R,
+    output: &mut W,
+    compressor: Arc<dyn Compressor>,
+    pool: Option<&ThreadPool>,
+) -> Result<Stats>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let start = Instant::now();
+    let job = move |input: &mut dyn Read, output: &mut dyn Write| {
+        compressor.decompress(input, output).map_err(CompressorError::decode_buffer)
+    };
+    let ((), read, written) = pipe(input, output, pool, job).await?;
+    Ok(Stats { original_size: written, compressed_size: read, duration_secs: start.elapsed().as_secs_f64(), ..Default::default() })
+}
+
+/// How [`compress_folder`] runs
+///
+/// Unlike [`CompressOptions`](crate::options::CompressOptions) it owns what it refers to, so
+/// the job can outlive the caller's borrows. The defaults match: zstd at its default level
+/// on the global rayon pool, refusing to overwrite existing outputs, reporting no progress
+/// and ignoring cancellation until a token is supplied.
+#[derive(Clone)]
+pub struct AsyncCompressOptions {
+    compressor: Arc<dyn Compressor>,
+    level: Option<i32>,
+    pool: Option<Arc<ThreadPool>>,
+    overwrite: OverwritePolicy,
+    filters: Filters,
+    progress: Arc<dyn ProgressSink>,
+    cancel: CancelToken,
+}
+
+impl Default for AsyncCompressOptions {
+    fn default() -> Self {
+        AsyncCompressOptions {
+            compressor: Arc::new(ZstdCompressor),
+            level: None,
+            pool: None,
+            overwrite: OverwritePolicy::default(),
+            filters: Filters::default(),
+            progress: Arc::new(NoProgress),
+            cancel: CancelToken::new(),
+        }
+    }
+}
+
+impl AsyncCompressOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compressor to write with; wrap one in an encrypting compressor to encrypt
+    pub fn format(mut self, compressor: Arc<dyn Compressor>) -> Self {
+        self.compressor = compressor;
+        self
+    }
+
+    /// Level to compress at instead of the format's default, passed on unchanged
+    pub fn level(mut self, level: i32) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    /// Rayon pool to compress on instead of the global one
+    pub fn thread_pool(mut self, pool: Arc<ThreadPool>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    pub fn overwrite(mut self, overwrite: OverwritePolicy) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Which files of the folder are compressed
+    pub fn filters(mut self, filters: Filters) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    /// Sink told about each file; it is called from rayon threads and runtime tasks alike
+    pub fn progress(mut self, progress: Arc<dyn ProgressSink>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Stop the job at the next buffer read once `token` is cancelled
+    pub fn cancel(mut self, token: &CancelToken) -> Self {
+        self.cancel = token.clone();
+        self
+    }
+
+    fn effective_level(&self) -> i32 {
+        self.level.unwrap_or_else(|| self.compressor.default_level())
+    }
+}
+
+/// Compress every file of the folder `input` into `output` next to a manifest
+///
+/// Files are opened, read and written on the runtime while their compression and hashing run
+/// on the rayon pool, one file per pool thread at a time. Sources are picked and outputs
+/// checked as [`CompressOptions::run`](crate::options::CompressOptions::run) does, and each
+/// finished file is journaled, so a job stopped by its token can be finished synchronously
+/// with `CompressMode::Resume`. Dropping the returned future abandons the files in flight
+/// and removes their `.part` files.
+pub async fn compress_folder(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    options: AsyncCompressOptions,
+) -> Result<CompressReport> {
+    let start = Instant::now();
+    let (input, output) = (input.as_ref().to_path_buf(), output.as_ref().to_path_buf());
+    let level = options.effective_level();
+    let progress = options.progress.clone();
+    let (jobs, skipped, mut manifest, journal) = {
+        let (input, output, options) = (input.clone(), output.clone(), options.clone());
+        blocking(move || {
+            if !input.is_dir() {
+                return Err(CompressorError::InvalidInput(format!("{} is not a directory", input.display())));
+            }
+            let compressor = &*options.compressor;
+            let filter = options.filters.compile()?;
+            let (plan, total_bytes) = compressor::plan_folder(&input, &output, compressor, &filter, options.overwrite)?;
+            let journal = Journal::create(&output, compressor.extension(), level)?;
+            options.progress.job_started(plan.jobs.len() + plan.skipped.len(), total_bytes);
+            let mut manifest = Manifest::default();
+            compressor::keep_skipped(&input, &output, &plan.skipped, &mut manifest, &*options.progress)?;
+            Ok((plan.jobs, plan.skipped.len(), manifest, journal))
+        })
+        .await?
+    };
+    let mut report = CompressReport {
+        stats: Stats { skipped_files: skipped, ..Default::default() },
+        manifest: Some(output.join(manifest::MANIFEST_FILE)),
+        ..Default::default()
+    };
+
+    let limit = options.pool.as_ref().map_or_else(rayon::current_num_threads, |pool| pool.current_num_threads());
+    let journal = Arc::new(Mutex::new(journal));
+    let mut pending = jobs.into_iter();
+    let mut tasks = JoinSet::new();
+    let mut failed = None;
+    loop {
+        while failed.is_none() && tasks.len() < limit
+            && let Some((file, out_file)) = pending.next()
+        {
+            let (input, output, options, journal) = (input.clone(), output.clone(), options.clone(), journal.clone());
+            tasks.spawn(async move {
+                let (entry, stats) = compress_entry(&input, &output, &file, &options, level).await?;
+                let sync_secs = {
+                    let entry = entry.clone();
+                    blocking(move || journal.lock().unwrap().record(&entry)).await?
+                };
+                Ok::<_, CompressorError>((FileResult { source: file, output: out_file, stats }, entry, sync_secs))
+            });
+        }
+        let Some(joined) = tasks.join_next().await else { break };
+        // after a failure the files in flight still finish, so none is left half written
+        match joined.map_err(join_error)? {
+            Ok((result, entry, sync_secs)) => {
+                report.stats.sync_secs += result.stats.sync_secs + sync_secs;
+                report.files.push(result);
+                manifest.insert(entry);
+            }
+            Err(err) => {
+                failed.get_or_insert(err);
+            }
+        }
+    }
+
+    let cancel = options.cancel.clone();
+    blocking(move || {
+        if let Some(err) = failed {
+            return Err(compressor::stop_folder_job(err, &*progress, &cancel, &manifest, &output));
+        }
+        progress.job_finished(None);
+        report.stats.sync_secs += manifest.save(&output)?;
+        let journal = Arc::into_inner(journal).expect("every task has finished");
+        journal.into_inner().unwrap().finish()?;
+        report.files.sort_by(|a, b| a.source.cmp(&b.source));
+        compressor::add_folder_totals(&mut report, &manifest, &output, false);
+        report.stats.duration_secs = start.elapsed().as_secs_f64();
+        Ok(report)
+    })
+    .await
+}
+
+/// Compress one file of a folder and describe the result for the manifest
+async fn compress_entry(
+    input: &Path,
+    output: &Path,
+    file: &Path,
+    options: &AsyncCompressOptions,
+    level: i32,
+) -> Result<(ManifestEntry, Stats)> {
+    let start = Instant::now();
+    let compressor = options.compressor.clone();
+    let rel = file.strip_prefix(input).unwrap();
+    let out_rel = compressor::output_name(rel, &*compressor);
+    let out_file = output.join(&out_rel);
+    let mut source = tokio::fs::File::open(file).await.at(file)?;
+    let meta = source.metadata().await.at(file)?;
+    if let Some(parent) = out_file.parent() {
+        tokio::fs::create_dir_all(parent).await.at(parent)?;
+    }
+    let part = PartFile::new(compressor::part_path(&out_file));
+    let mut target = tokio::fs::File::create(part.path()).await.at(part.path())?;
+
+    let progress = options.progress.clone();
+    progress.file_started(file, meta.len());
+    // the job reports progress, notices cancellation and takes both hashes off the runtime
+    let job = {
+        let (compressor, progress, cancel, path) = (compressor.clone(), progress.clone(), options.cancel.clone(), file.to_path_buf());
+        move |input: &mut dyn Read, output: &mut dyn Write| {
+            let mut input = HashingReader {
+                inner: ProgressReader::new(Cancellable(input, &cancel), &path, &*progress),
+                hasher: Sha256::new(),
+            };
+            let mut output = HashingWriter { inner: output, hasher: Sha256::new() };
+            compressor.compress(&mut input, &mut output, level)?;
+            Ok((hex::encode(input.hasher.finalize()), hex::encode(output.hasher.finalize())))
+        }
+    };
+    let ((source_hash, hash), read, written) = match pipe(&mut source, &mut target, options.pool.as_deref(), job).await {
+        Ok(done) => done,
+        Err(err) => {
+            let err = cancel::map_cancelled(err, &options.cancel);
+            progress.file_failed(file, &err);
+            return Err(err);
+        }
+    };
+    progress.file_finished(file, meta.len());
+
+    let synced = Instant::now();
+    target.sync_all().await.at(part.path())?;
+    drop(target);
+    let mut sync_secs = synced.elapsed().as_secs_f64();
+    sync_secs += blocking(move || part.commit(&out_file).at(&out_file)).await?;
+
+    let entry = compressor::manifest_entry(rel, &out_rel, read, &meta, source_hash, hash, &*compressor);
+    let stats = Stats {
+        original_size: read,
+        compressed_size: written,
+        duration_secs: start.elapsed().as_secs_f64(),
+        sync_secs,
+        skipped_files: 0,
+    };
+    Ok((entry, stats))
+}
+
+/// Run `job` on `pool`, or the global rayon pool, over `input` and `output`; returns its
+/// result and the bytes read from `input` and written to `output`
+///
+/// Bytes cross between this task and the job through bounded channels, so only copying
+/// happens on the runtime. A panic in the job resumes here.
+async fn pipe<R, W, T, F>(input: &mut R, output: &mut W, pool: Option<&ThreadPool>, job: F) -> Result<(T, u64, u64)>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+    T: Send + 'static,
+    F: FnOnce(&mut dyn Read, &mut dyn Write) -> Result<T> + Send + 'static,
+{
+    let (input_tx, input_rx) = mpsc::channel(CHANNEL_DEPTH);
+    let (output_tx, mut output_rx) = mpsc::channel::<Vec<u8>>(CHANNEL_DEPTH);
+    let (done_tx, done_rx) = oneshot::channel();
+    let run = move || {
+        let mut reader = ChannelReader { rx: input_rx, chunk: Vec::new(), pos: 0 };
+        let mut writer = BufWriter::with_capacity(CHUNK_SIZE, ChannelWriter(output_tx));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| -> Result<T> {
+            let value = job(&mut reader, &mut writer)?;
+            writer.flush()?;
+            Ok(value)
+        }));
+        let _ = done_tx.send(result);
+    };
+    match pool {
+        Some(pool) => pool.spawn(run),
+        None => rayon::spawn(run),
+    }
+
+    let feed = async move {
+        let mut read = 0;
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        loop {
+            // a failed read reaches the job as an error, never as a clean end of input
+            let chunk = match input.read(&mut buf).await {
+                Ok(0) => return read,
+                Ok(n) => Ok(buf[..n].to_vec()),
+                Err(err) => Err(err),
+            };
+            let failed = chunk.is_err();
+            read += chunk.as_ref().map_or(0, |c| c.len() as u64);
+            // a closed channel means the job is done with its input
+            if input_tx.send(chunk).await.is_err() || failed {
+                return read;
+            }
+        }
+    };
+    let drain = async move {
+        let mut written = 0;
+        while let Some(chunk) = output_rx.recv().await {
+            output.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+        }
+        output.flush().await?;
+        Ok::<_, io::Error>(written)
+    };
+    let (read, written) = tokio::join!(feed, drain);
+    let result = match done_rx.await.expect("the rayon job always reports back") {
+        Ok(result) => result,
+        Err(payload) => panic::resume_unwind(payload),
+    };
+    // the job only sees a broken pipe when the output fails, so that error goes first
+    let written = written?;
+    Ok((result?, read, written))
+}
+
+/// Job side of the input channel
+struct ChannelReader {
+    rx: mpsc::Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => (self.chunk, self.pos) = (chunk?, 0),
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Job side of the output channel
+struct ChannelWriter(mpsc::Sender<Vec<u8>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.blocking_send(buf.to_vec()).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hashes what the job reads
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Hashes what the job writes
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Run blocking work on the runtime's blocking pool
+async fn blocking<T: Send + 'static>(job: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+    tokio::task::spawn_blocking(job).await.map_err(join_error)?
+}
+
+fn join_error(err: JoinError) -> CompressorError {
+    match err.try_into_panic() {
+        Ok(payload) => panic::resume_unwind(payload),
+        Err(err) => CompressorError::Io { path: None, source: io::Error::other(err) },
+    }
+}
+
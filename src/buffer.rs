@@ -0,0 +1,67 @@
+use std::cell::Cell;
+
+use crate::compressor::Compressor;
+use crate::error::{CompressorError, Result};
+use crate::limits::{DecompressLimits, LimitedWriter};
+use crate::registry::{self, Format};
+
+/// Compress `data` in memory, for payloads that never touch the disk
+///
+/// Repeated calls on one thread reuse that thread's zstd context, so small payloads do not
+/// pay for setting one up each time.
+pub fn compress_bytes(data: &[u8], compressor: &dyn Compressor, level: i32) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    compress_into(data, &mut output, compressor, level)?;
+    Ok(output)
+}
+
+/// Append the compressed form of `data` to `output`, so callers can reuse one buffer
+pub fn compress_into(data: &[u8], output: &mut Vec<u8>, compressor: &dyn Compressor, level: i32) -> Result<()> {
+    let start = output.len();
+    let result = compressor.compress_buffer(data, output, level);
+    if result.is_err() {
+        output.truncate(start);
+    }
+    result
+}
+
+/// Decompress `data` in memory, recognising its format by its magic bytes
+/// among the registered formats
+///
+/// Fails with `UnsupportedFormat` when no known format matches. Encrypted data carries no
+/// recognisable magic; use [`decompress_bytes_with`] and the matching compressor.
+pub fn decompress_bytes(data: &[u8], limits: &DecompressLimits) -> Result<Vec<u8>> {
+    let compressor = registry::global().for_magic(data).map(Format::compressor)
+        .ok_or_else(|| CompressorError::UnsupportedFormat("no known magic bytes at the start of the input".to_string()))?;
+    decompress_bytes_with(data, compressor, limits)
+}
+
+/// Decompress `data` in memory with `compressor`
+pub fn decompress_bytes_with(data: &[u8], compressor: &dyn Compressor, limits: &DecompressLimits) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    decompress_into(data, &mut output, compressor, limits)?;
+    Ok(output)
+}
+
+/// Append the decompressed form of `data` to `output`, stopping at `limits`
+///
+/// The whole of `data` counts as read from the start, so `max_ratio` bounds the overall
+/// expansion of the buffer. On error `output` is left as it was.
+pub fn decompress_into(data: &[u8], output: &mut Vec<u8>, compressor: &dyn Compressor, limits: &DecompressLimits) -> Result<()> {
+    let start = output.len();
+    let read = Cell::new(data.len() as u64);
+    let mut writer = LimitedWriter { inner: &mut *output, written: 0, limits: *limits, read: &read, tripped: None };
+    let result = match limits.max_window_log {
+        Some(max_window_log) => compressor.decompress_window_limited(&mut &data[..], &mut writer, max_window_log),
+        None => compressor.decompress_buffer(data, &mut writer),
+    };
+    // the decoder reports a tripped limit as a plain I/O error; surface the real cause
+    let result = match writer.tripped.take() {
+        Some(err) => Err(err.into()),
+        None => result.map_err(CompressorError::decode_buffer),
+    };
+    if result.is_err() {
+        output.truncate(start);
+    }
+    result
+}
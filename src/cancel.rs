@@ -0,0 +1,103 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+};
+
+use crate::durable;
+use crate::error::CompressorError;
+
+/// The operation was stopped through its `CancelToken`
+///
+/// Raised inside readers; library functions report it as `CompressorError::Cancelled`.
+#[derive(Debug, thiserror::Error)]
+#[error("operation cancelled")]
+pub struct Cancelled;
+
+/// Shared flag asking running operations to stop at the next buffer they read
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Clear the flag so the token can be used for the next operation
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() { Err(Cancelled) } else { Ok(()) }
+    }
+}
+
+/// Process-wide token the CLI cancels on Ctrl-C and hands to every operation it runs
+///
+/// Library operations never consult it on their own; pass it, or a token of your own, to
+/// [`CompressOptions::cancel`](crate::options::CompressOptions::cancel) and the like.
+pub fn global() -> &'static CancelToken {
+    static GLOBAL: OnceLock<CancelToken> = OnceLock::new();
+    GLOBAL.get_or_init(CancelToken::new)
+}
+
+/// Reader that fails with `Cancelled` once its token is cancelled
+pub(crate) struct Cancellable<'a, R: Read>(pub R, pub &'a CancelToken);
+
+impl<R: Read> Read for Cancellable<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.1.check().map_err(io::Error::other)?;
+        self.0.read(buf)
+    }
+}
+
+/// Turn an error caused by cancelling `token` into `Cancelled`, whatever layer wrapped it
+pub(crate) fn map_cancelled(err: CompressorError, token: &CancelToken) -> CompressorError {
+    if token.is_cancelled() { CompressorError::Cancelled } else { err }
+}
+
+/// In-flight temporary file, deleted on drop unless `commit` moved it into place
+pub(crate) struct PartFile {
+    path: PathBuf,
+    committed: bool,
+}
+
+impl PartFile {
+    pub fn new(path: PathBuf) -> Self {
+        PartFile { path, committed: false }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Rename the finished (and already synced) file to `dest`, then sync the directory
+    ///
+    /// Returns the seconds spent syncing.
+    pub fn commit(mut self, dest: &Path) -> io::Result<f64> {
+        fs::rename(&self.path, dest)?;
+        self.committed = true;
+        durable::sync_dir(dest.parent().unwrap_or(Path::new("")))
+    }
+}
+
+impl Drop for PartFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use clap::{Parser, Subcommand};
+use parallel_compressor::registry::{self, Format};
 
 #[derive(Parser)]
 #[command(name = "Parallel Compressor", version)]
@@ -10,21 +11,157 @@ pub struct CliArgs {
 #[derive(Subcommand)]
 pub enum SubCommand {
     Compress {
-        #[arg(short, long)]
-        input: String,
-        #[arg(short, long)]
-        output: String,
+        #[arg(short, long, required_unless_present = "in_place")]
+        input: Option<String>,
+        #[arg(short, long, required_unless_present = "in_place")]
+        output: Option<String>,
+        /// Compress a file, or every file of a folder, next to itself instead of into --output
+        #[arg(short = 'r', long, value_name = "PATH", conflicts_with_all = ["input", "output", "dedup"])]
+        in_place: Option<String>,
         #[arg(short, long, default_value_t = 4)]
         threads: usize,
-        #[arg(long, default_value_t = 3)]
-        level: i32, // Zstd supports 1..=22, default=3
-        #[arg(long, default_value = "zst")]
-        format: String, // zst, gz, lz4
+        /// Compression level; the range and default depend on --format
+        #[arg(long, allow_hyphen_values = true)]
+        level: Option<i32>,
+        /// Use the nearest level --format supports instead of refusing one outside its range
+        #[arg(long, requires = "level")]
+        clamp_level: bool,
+        #[arg(long, default_value = "zst", value_parser = parse_format, help = format_help())]
+        format: &'static Format,
+        /// Only recompress files that changed since the last run into this output
+        #[arg(long)]
+        incremental: bool,
+        /// With --incremental, delete outputs whose source no longer exists
+        #[arg(long, requires = "incremental")]
+        delete: bool,
+        /// Store input as deduplicated content-defined chunks in the output folder
+        #[arg(long, conflicts_with = "incremental")]
+        dedup: bool,
+        /// Encrypt the compressed output (passphrase from RUSTZIP_PASSPHRASE or a prompt)
+        #[arg(long, conflicts_with = "dedup")]
+        encrypt: bool,
+        /// Encrypt with a 32-byte raw or 64-char hex key file instead of a passphrase
+        #[arg(long, conflicts_with_all = ["dedup", "encrypt"])]
+        key_file: Option<String>,
+        /// Encrypt to an age X25519 recipient (age1...); may be repeated
+        #[arg(long, conflicts_with_all = ["dedup", "encrypt", "key_file"])]
+        recipient: Vec<String>,
+        /// Encrypt to every recipient listed in a file; may be repeated
+        #[arg(long, conflicts_with_all = ["dedup", "encrypt", "key_file"])]
+        recipients_file: Vec<String>,
+        /// Sign the folder manifest with this Ed25519 secret key (see `keygen`)
+        #[arg(long, conflicts_with = "dedup")]
+        sign_key: Option<String>,
+        /// Write Reed-Solomon recovery data (.par) able to rebuild this % of damaged blocks
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..=100), conflicts_with = "dedup")]
+        parity: Option<u32>,
+        /// Continue an interrupted folder job, skipping files it already finished; replaces
+        /// the outputs that job left, so it cannot be combined with --no-clobber or --skip-existing
+        #[arg(long, conflicts_with_all = ["incremental", "dedup", "no_clobber", "skip_existing"])]
+        resume: bool,
+        /// Overwrite outputs that already exist
+        #[arg(long, conflicts_with_all = ["no_clobber", "skip_existing"])]
+        force: bool,
+        /// Refuse to start if any output already exists (the default)
+        #[arg(long, conflicts_with = "skip_existing")]
+        no_clobber: bool,
+        /// Leave existing outputs alone and skip the inputs that would produce them
+        #[arg(long)]
+        skip_existing: bool,
+        /// Delete each input once its output is written (like gzip)
+        #[arg(long, conflicts_with_all = ["keep", "dedup", "incremental"])]
+        rm_source: bool,
+        /// Keep inputs after compressing (the default)
+        #[arg(long)]
+        keep: bool,
+        /// With --rm-source, decode each output and compare hashes before deleting its input
+        #[arg(long, requires = "rm_source", conflicts_with_all = ["recipient", "recipients_file"])]
+        verify: bool,
+        /// Only compress folder files whose relative path matches this glob; may be repeated
+        #[arg(long, value_name = "GLOB", conflicts_with = "dedup")]
+        include: Vec<String>,
+        /// Leave out folder files whose relative path matches this glob; may be repeated
+        #[arg(long, value_name = "GLOB", conflicts_with = "dedup")]
+        exclude: Vec<String>,
     },
     Decompress {
+        #[arg(short, long, required_unless_present = "in_place")]
+        input: Option<String>,
+        #[arg(short, long, required_unless_present = "in_place")]
+        output: Option<String>,
+        /// Restore a compressed file, or a folder compressed with -r, next to itself
+        #[arg(short = 'r', long, value_name = "PATH", conflicts_with_all = ["input", "output"])]
+        in_place: Option<String>,
+        /// Key file for inputs encrypted with --key-file
+        #[arg(long)]
+        key_file: Option<String>,
+        /// age identity file for inputs encrypted to recipients; may be repeated
+        #[arg(long)]
+        identity: Vec<String>,
+        /// Refuse to decompress unless the input is listed in a manifest signed by this public key
+        #[arg(long)]
+        trusted_key: Option<String>,
+        /// Recover what is readable from a truncated or corrupted file, restarting at the next
+        /// frame after damage; a file written as one frame keeps only what precedes it
+        #[arg(long, conflicts_with_all = ["max_output", "max_ratio", "max_window_log"])]
+        salvage: bool,
+        /// Stop once the output exceeds this many bytes
+        #[arg(long, value_name = "BYTES")]
+        max_output: Option<u64>,
+        /// Stop once the output grows past this many bytes per compressed byte read
+        #[arg(long, value_name = "RATIO")]
+        max_ratio: Option<f64>,
+        /// Refuse zstd frames needing a window larger than 2^N bytes
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(10..=31))]
+        max_window_log: Option<u32>,
+        /// Overwrite outputs that already exist
+        #[arg(long, conflicts_with_all = ["no_clobber", "skip_existing"])]
+        force: bool,
+        /// Refuse to start if any output already exists (the default)
+        #[arg(long, conflicts_with = "skip_existing")]
+        no_clobber: bool,
+        /// Leave existing outputs alone and skip the inputs that would produce them
+        #[arg(long)]
+        skip_existing: bool,
+        /// Delete each input once its output is written and checked
+        #[arg(long, conflicts_with_all = ["keep", "salvage"])]
+        rm_source: bool,
+        /// Keep inputs after decompressing (the default)
+        #[arg(long)]
+        keep: bool,
+        /// With --rm-source, decode each input again and compare hashes before deleting it
+        #[arg(long, requires = "rm_source")]
+        verify: bool,
+    },
+    /// Check every artifact of a compressed folder against its manifest
+    Verify {
+        #[arg(short, long)]
+        input: String,
+        /// Require a manifest signature from this Ed25519 public key
+        #[arg(long)]
+        trusted_key: Option<String>,
+    },
+    /// Rebuild damaged artifacts from their .par recovery files
+    Repair {
+        /// Artifact, or folder whose artifacts have .par files
         #[arg(short, long)]
         input: String,
+    },
+    /// Generate an Ed25519 key pair for signing manifests (writes <output> and <output>.pub)
+    Keygen {
         #[arg(short, long)]
         output: String,
     },
-}
\ No newline at end of file
+}
+
+fn parse_format(name: &str) -> Result<&'static Format, String> {
+    let registry = registry::global();
+    registry.get(name).ok_or_else(|| {
+        let names: Vec<&str> = registry.formats().map(Format::name).collect();
+        format!("unknown format, expected one of: {}", names.join(", "))
+    })
+}
+
+fn format_help() -> String {
+    format!("Compression format: {}", registry::global().help())
+}
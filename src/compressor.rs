@@ -1,22 +1,37 @@
 use std::{
+    cell::{Cell, RefCell},
+    collections::HashSet,
+    sync::Mutex,
     fs::File,
-    io::{Read, Write},
+    io::{BufRead, BufWriter, Read, Write},
     path::{Path, PathBuf},
     fs::metadata,
+    ops::RangeInclusive,
     time::Instant,
 };
 
-use anyhow::Context;
-use colored::*;
-use flate2::write::{GzDecoder, GzEncoder};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
 use flate2::Compression as GzCompression;
 use hex;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use lz4_flex::frame::{FrameDecoder as Lz4Decoder, FrameEncoder as Lz4Encoder};
-use rayon::ThreadPoolBuilder;
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
 use sha2::{Digest, Sha256};
 use walkdir::WalkDir;
-use zstd::stream::{Decoder, Encoder};
+use zstd::stream::Encoder;
+
+use crate::cancel::{self, CancelToken, Cancellable, PartFile};
+use crate::durable;
+use crate::error::{CompressorError, PathContext, Result};
+use crate::extract::{self, SafeDestination};
+use crate::journal::{self, Journal};
+use crate::limits::{self, CountingReader, DecompressLimits, LimitError, LimitedWriter};
+use crate::manifest::{self, Manifest, ManifestEntry};
+use crate::options::{CompressMode, CompressOptions, DecompressOptions, FileFilter, HashCheck};
+use crate::overwrite::{self, OutputPlan, OverwritePolicy};
+use crate::progress::{NoProgress, ProgressReader, ProgressSink};
+use crate::signing::SIGNATURE_FILE;
+use crate::source;
 
 /// Zstd 
 pub struct ZstdCompressor;
@@ -27,220 +42,781 @@ pub struct GzipCompressor;
 /// Lz4 
 pub struct Lz4Compressor;
 /// Trait for multi-format compression support (object-safe)
-pub trait Compressor {
-    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write, level: i32) -> anyhow::Result<()>;
-    fn decompress(&self, input: &mut dyn Read, output: &mut dyn Write) -> anyhow::Result<()>;
-    fn extension(&self) -> &'static str;
+///
+/// Folder jobs share one compressor between worker threads, hence `Send + Sync`.
+pub trait Compressor: Send + Sync {
+    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write, level: i32) -> Result<()>;
+    /// Decode `input` into `output`
+    ///
+    /// Malformed or truncated input must fail with an `InvalidData` or `UnexpectedEof` I/O
+    /// error so callers report it as `CorruptData`; errors of `input` are returned as raised.
+    fn decompress(&self, input: &mut dyn Read, output: &mut dyn Write) -> Result<()>;
+    /// `decompress`, refusing frames that need a window larger than `2^max_window_log` bytes
+    ///
+    /// Formats without a configurable window ignore the limit.
+    fn decompress_window_limited(&self, input: &mut dyn Read, output: &mut dyn Write, max_window_log: u32) -> Result<()> {
+        let _ = max_window_log;
+        self.decompress(input, output)
+    }
+    /// Compress all of `input`, appending the result to `output`
+    ///
+    /// The default streams through `compress`. Formats whose setup cost matters for small
+    /// payloads override it to reuse a per-thread context.
+    fn compress_buffer(&self, input: &[u8], output: &mut Vec<u8>, level: i32) -> Result<()> {
+        self.compress(&mut &input[..], output, level)
+    }
+    /// `decompress` of an in-memory input, overridden like `compress_buffer`
+    fn decompress_buffer(&self, input: &[u8], output: &mut dyn Write) -> Result<()> {
+        self.decompress(&mut &input[..], output)
+    }
+    /// Push-style encoder writing the compressed stream to `output`
+    ///
+    /// Formats that can stream override it. The default fails with `UnsupportedFormat`
+    /// rather than hold the whole input in memory until `finish`.
+    fn encoder<'a>(&'a self, output: Box<dyn StreamEncoder + 'a>, level: i32) -> Result<Box<dyn StreamEncoder + 'a>> {
+        let _ = (output, level);
+        Err(CompressorError::UnsupportedFormat(format!("{} has no streaming encoder", self.extension())))
+    }
+    /// Pull-style decoder over `input` that consumes only the bytes it has decoded
+    fn decoder<'a>(&'a self, input: Box<dyn BufRead + 'a>) -> Result<Box<dyn Read + 'a>>;
+    fn extension(&self) -> &str;
+    /// Bytes every frame (or member) of this format starts with, used to resync after damage
+    fn magic(&self) -> &'static [u8] { &[] }
+    /// Encryption applied on top of the compressed stream, recorded in the manifest
+    fn encryption(&self) -> Option<&'static str> { None }
+    /// Levels `compress` distinguishes; `None` for formats with a single setting
+    fn level_range(&self) -> Option<RangeInclusive<i32>> { None }
+    /// Level used when the caller does not pick one
+    fn default_level(&self) -> i32 { 0 }
+    /// Whether the format can prime its compressor with a shared dictionary
+    fn supports_dictionaries(&self) -> bool { false }
+    /// Whether one stream is compressed on several threads
+    fn supports_multithreading(&self) -> bool { false }
+    /// Whether a reader can start decoding part-way through a stream
+    fn supports_seeking(&self) -> bool { false }
+    /// Whether the stream carries a checksum of its content, so damage is detected on decode
+    fn has_checksum(&self) -> bool { false }
+    /// `level` moved into `level_range`; formats without levels always get `default_level`
+    fn clamp_level(&self, level: i32) -> i32 {
+        match self.level_range() {
+            Some(range) => level.clamp(*range.start(), *range.end()),
+            None => self.default_level(),
+        }
+    }
+}
+
+/// Writer returned by [`Compressor::encoder`]
+///
+/// `finish` ends the stream, then finishes the writer it was built on, so encoders stack.
+/// Encoders are `Send`, so a writer built on them can move to another thread.
+pub trait StreamEncoder: Write + Send {
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+impl StreamEncoder for Encoder<'_, Box<dyn StreamEncoder + '_>> {
+    fn finish(self: Box<Self>) -> Result<()> {
+        (*self).finish()?.finish()
+    }
+}
+
+impl StreamEncoder for GzEncoder<Box<dyn StreamEncoder + '_>> {
+    fn finish(self: Box<Self>) -> Result<()> {
+        (*self).finish()?.finish()
+    }
+}
+
+impl StreamEncoder for Lz4Encoder<Box<dyn StreamEncoder + '_>> {
+    fn finish(self: Box<Self>) -> Result<()> {
+        (*self).finish().map_err(std::io::Error::from)?.finish()
+    }
+}
+
+/// Error raised by a decoder's input, carried through the decoder unchanged
+#[derive(Debug)]
+struct InputError(std::io::Error);
+
+impl std::fmt::Display for InputError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for InputError {}
+
+/// Input of a decoder, tagging its errors so [`FormatErrors`] can tell them apart
+struct InputErrors<R>(R);
+
+impl<R: Read> Read for InputErrors<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.read(buf).map_err(tag_input_error)
+    }
+}
+
+impl<R: BufRead> BufRead for InputErrors<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.0.fill_buf().map_err(tag_input_error)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.0.consume(amt)
+    }
+}
+
+fn tag_input_error(err: std::io::Error) -> std::io::Error {
+    std::io::Error::new(err.kind(), InputError(err))
+}
+
+/// Decoder whose own failures are reported as `InvalidData`
+///
+/// zstd and lz4 report malformed input with `ErrorKind::Other` and flate2 with `InvalidInput`,
+/// kinds a failing reader or a cancelled job use too. Errors of the decoder's input, read
+/// through [`InputErrors`], come back as they were raised; anything else of those kinds is
+/// a format error.
+struct FormatErrors<R>(R);
+
+impl<R: Read> Read for FormatErrors<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.read(buf).map_err(|err| {
+            if err.get_ref().is_some_and(|inner| inner.is::<InputError>()) {
+                let inner = err.into_inner().expect("checked above");
+                return inner.downcast::<InputError>().expect("checked above").0;
+            }
+            match err.kind() {
+                std::io::ErrorKind::Other | std::io::ErrorKind::InvalidInput => std::io::Error::new(std::io::ErrorKind::InvalidData, err),
+                _ => err,
+            }
+        })
+    }
+}
+
+thread_local! {
+    /// Bulk compressor and the level it is set to, kept for the next buffer on this thread
+    static ZSTD_CCTX: RefCell<Option<(i32, zstd::bulk::Compressor<'static>)>> = const { RefCell::new(None) };
+    static ZSTD_DCTX: RefCell<Option<zstd::zstd_safe::DCtx<'static>>> = const { RefCell::new(None) };
 }
 
 impl Compressor for ZstdCompressor {
-    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write, level: i32) -> anyhow::Result<()> {
+    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write, level: i32) -> Result<()> {
         let mut encoder = zstd::stream::Encoder::new(output, level)?;
+        // a content checksum lets damaged frames be detected instead of decoding to garbage
+        encoder.include_checksum(true)?;
         std::io::copy(input, &mut encoder)?;
         encoder.finish()?;
         Ok(())
     }
-    fn decompress(&self, input: &mut dyn Read, output: &mut dyn Write) -> anyhow::Result<()> {
-        let mut decoder = zstd::stream::Decoder::new(input)?;
+    fn decompress(&self, input: &mut dyn Read, output: &mut dyn Write) -> Result<()> {
+        let mut decoder = FormatErrors(zstd::stream::Decoder::new(InputErrors(input))?);
         std::io::copy(&mut decoder, output)?;
         Ok(())
     }
+    fn decompress_window_limited(&self, input: &mut dyn Read, output: &mut dyn Write, max_window_log: u32) -> Result<()> {
+        let mut decoder = zstd::stream::Decoder::new(InputErrors(input))?;
+        decoder.window_log_max(max_window_log)?;
+        match std::io::copy(&mut FormatErrors(decoder), output) {
+            Err(e) if limits::is_window_error(&e) => Err(LimitError::WindowTooLarge { max_window_log }.into()),
+            Err(e) => Err(e.into()),
+            Ok(_) => Ok(()),
+        }
+    }
+    fn compress_buffer(&self, input: &[u8], output: &mut Vec<u8>, level: i32) -> Result<()> {
+        ZSTD_CCTX.with_borrow_mut(|cached| {
+            let compressor = match cached {
+                Some((cached_level, compressor)) if *cached_level == level => compressor,
+                _ => {
+                    let mut compressor = zstd::bulk::Compressor::new(level)?;
+                    compressor.set_parameter(zstd::zstd_safe::CParameter::ChecksumFlag(true))?;
+                    &mut cached.insert((level, compressor)).1
+                }
+            };
+            output.extend_from_slice(&compressor.compress(input)?);
+            Ok(())
+        })
+    }
+    fn decompress_buffer(&self, input: &[u8], output: &mut dyn Write) -> Result<()> {
+        ZSTD_DCTX.with_borrow_mut(|cached| {
+            let context = cached.get_or_insert_with(zstd::zstd_safe::DCtx::create);
+            // a previous buffer may have failed half-way through a frame
+            context
+                .reset(zstd::zstd_safe::ResetDirective::SessionOnly)
+                .map_err(|code| std::io::Error::other(zstd::zstd_safe::get_error_name(code)))?;
+            let mut decoder = FormatErrors(zstd::stream::read::Decoder::with_context(input, context));
+            std::io::copy(&mut decoder, output)?;
+            Ok(())
+        })
+    }
+    fn encoder<'a>(&'a self, output: Box<dyn StreamEncoder + 'a>, level: i32) -> Result<Box<dyn StreamEncoder + 'a>> {
+        let mut encoder = Encoder::new(output, level)?;
+        encoder.include_checksum(true)?;
+        Ok(Box::new(encoder))
+    }
+    fn decoder<'a>(&'a self, input: Box<dyn BufRead + 'a>) -> Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(FormatErrors(zstd::stream::read::Decoder::with_buffer(InputErrors(input))?)))
+    }
     fn extension(&self) -> &'static str { "zst" }
+    fn magic(&self) -> &'static [u8] { &[0x28, 0xB5, 0x2F, 0xFD] }
+    fn level_range(&self) -> Option<RangeInclusive<i32>> { Some(zstd::compression_level_range()) }
+    fn default_level(&self) -> i32 { zstd::DEFAULT_COMPRESSION_LEVEL }
+    fn supports_dictionaries(&self) -> bool { true }
+    fn has_checksum(&self) -> bool { true }
 }
 
 impl Compressor for GzipCompressor {
-    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write, level: i32) -> anyhow::Result<()> {
-        let mut encoder = GzEncoder::new(output, GzCompression::new(level as u32));
+    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write, level: i32) -> Result<()> {
+        let mut encoder = GzEncoder::new(output, GzCompression::new(self.clamp_level(level) as u32));
         std::io::copy(input, &mut encoder)?;
         encoder.finish()?;
         Ok(())
     }
-    fn decompress(&self, input: &mut dyn Read, output: &mut dyn Write) -> anyhow::Result<()> {
-        let mut decoder = GzDecoder::new(output);
-        std::io::copy(input, &mut decoder)?;
+    fn decompress(&self, input: &mut dyn Read, output: &mut dyn Write) -> Result<()> {
+        let mut decoder = FormatErrors(GzDecoder::new(InputErrors(input)));
+        std::io::copy(&mut decoder, output)?;
         Ok(())
     }
+    fn encoder<'a>(&'a self, output: Box<dyn StreamEncoder + 'a>, level: i32) -> Result<Box<dyn StreamEncoder + 'a>> {
+        Ok(Box::new(GzEncoder::new(output, GzCompression::new(self.clamp_level(level) as u32))))
+    }
+    fn decoder<'a>(&'a self, input: Box<dyn BufRead + 'a>) -> Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(FormatErrors(flate2::bufread::MultiGzDecoder::new(InputErrors(input)))))
+    }
     fn extension(&self) -> &'static str { "gz" }
+    fn magic(&self) -> &'static [u8] { &[0x1F, 0x8B, 0x08] }
+    fn level_range(&self) -> Option<RangeInclusive<i32>> { Some(0..=9) }
+    fn default_level(&self) -> i32 { 6 }
+    // every member ends with a CRC-32 of its content
+    fn has_checksum(&self) -> bool { true }
 }
 
 impl Compressor for Lz4Compressor {
-    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write, _level: i32) -> anyhow::Result<()> {
+    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write, _level: i32) -> Result<()> {
         let mut encoder = Lz4Encoder::new(output);
         std::io::copy(input, &mut encoder)?;
-        encoder.finish()?;
+        encoder.finish().map_err(std::io::Error::from)?;
         Ok(())
     }
-    fn decompress(&self, input: &mut dyn Read, output: &mut dyn Write) -> anyhow::Result<()> {
-        let mut decoder = Lz4Decoder::new(input);
+    fn decompress(&self, input: &mut dyn Read, output: &mut dyn Write) -> Result<()> {
+        let mut decoder = FormatErrors(Lz4Decoder::new(InputErrors(input)));
         std::io::copy(&mut decoder, output)?;
         Ok(())
     }
+    fn encoder<'a>(&'a self, output: Box<dyn StreamEncoder + 'a>, _level: i32) -> Result<Box<dyn StreamEncoder + 'a>> {
+        Ok(Box::new(Lz4Encoder::new(output)))
+    }
+    fn decoder<'a>(&'a self, input: Box<dyn BufRead + 'a>) -> Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(FormatErrors(Lz4Decoder::new(InputErrors(input)))))
+    }
     fn extension(&self) -> &'static str { "lz4" }
+    fn magic(&self) -> &'static [u8] { &[0x04, 0x22, 0x4D, 0x18] }
 }
 
-/// Compress a file or directory
-pub fn compress_path_with(input_path: &str, output_path: &str, threads: usize, level: i32, compressor: &dyn Compressor) -> anyhow::Result<()> {
-    ThreadPoolBuilder::new().num_threads(threads).build_global().ok();
-    let input = Path::new(input_path);
-    let output = Path::new(output_path);
-    let mp = MultiProgress::new();
+/// Compress a file or directory, refusing to overwrite existing outputs and reporting no progress
+pub fn compress_path_with(input_path: &str, output_path: &str, threads: usize, level: i32, compressor: &dyn Compressor) -> Result<Stats> {
+    compress_path_overwrite(input_path, output_path, threads, level, compressor, OverwritePolicy::default(), &NoProgress)
+}
+
+/// Compress a file or directory, handling existing outputs according to `overwrite`
+///
+/// Every output name is checked before any work starts, so a name collision or a refused
+/// overwrite fails the job without touching anything. Passing the input directory as
+/// `output_path` compresses every file next to itself, with the manifest at the top.
+pub fn compress_path_overwrite(
+    input_path: &str,
+    output_path: &str,
+    threads: usize,
+    level: i32,
+    compressor: &dyn Compressor,
+    overwrite: OverwritePolicy,
+    progress: &dyn ProgressSink,
+) -> Result<Stats> {
+    CompressOptions::new()
+        .format(compressor)
+        .level(level)
+        .threads(threads)
+        .overwrite(overwrite)
+        .progress(progress)
+        .run(input_path, output_path)
+        .map(|report| report.stats)
+}
+
+/// Compress a file or directory, skipping files an interrupted run into `output_path` finished
+///
+/// Folder jobs keep a journal of finished entries next to their output. A resumed job reuses
+/// an entry when its source size and mtime are unchanged and its artifact still has the
+/// recorded hash, discards leftover `.part` files, and ends with the same manifest an
+/// uninterrupted run would have written. Outputs of the interrupted job are its own, so
+/// they are overwritten as needed. Returns the number of files skipped.
+pub fn compress_path_resume(
+    input_path: &str,
+    output_path: &str,
+    threads: usize,
+    level: i32,
+    compressor: &dyn Compressor,
+    progress: &dyn ProgressSink,
+) -> Result<usize> {
+    CompressOptions::new()
+        .format(compressor)
+        .level(level)
+        .threads(threads)
+        .mode(CompressMode::Resume)
+        .progress(progress)
+        .run(input_path, output_path)
+        .map(|report| report.stats.skipped_files)
+}
+
+/// Full or resumed compress job behind [`CompressOptions::run`]
+pub(crate) fn compress_journaled(input: &Path, output: &Path, options: &CompressOptions, resume: bool) -> Result<CompressReport> {
+    let start = Instant::now();
+    let (compressor, progress, level) = (options.compressor, options.progress, options.effective_level());
+    // outputs of the interrupted job are its own
+    let overwrite = if resume { OverwritePolicy::Force } else { options.overwrite };
 
     if input.is_file() {
-        let out = if output.is_dir() {
-            output.join(
-                PathBuf::from(input.file_name().unwrap())
-                    .with_extension(compressor.extension())
-            )
-        } else {
-            output.to_path_buf()
-        };
-        compress_single_file_with(input, &out, &mp, level, compressor)?;
-    } else if input.is_dir() {
-        let mut files = Vec::new();
-        let mut total_bytes: u64 = 0;
-        for entry in WalkDir::new(input).into_iter().filter_map(Result::ok) {
-            if entry.file_type().is_file() {
-                total_bytes += entry.metadata().map(|m| m.len()).unwrap_or(0);
-                files.push(entry.path().to_path_buf());
-            }
+        let out = single_output_path(input, output, compressor);
+        let plan = overwrite::plan_outputs(vec![(input.to_path_buf(), out.clone())], overwrite)?;
+        if !plan.skipped.is_empty() {
+            progress.file_skipped(input, metadata(input).map(|m| m.len()).unwrap_or(0));
+            return Ok(CompressReport { stats: Stats { skipped_files: 1, ..Default::default() }, ..Default::default() });
+        }
+        let result = compress_file_checked(input, &out, options);
+        progress.job_finished(result.as_ref().err());
+        let (stats, _) = result?;
+        return Ok(CompressReport {
+            stats: stats.clone(),
+            files: vec![FileResult { source: input.to_path_buf(), output: out, stats }],
+            verified: usize::from(options.hash == HashCheck::Verify),
+            ..Default::default()
+        });
+    } else if !input.is_dir() {
+        return Err(CompressorError::InvalidInput(format!("{} is not a file or directory", input.display())));
+    }
+
+    let (plan, total_bytes) = plan_folder(input, output, compressor, &options.filters.compile()?, overwrite)?;
+    let finished = match resume {
+        true => {
+            journal::discard_part_files(output, compressor.extension())?;
+            journal::load(output, compressor.extension(), level)?
         }
-        let global_bar = mp.add(ProgressBar::new(total_bytes));
-        global_bar.set_style(
-            ProgressStyle::with_template(
-                "{msg:.bold} [{bar:40.green/black}] {bytes}/{total_bytes} {percent:>3}%"
-            ).unwrap()
-            .progress_chars("=> ")
-        );
-        global_bar.set_message("Total progress");
-        for file in files {
-            let rel = file.strip_prefix(input).unwrap();
-            let out_file = output.join(rel).with_extension(compressor.extension());
-            compress_single_file_with(&file, &out_file, &mp, level, compressor)?;
-            global_bar.inc(metadata(&file)?.len());
+        false => None,
+    };
+    let journal = match finished {
+        Some(_) => Journal::reopen(output)?,
+        None => Journal::create(output, compressor.extension(), level)?,
+    };
+    let finished = finished.unwrap_or_default();
+
+    progress.job_started(plan.jobs.len() + plan.skipped.len(), total_bytes);
+    let mut manifest = Manifest::default();
+    let mut report = CompressReport {
+        stats: Stats { skipped_files: plan.skipped.len(), ..Default::default() },
+        manifest: Some(output.join(manifest::MANIFEST_FILE)),
+        ..Default::default()
+    };
+    keep_skipped(input, output, &plan.skipped, &mut manifest, progress)?;
+    let mut pending = Vec::new();
+    for (file, _) in &plan.jobs {
+        let source = file.strip_prefix(input).unwrap().to_string_lossy().to_string();
+        if let Some(done) = finished.get(&source)
+            && still_finished(done, file, output, compressor)?
+        {
+            progress.file_skipped(file, done.size);
+            manifest.insert(done.clone());
+            report.stats.skipped_files += 1;
+            continue;
         }
-        global_bar.finish_with_message("All files done");
+        pending.push(file);
+    }
+    // files finish in any order; each is journaled as soon as it is done
+    let state = Mutex::new((journal, manifest, 0.0));
+    let compressed = pending
+        .par_iter()
+        .map(|file| {
+            let (entry, stats) = compress_manifest_entry(input, output, file, options)?;
+            let (journal, manifest, sync_secs) = &mut *state.lock().unwrap();
+            *sync_secs += stats.sync_secs + journal.record(&entry)?;
+            let result = FileResult { source: file.to_path_buf(), output: output.join(&entry.output), stats };
+            manifest.insert(entry);
+            Ok(result)
+        })
+        .collect::<Result<Vec<_>>>();
+    let (journal, manifest, sync_secs) = state.into_inner().unwrap();
+    match compressed {
+        Ok(files) => report.files = files,
+        Err(err) => return Err(stop_folder_job(err, progress, &options.cancel, &manifest, output)),
+    }
+    report.stats.sync_secs += sync_secs;
+    progress.job_finished(None);
+    report.stats.sync_secs += manifest.save(output)?;
+    journal.finish()?;
+    add_folder_totals(&mut report, &manifest, output, options.hash == HashCheck::Verify);
+    report.stats.duration_secs = start.elapsed().as_secs_f64();
+    Ok(report)
+}
+
+/// Fill in the sizes of everything `manifest` lists, and how many artifacts were verified
+pub(crate) fn add_folder_totals(report: &mut CompressReport, manifest: &Manifest, output: &Path, verified: bool) {
+    report.stats.original_size = manifest.entries.values().map(|e| e.size).sum();
+    report.stats.compressed_size = manifest.entries.values()
+        .filter_map(|e| metadata(output.join(&e.output)).ok())
+        .map(|m| m.len())
+        .sum();
+    if verified {
+        report.verified = report.files.len();
+    }
+}
+
+/// Worker pool for one job; dropped, and its threads stopped, when the job ends
+pub(crate) fn build_pool(threads: usize) -> Result<ThreadPool> {
+    ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(|err| CompressorError::Io { path: None, source: std::io::Error::other(err) })
+}
+
+/// Artifact name for `path`: the compressor's extension is appended (`a.txt` → `a.txt.zst`)
+///
+/// Appending keeps `a.txt` and `a.log` apart and lets decompression restore the full name.
+pub fn output_name(path: &Path, compressor: &dyn Compressor) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(compressor.extension());
+    PathBuf::from(name)
+}
+
+/// Whether a journaled entry can stand in for compressing `file` again
+fn still_finished(done: &ManifestEntry, file: &Path, output: &Path, compressor: &dyn Compressor) -> Result<bool> {
+    let meta = metadata(file).at(file)?;
+    let out_rel = output_name(Path::new(&done.source), compressor);
+    let artifact = output.join(&done.output);
+    Ok(done.size == meta.len()
+        && done.mtime_ns == manifest::mtime_ns(&meta)
+        && done.output == out_rel.to_string_lossy()
+        && artifact.is_file()
+        && sha256_file(&artifact)? == done.hash)
+}
+
+/// Where a single-file input is written: inside `output` if it is a directory, else `output` itself
+pub fn single_output_path(input: &Path, output: &Path, compressor: &dyn Compressor) -> PathBuf {
+    if output.is_dir() {
+        output.join(output_name(Path::new(input.file_name().unwrap()), compressor))
     } else {
-        anyhow::bail!("Input path is not a file or directory");
+        output.to_path_buf()
     }
-    Ok(())
 }
 
-/// Compress a single file
-pub fn compress_single_file_with(
-    input_path: &Path,
-    output_path: &Path,
-    mp: &MultiProgress,
+/// What an incremental run did to each source file
+#[derive(Debug, Default)]
+pub struct ChangeSummary {
+    pub added: Vec<String>,
+    pub modified: Vec<String>,
+    pub removed: Vec<String>,
+    pub unchanged: Vec<String>,
+    /// Whether the outputs of removed sources were deleted
+    pub pruned: bool,
+}
+
+/// Compress a directory, recompressing only files that changed since the last run
+///
+/// Sources are compared against the index left in `output_path` by a previous run:
+/// a file whose size and mtime match is skipped outright, otherwise its content hash
+/// decides. Outputs of sources that no longer exist are deleted only when `delete` is set.
+pub fn compress_path_incremental(
+    input_path: &str,
+    output_path: &str,
+    threads: usize,
     level: i32,
-    compressor: &dyn Compressor
-) -> anyhow::Result<Stats> {
+    compressor: &dyn Compressor,
+    delete: bool,
+    progress: &dyn ProgressSink,
+) -> Result<ChangeSummary> {
+    CompressOptions::new()
+        .format(compressor)
+        .level(level)
+        .threads(threads)
+        .mode(CompressMode::Incremental { delete })
+        .progress(progress)
+        .run(input_path, output_path)
+        .map(|report| report.changes.unwrap_or_default())
+}
+
+/// Incremental compress job behind [`CompressOptions::run`]
+pub(crate) fn compress_incremental(input: &Path, output: &Path, options: &CompressOptions, delete: bool) -> Result<CompressReport> {
     let start = Instant::now();
-    let file_size = metadata(input_path)?.len();
-    if let Some(parent) = output_path.parent() {
-        std::fs::create_dir_all(parent)?;
+    let (compressor, progress) = (options.compressor, options.progress);
+    if !input.is_dir() {
+        return Err(CompressorError::InvalidInput("Incremental mode requires a directory input".to_string()));
     }
-    let mut tmp_path = output_path.to_path_buf();
-    tmp_path.set_extension(
-        match output_path.extension() {
-            Some(ext) => format!("{}.part", ext.to_string_lossy()),
-            None => "part".to_string(),
+    let previous = Manifest::load(output)?;
+    let mut manifest = Manifest::default();
+    let mut summary = ChangeSummary { pruned: delete, ..Default::default() };
+    let mut report = CompressReport { manifest: Some(output.join(manifest::MANIFEST_FILE)), ..Default::default() };
+
+    let (files, _) = collect_sources(input, output, compressor, &options.filters.compile()?);
+    // incremental runs rewrite their own artifacts by design; only refuse impossible layouts
+    let jobs = files.iter().map(|file| (file.clone(), output.join(output_name(file.strip_prefix(input).unwrap(), compressor))));
+    overwrite::plan_outputs(jobs.collect(), OverwritePolicy::Force)?;
+    let mut pending = Vec::new();
+    for file in files {
+        let rel = file.strip_prefix(input).unwrap();
+        let source = rel.to_string_lossy().to_string();
+        let out_rel = output_name(rel, compressor).to_string_lossy().to_string();
+        let Some(prev) = previous.entries.get(&source) else {
+            summary.added.push(source);
+            pending.push(file);
+            continue;
+        };
+        if prev.output != out_rel || !output.join(&prev.output).is_file() {
+            summary.modified.push(source);
+            pending.push(file);
+            continue;
         }
-    );
-    let mut input_file = File::open(input_path)
-        .with_context(|| format!("Failed to open {}", input_path.display()))?;
-    let mut output_file = File::create(&tmp_path)
-        .with_context(|| format!("Failed to create {}", tmp_path.display()))?;
-    // Progress bar
-    let bar = mp.add(ProgressBar::new(file_size));
-    bar.set_prefix(format!("{}", input_path.file_name().unwrap_or_default().to_string_lossy()));
-    bar.set_style(
-        ProgressStyle::with_template(
-            "{prefix:.dim}  [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} {percent:>3}%"
-        ).unwrap()
-        .progress_chars("=> ")
-    );
-    // Wrap input_file in a progress reader
-    struct ProgressReader<'a, R: Read> {
-        inner: R,
-        bar: &'a ProgressBar,
-        total: u64,
-    }
-    impl<'a, R: Read> Read for ProgressReader<'a, R> {
-        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-            let n = self.inner.read(buf)?;
-            if n > 0 {
-                self.total += n as u64;
-                self.bar.set_position(self.total);
+        let meta = metadata(&file).at(&file)?;
+        let (size, mtime) = (meta.len(), manifest::mtime_ns(&meta));
+        if prev.size == size && prev.mtime_ns == mtime {
+            manifest.insert(prev.clone());
+            summary.unchanged.push(source);
+        } else if prev.size == size && sha256_file(&file)? == prev.source_hash {
+            // touched but not edited: keep the artifact, refresh the fingerprint
+            manifest.insert(ManifestEntry { mtime_ns: mtime, ..prev.clone() });
+            summary.unchanged.push(source);
+        } else {
+            summary.modified.push(source);
+            pending.push(file);
+        }
+    }
+
+    let total_bytes = pending.iter().filter_map(|f| metadata(f).ok()).map(|m| m.len()).sum();
+    progress.job_started(pending.len(), total_bytes);
+    let state = Mutex::new((manifest, 0.0));
+    let compressed = pending
+        .par_iter()
+        .map(|file| {
+            let (entry, stats) = compress_manifest_entry(input, output, file, options)?;
+            let (manifest, sync_secs) = &mut *state.lock().unwrap();
+            *sync_secs += stats.sync_secs;
+            let result = FileResult { source: file.clone(), output: output.join(&entry.output), stats };
+            manifest.insert(entry);
+            Ok(result)
+        })
+        .collect::<Result<Vec<_>>>();
+    let (mut manifest, sync_secs) = state.into_inner().unwrap();
+    match compressed {
+        Ok(files) => report.files = files,
+        Err(err) => {
+            // artifacts not redone yet still match their old entries; the next run redoes them
+            for (source, prev) in &previous.entries {
+                if !manifest.entries.contains_key(source) {
+                    manifest.insert(prev.clone());
+                }
             }
-            Ok(n)
+            return Err(stop_folder_job(err, options.progress, &options.cancel, &manifest, output));
         }
     }
-    let mut progress_reader = ProgressReader { inner: &mut input_file, bar: &bar, total: 0 };
-    compressor.compress(&mut progress_reader, &mut output_file, level)?;
-    bar.finish_with_message("done");
-    std::fs::rename(&tmp_path, output_path)?;
-    let duration = start.elapsed();
-    let compressed_size = metadata(output_path)?.len();
-    Ok(Stats {
-        original_size: file_size,
-        compressed_size,
-        duration_secs: duration.as_secs_f64(),
-    })
-}
-
-/// Decompress a file
-pub fn decompress_file_with(input_path: &str, output_path: &str, compressor: &dyn Compressor) -> anyhow::Result<()> {
-    let mut input_file = File::open(input_path)?;
-    let mut output_file = File::create(output_path)?;
-    compressor.decompress(&mut input_file, &mut output_file)?;
+    report.stats.sync_secs += sync_secs;
+    progress.job_finished(None);
+
+    for (source, prev) in &previous.entries {
+        if let Some(current) = manifest.entries.get(source) {
+            // recompressed under a different output name: drop the stale artifact
+            let stale = output.join(&prev.output);
+            if current.output != prev.output
+                && stale.is_file()
+                && manifest.entries.values().all(|e| e.output != prev.output)
+            {
+                std::fs::remove_file(&stale).at(&stale)?;
+            }
+            continue;
+        }
+        summary.removed.push(source.clone());
+        if delete {
+            let stale = output.join(&prev.output);
+            if stale.is_file() {
+                std::fs::remove_file(&stale)
+                    .at(&stale)?;
+            }
+        } else {
+            manifest.insert(prev.clone());
+        }
+    }
+
+    report.stats.sync_secs += manifest.save(output)?;
+    add_folder_totals(&mut report, &manifest, output, options.hash == HashCheck::Verify);
+    report.stats.duration_secs = start.elapsed().as_secs_f64();
+    report.changes = Some(summary);
+    Ok(report)
+}
+
+/// Tear down a folder job that failed part-way, returning the error to report
+///
+/// The progress sink is told the job ended. On cancellation the manifest of the files
+/// completed so far is saved; it only lists finished artifacts, so callers can load it to
+/// report what was done.
+pub(crate) fn stop_folder_job(
+    err: CompressorError,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+    manifest: &Manifest,
+    output: &Path,
+) -> CompressorError {
+    let err = cancel::map_cancelled(err, cancel);
+    let err = match err {
+        // the manifest is what a later run needs, so its failure is the one to report
+        CompressorError::Cancelled => manifest.save(output).err().unwrap_or(err),
+        err => err,
+    };
+    progress.job_finished(Some(&err));
+    err
+}
+
+/// Sources of the folder `input` paired with their artifacts in `output` and checked against
+/// `overwrite`, with the combined size of every source
+pub(crate) fn plan_folder(
+    input: &Path,
+    output: &Path,
+    compressor: &dyn Compressor,
+    filter: &FileFilter,
+    overwrite: OverwritePolicy,
+) -> Result<(OutputPlan, u64)> {
+    let (files, total_bytes) = collect_sources(input, output, compressor, filter);
+    let jobs = files
+        .into_iter()
+        .map(|file| {
+            let out = output.join(output_name(file.strip_prefix(input).unwrap(), compressor));
+            (file, out)
+        })
+        .collect();
+    Ok((overwrite::plan_outputs(jobs, overwrite)?, total_bytes))
+}
+
+/// Report the sources the overwrite policy left alone, carrying their manifest entries over
+///
+/// An existing output we were told to leave alone keeps the entry it had, if any.
+pub(crate) fn keep_skipped(
+    input: &Path,
+    output: &Path,
+    skipped: &[(PathBuf, PathBuf)],
+    manifest: &mut Manifest,
+    progress: &dyn ProgressSink,
+) -> Result<()> {
+    let previous = if skipped.is_empty() { Manifest::default() } else { Manifest::load(output)? };
+    for (file, _) in skipped {
+        let source = file.strip_prefix(input).unwrap().to_string_lossy().to_string();
+        if let Some(entry) = previous.entries.get(&source) {
+            manifest.insert(entry.clone());
+        }
+        progress.file_skipped(file, metadata(file).map(|m| m.len()).unwrap_or(0));
+    }
     Ok(())
 }
 
-/// Choose an adaptive chunk size based on file size (256 KB to 4 MB)
-fn choose_chunk_size(file_size: u64) -> usize {
-    let min = 256 * 1024;
-    let max = 4 * 1024 * 1024;
-    let mut chunk = (file_size / 64) as usize;
-    chunk = chunk.clamp(min, max);
-    chunk
+/// Files of `input` to compress into `output`, with their combined size
+///
+/// When compressing in place (`output` is `input`), the walk leaves out what this tool writes
+/// there itself: artifacts, their `.part` and `.par` files, and the manifest, signature and
+/// journal. The list is complete before the first output is created, so a job never picks up
+/// its own outputs. Files `filter` rejects are left out as well.
+pub(crate) fn collect_sources(input: &Path, output: &Path, compressor: &dyn Compressor, filter: &FileFilter) -> (Vec<PathBuf>, u64) {
+    let (files, _) = collect_files(input);
+    let in_place = is_in_place(input, output);
+    let ours = [".part", ".par"].map(|suffix| format!(".{}{}", compressor.extension(), suffix));
+    let artifact = format!(".{}", compressor.extension());
+    let files: Vec<PathBuf> = files
+        .into_iter()
+        .filter(|file| {
+            if !filter.accepts(file.strip_prefix(input).unwrap()) {
+                return false;
+            }
+            if !in_place {
+                return true;
+            }
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            let top_level = file.parent() == Some(input);
+            // a crash while saving the manifest can leave its `.part` behind
+            let saved = name.strip_suffix(".part").unwrap_or(&name);
+            !(name.ends_with(&artifact)
+                || ours.iter().any(|suffix| name.ends_with(suffix.as_str()))
+                || top_level && [manifest::MANIFEST_FILE, manifest::INDEX_FILE, SIGNATURE_FILE, journal::JOURNAL_FILE].contains(&saved))
+        })
+        .collect();
+    let total_bytes = files.iter().filter_map(|f| metadata(f).ok()).map(|m| m.len()).sum();
+    (files, total_bytes)
 }
 
-pub struct Stats {
-    pub original_size: u64,
-    pub compressed_size: u64,
-    pub duration_secs: f64,
+/// Whether `output` names the same directory as `input`
+pub fn is_in_place(input: &Path, output: &Path) -> bool {
+    match (input.canonicalize(), output.canonicalize()) {
+        (Ok(input), Ok(output)) => input == output,
+        _ => false,
+    }
 }
 
-use std::io::{BufReader, BufWriter};
-pub fn sha256_file(path: &Path) -> anyhow::Result<String> {
-    let mut file = File::open(path)?;
-    let mut hasher = Sha256::new();
-    let mut buf = [0u8; 8192];
-    loop {
-        let n = file.read(&mut buf)?;
-        if n == 0 { break; }
-        hasher.update(&buf[..n]);
+/// Walk `input` and return every regular file below it with their combined size
+pub(crate) fn collect_files(input: &Path) -> (Vec<PathBuf>, u64) {
+    let mut files = Vec::new();
+    let mut total_bytes: u64 = 0;
+    for entry in WalkDir::new(input).sort_by_file_name().into_iter().filter_map(Result::ok) {
+        if entry.file_type().is_file() {
+            total_bytes += entry.metadata().map(|m| m.len()).unwrap_or(0);
+            files.push(entry.path().to_path_buf());
+        }
     }
-    Ok(hex::encode(hasher.finalize()))
+    (files, total_bytes)
 }
 
-pub fn compress_single_file(
+/// Compress one file of a folder job and describe the result for the manifest
+fn compress_manifest_entry(input: &Path, output: &Path, file: &Path, options: &CompressOptions) -> Result<(ManifestEntry, Stats)> {
+    let compressor = options.compressor;
+    let rel = file.strip_prefix(input).unwrap();
+    let out_rel = output_name(rel, compressor);
+    let out_file = output.join(&out_rel);
+    let meta = metadata(file).at(file)?;
+    let (stats, source_hash) = compress_file_checked(file, &out_file, options)?;
+    let entry = manifest_entry(rel, &out_rel, stats.original_size, &meta, source_hash, sha256_file(&out_file)?, compressor);
+    Ok((entry, stats))
+}
+
+/// Manifest entry for the source `rel`, compressed by `compressor` into `out_rel`
+pub(crate) fn manifest_entry(
+    rel: &Path,
+    out_rel: &Path,
+    size: u64,
+    meta: &std::fs::Metadata,
+    source_hash: String,
+    hash: String,
+    compressor: &dyn Compressor,
+) -> ManifestEntry {
+    ManifestEntry {
+        source: rel.to_string_lossy().to_string(),
+        output: out_rel.to_string_lossy().to_string(),
+        size,
+        mtime_ns: manifest::mtime_ns(meta),
+        source_hash,
+        hash,
+        encryption: compressor.encryption().map(str::to_string),
+    }
+}
+
+/// Compress a single file
+pub fn compress_single_file_with(
     input_path: &Path,
     output_path: &Path,
-    mp: &MultiProgress,
-    level: i32
-) -> anyhow::Result<Stats> {
-    // streamed I/O version
-    let start = Instant::now();
-    let input_file = File::open(input_path)
-        .with_context(|| format!("Failed to open {}", input_path.display()))?;
-    let file_size = metadata(input_path)?.len();
-    let chunk_size = choose_chunk_size(file_size);
-    let mut reader = BufReader::with_capacity(chunk_size, input_file);
+    progress: &dyn ProgressSink,
+    level: i32,
+    compressor: &dyn Compressor
+) -> Result<Stats> {
+    compress_file_hashed(input_path, output_path, progress, level, compressor, &CancelToken::new()).map(|(stats, _)| stats)
+}
 
-    if let Some(parent) = output_path.parent() {
-        std::fs::create_dir_all(parent)?;
+/// Compress one file as `options` say, decoding the artifact again under `HashCheck::Verify`
+fn compress_file_checked(input_path: &Path, output_path: &Path, options: &CompressOptions) -> Result<(Stats, String)> {
+    let (stats, source_hash) =
+        compress_file_hashed(input_path, output_path, options.progress, options.effective_level(), options.compressor, &options.cancel)?;
+    if options.hash == HashCheck::Verify {
+        source::check_decodes_to(output_path, options.compressor, source_hash.clone())?;
     }
-    // Write to a temporary .part file first
+    Ok((stats, source_hash))
+}
+
+/// Temporary name `output_path` is written under until it is complete
+pub(crate) fn part_path(output_path: &Path) -> PathBuf {
     let mut tmp_path = output_path.to_path_buf();
     tmp_path.set_extension(
         match output_path.extension() {
@@ -248,254 +824,403 @@ pub fn compress_single_file(
             None => "part".to_string(),
         }
     );
-    let output_file = File::create(&tmp_path)
-        .with_context(|| format!("Failed to create {}", tmp_path.display()))?;
-    let mut writer = BufWriter::with_capacity(chunk_size, output_file);
-    let mut encoder = Encoder::new(&mut writer, level)?;
-
-    // Progress bar: unknown chunk count, so use bytes
-    let original_size = file_size;
-    let bar = mp.add(ProgressBar::new(original_size));
-    bar.set_prefix(format!("{}", input_path.file_name().unwrap_or_default().to_string_lossy()));
-    bar.set_style(
-        ProgressStyle::with_template(
-            "{prefix:.dim}  [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} {percent:>3}%"
-        ).unwrap()
-        .progress_chars("=> ")
-    );
+    tmp_path
+}
 
-    let mut total_read = 0u64;
-    let mut buf = vec![0u8; chunk_size];
-    loop {
-        let n = reader.read(&mut buf)?;
-        if n == 0 { break; }
-        encoder.write_all(&buf[..n])?;
-        total_read += n as u64;
-        bar.set_position(total_read.min(original_size));
+/// Compress a single file, returning the SHA-256 of the source alongside the stats
+fn compress_file_hashed(
+    input_path: &Path,
+    output_path: &Path,
+    progress: &dyn ProgressSink,
+    level: i32,
+    compressor: &dyn Compressor,
+    cancel: &CancelToken,
+) -> Result<(Stats, String)> {
+    let start = Instant::now();
+    let file_size = metadata(input_path).at(input_path)?.len();
+    if let Some(parent) = output_path.parent() {
+        std::fs::create_dir_all(parent).at(parent)?;
+    }
+    let mut input_file = File::open(input_path)
+        .at(input_path)?;
+    let part = PartFile::new(part_path(output_path));
+    let mut output_file = File::create(part.path())
+        .at(part.path())?;
+    progress.file_started(input_path, file_size);
+    // Wrap input_file in a reader that reports progress and hashes what it reads
+    let mut reader = HashingReader {
+        inner: ProgressReader::new(Cancellable(&mut input_file, cancel), input_path, progress),
+        hasher: Some(Sha256::new()),
+    };
+    if let Err(err) = compressor.compress(&mut reader, &mut output_file, level) {
+        let err = cancel::map_cancelled(err, cancel);
+        progress.file_failed(input_path, &err);
+        return Err(err);
     }
-    encoder.finish()?;
-    writer.flush()?;
-    bar.finish_with_message("done");
+    let source_hash = reader.hex_digest().expect("hashing was asked for");
+    progress.file_finished(input_path, file_size);
+    let mut sync_secs = durable::sync_file(&output_file).at(part.path())?;
+    drop(output_file);
+    sync_secs += part.commit(output_path).at(output_path)?;
+    let duration = start.elapsed();
+    let compressed_size = metadata(output_path).at(output_path)?.len();
+    Ok((
+        Stats {
+            original_size: file_size,
+            compressed_size,
+            duration_secs: duration.as_secs_f64(),
+            sync_secs,
+            skipped_files: 0,
+        },
+        source_hash,
+    ))
+}
 
-    // Atomically rename .part file to final output
-    std::fs::rename(&tmp_path, output_path)?;
+/// Reader that hashes what passes through it, when given a hasher
+struct HashingReader<R> {
+    inner: R,
+    hasher: Option<Sha256>,
+}
 
-    let duration = start.elapsed();
-    let compressed_size = metadata(output_path)?.len();
-
-    // pretty per‑file stats
-    println!("\n{}", "📊 Compression complete!".bold().green());
-    println!(
-        "{} {:.2} MB",
-        "Original size:   ".blue(),
-        original_size as f64 / 1_048_576.0
-    );
-    println!(
-        "{} {:.2} MB",
-        "Compressed size: ".blue(),
-        compressed_size as f64 / 1_048_576.0
-    );
-    let ratio = compressed_size as f64 / original_size as f64;
-    println!("{} {:.2}%", "Compression ratio:".yellow(), ratio * 100.0);
-    println!("{} {:.2} s", "Time taken:".magenta(), duration.as_secs_f64());
-    let speed = (original_size as f64 / 1_048_576.0) / duration.as_secs_f64();
-    println!("{} {:.2} MB/s", "Throughput:".cyan(), speed);
+impl<R> HashingReader<R> {
+    fn hex_digest(self) -> Option<String> {
+        self.hasher.map(|hasher| hex::encode(hasher.finalize()))
+    }
+}
 
-    Ok(Stats {
-        original_size,
-        compressed_size,
-        duration_secs: duration.as_secs_f64(),
-    })
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..n]);
+        }
+        Ok(n)
+    }
 }
 
-pub fn compress_path(input_path: &str, output_path: &str, threads: usize, level: i32) -> anyhow::Result<()> {
-    // build the global pool once here
-    ThreadPoolBuilder::new().num_threads(threads).build_global().ok();
+/// Decompress a file without limits, reporting no progress
+pub fn decompress_file_with(input_path: &str, output_path: &str, compressor: &dyn Compressor) -> Result<DecompressReport> {
+    decompress_file_limited(input_path, output_path, compressor, &DecompressLimits::default(), &NoProgress)
+}
 
-    let input = Path::new(input_path);
-    let output = Path::new(output_path);
-    let mp = MultiProgress::new();
+/// Decompress one file, stopping with a `LimitError` as soon as the output crosses `limits`
+///
+/// The partial output is removed when a limit is hit.
+pub fn decompress_file_limited(
+    input_path: &str,
+    output_path: &str,
+    compressor: &dyn Compressor,
+    limits: &DecompressLimits,
+    progress: &dyn ProgressSink,
+) -> Result<DecompressReport> {
+    decompress_one(Path::new(input_path), Path::new(output_path), compressor, limits, progress, &CancelToken::new(), None)
+}
 
-    use std::collections::BTreeMap;
-    let mut manifest = BTreeMap::new();
-    if input.is_file() {
-        // single file
-        let out = if output.is_dir() {
-            // if output is a dir, mirror file name with .zst
-            output.join(
-                PathBuf::from(input.file_name().unwrap())
-                    .with_extension("zst")
-            )
-        } else {
-            output.to_path_buf()
-        };
-        let _ = compress_single_file(input, &out, &mp, level)?;
-        // SHA-256 manifest for single file
-        let hash = sha256_file(&out)?;
-        manifest.insert(out.file_name().unwrap().to_string_lossy().to_string(), hash);
-    } else if input.is_dir() {
-        // walk dir and collect files first to know count and total size
-        let mut files = Vec::new();
-        let mut total_bytes: u64 = 0;
-        for entry in WalkDir::new(input).into_iter().filter_map(Result::ok) {
-            if entry.file_type().is_file() {
-                total_bytes += entry.metadata().map(|m| m.len()).unwrap_or(0);
-                files.push(entry.path().to_path_buf());
+/// Single-file decompression behind [`decompress_file_limited`] and [`DecompressOptions::run`](crate::options::DecompressOptions::run)
+///
+/// With `signed_hash`, the compressed bytes are hashed as they are decoded, so the output
+/// comes from exactly the bytes checked; it is removed unless they match and decode.
+pub(crate) fn decompress_one(
+    input_path: &Path,
+    output_path: &Path,
+    compressor: &dyn Compressor,
+    limits: &DecompressLimits,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+    signed_hash: Option<&str>,
+) -> Result<DecompressReport> {
+    let start = Instant::now();
+    let read = Cell::new(0);
+    let file = File::open(input_path).at(input_path)?;
+    let compressed_size = file.metadata().at(input_path)?.len();
+    progress.file_started(input_path, compressed_size);
+    let mut input = CountingReader {
+        inner: HashingReader {
+            inner: ProgressReader::new(Cancellable(file, cancel), input_path, progress),
+            hasher: signed_hash.map(|_| Sha256::new()),
+        },
+        count: &read,
+    };
+    let mut output = LimitedWriter {
+        inner: BufWriter::new(File::create(output_path).at(output_path)?),
+        written: 0,
+        limits: *limits,
+        read: &read,
+        tripped: None,
+    };
+    let result = decode_limited(compressor, &mut input, &mut output, limits.max_window_log, cancel)
+        .map_err(|err| CompressorError::decode(input_path, Some(read.get()), err))
+        .and_then(|()| {
+            if let Some(expected) = signed_hash {
+                // the signed hash covers the whole file, trailing bytes the decoder left included
+                std::io::copy(&mut input, &mut std::io::sink()).at(input_path)?;
+                let actual = input.inner.hex_digest().expect("hashing was asked for");
+                if actual != expected {
+                    return Err(CompressorError::Integrity { path: input_path.to_path_buf(), expected: expected.to_string(), actual });
+                }
             }
-        }
+            output.flush().at(output_path)?;
+            durable::sync_file(output.inner.get_ref()).at(output_path)?;
+            Ok(DecompressReport {
+                files: 1,
+                compressed_size,
+                decompressed_size: output.written,
+                duration_secs: start.elapsed().as_secs_f64(),
+                ..Default::default()
+            })
+        });
+    drop(output);
+    match &result {
+        Ok(_) => progress.file_finished(input_path, compressed_size),
+        Err(err) => progress.file_failed(input_path, err),
+    }
+    // nothing decoded from bytes that were not vouched for is kept
+    let discard = matches!(result, Err(CompressorError::LimitExceeded(_) | CompressorError::Cancelled))
+        || (signed_hash.is_some() && result.is_err());
+    if discard {
+        let _ = std::fs::remove_file(output_path);
+    }
+    result
+}
 
-        // global progress bar by bytes
-        let global_bar = mp.add(ProgressBar::new(total_bytes));
-        global_bar.set_style(
-            ProgressStyle::with_template(
-                "{msg:.bold} [{bar:40.green/black}] {bytes}/{total_bytes} {percent:>3}%"
-            ).unwrap()
-            .progress_chars("=> ")
-        );
-        global_bar.set_message("Total progress");
-
-        let mut total_orig: u64 = 0;
-        let mut total_comp: u64 = 0;
-        let mut total_secs: f64 = 0.0;
-
-        for file in files {
-            // keep directory structure under output/
-            let rel = file.strip_prefix(input).unwrap();
-            let out_file = output.join(rel).with_extension("zst");
-
-            // compress and update global progress bar as we drain input
-            let stats = {
-                let s = compress_single_file(&file, &out_file, &mp, level)?;
-                global_bar.inc(s.original_size);
-                s
-            };
-            // Compute hash for manifest
-            let hash = sha256_file(&out_file)?;
-            manifest.insert(rel.with_extension("zst").to_string_lossy().to_string(), hash);
-            total_orig += stats.original_size;
-            total_comp += stats.compressed_size;
-            total_secs += stats.duration_secs;
-        }
+/// Decode `input` into `output`, enforcing the limits `output` and `max_window_log` carry
+///
+/// The decoder reports a tripped limit as a plain I/O error; this surfaces the real cause
+/// as `LimitExceeded`, and cancelling `cancel` as `Cancelled`. Other errors are returned as the
+/// decoder gave them, for the caller to map with [`CompressorError::decode`].
+pub(crate) fn decode_limited<W: Write>(
+    compressor: &dyn Compressor,
+    input: &mut dyn Read,
+    output: &mut LimitedWriter<'_, W>,
+    max_window_log: Option<u32>,
+    cancel: &CancelToken,
+) -> Result<()> {
+    let result = match max_window_log {
+        Some(max_window_log) => compressor.decompress_window_limited(input, output, max_window_log),
+        None => compressor.decompress(input, output),
+    };
+    match output.tripped.take() {
+        Some(err) => Err(err.into()),
+        None => result.map_err(|err| cancel::map_cancelled(err, cancel)),
+    }
+}
 
-        global_bar.finish_with_message("All files done");
+/// Restore every file of a compressed folder under `output_path`, returning the number of files
+///
+/// Entries come from the folder's manifest index. Source and artifact names both go through
+/// the safe-extraction layer, and every restored file is checked against its source SHA-256.
+/// Existing files are never replaced and no progress is reported; see [`decompress_path_overwrite`].
+pub fn decompress_path_with(input_path: &str, output_path: &str, compressor: &dyn Compressor) -> Result<usize> {
+    decompress_path_overwrite(input_path, output_path, compressor, OverwritePolicy::default(), &NoProgress)
+}
 
-        // Write manifest
-        use std::io::Write;
-        let manifest_path = output.join("manifest-sha256.txt");
-        let mut mf = File::create(&manifest_path)?;
-        for (file, hash) in &manifest {
-            writeln!(mf, "{}  {}", hash, file)?;
-        }
-        println!("SHA-256 manifest written to {}", manifest_path.display());
-        // overall summary
-        println!("\n{}", "📦 Folder compression summary".bold().green());
-        println!(
-            "{} {:.2} MB",
-            "Total original:   ".blue(),
-            total_orig as f64 / 1_048_576.0
-        );
-        println!(
-            "{} {:.2} MB",
-            "Total compressed: ".blue(),
-            total_comp as f64 / 1_048_576.0
-        );
-        let ratio = total_comp as f64 / total_orig as f64;
-        println!("{} {:.2}%", "Overall ratio:    ".yellow(), ratio * 100.0);
-        println!("{} {:.2} s", "Total time:       ".magenta(), total_secs);
-        let throughput = (total_orig as f64 / 1_048_576.0) / total_secs.max(1e-9);
-        println!("{} {:.2} MB/s", "Avg throughput:   ".cyan(), throughput);
-    } else {
-        anyhow::bail!("Input path is not a file or directory");
+/// Restore a compressed folder, handling files already present under `output_path` according
+/// to `overwrite`; returns the number of files restored
+///
+/// With `output_path` equal to `input_path`, a tree compressed in place is restored in place.
+pub fn decompress_path_overwrite(
+    input_path: &str,
+    output_path: &str,
+    compressor: &dyn Compressor,
+    overwrite: OverwritePolicy,
+    progress: &dyn ProgressSink,
+) -> Result<usize> {
+    let input = Path::new(input_path);
+    let options = DecompressOptions::new().overwrite(overwrite).progress(progress);
+    restore_folder(input, Path::new(output_path), &load_index(input)?, compressor, &options).map(|report| report.files)
+}
+
+/// Index of the compressed folder `input`, which must have one
+pub(crate) fn load_index(input: &Path) -> Result<Manifest> {
+    if !input.join(manifest::INDEX_FILE).is_file() {
+        return Err(CompressorError::InvalidInput(format!("{} has no {}", input.display(), manifest::INDEX_FILE)));
+    }
+    Manifest::load(input)
+}
+
+/// Folder restore behind [`decompress_path_overwrite`] and [`DecompressOptions::run`](crate::options::DecompressOptions::run)
+///
+/// Restores exactly the entries of `manifest`, which callers load or verify beforehand.
+/// The limits of `options` apply to each restored file on its own.
+pub(crate) fn restore_folder(
+    input: &Path,
+    output: &Path,
+    manifest: &Manifest,
+    compressor: &dyn Compressor,
+    options: &DecompressOptions,
+) -> Result<DecompressReport> {
+    let start = Instant::now();
+    let progress = options.progress;
+    let destination = SafeDestination::new(output)?;
+    let mut jobs = Vec::new();
+    for entry in manifest.entries.values() {
+        jobs.push((input.join(extract::normalize_entry(&entry.output)?), destination.resolve(&entry.source)?));
     }
+    let skipped: HashSet<PathBuf> = overwrite::plan_outputs(jobs, options.overwrite)?
+        .skipped
+        .into_iter()
+        .map(|(_, restored)| restored)
+        .collect();
 
-    // After compression, verify all hashes
-    for (file, expected) in &manifest {
-        let path = if input.is_file() {
-            output.join(file)
-        } else {
-            output.join(file)
-        };
-        let actual = sha256_file(&path)?;
-        if &actual != expected {
-            println!("{}: {} != {}", file, actual, expected);
-            anyhow::bail!("Hash mismatch for {}", file);
+    let artifact_size = |entry: &ManifestEntry| metadata(input.join(&entry.output)).map(|m| m.len()).unwrap_or(0);
+    progress.job_started(manifest.entries.len(), manifest.entries.values().map(artifact_size).sum());
+    let mut report = DecompressReport { skipped_files: skipped.len(), verified: true, ..Default::default() };
+    for entry in manifest.entries.values() {
+        let artifact = input.join(extract::normalize_entry(&entry.output)?);
+        if skipped.contains(&destination.resolve(&entry.source)?) {
+            progress.file_skipped(&artifact, artifact_size(entry));
+            continue;
+        }
+        progress.file_started(&artifact, artifact_size(entry));
+        if let Err(err) = restore_entry(&artifact, entry, &destination, compressor, &options.limits, progress, &options.cancel) {
+            progress.file_failed(&artifact, &err);
+            progress.job_finished(Some(&err));
+            return Err(err);
         }
+        progress.file_finished(&artifact, artifact_size(entry));
+        report.files += 1;
+        report.compressed_size += artifact_size(entry);
+        report.decompressed_size += entry.size;
     }
-    println!("All files verified by SHA-256 hash.");
-    Ok(())
+    progress.job_finished(None);
+    report.duration_secs = start.elapsed().as_secs_f64();
+    Ok(report)
 }
 
-pub fn decompress_file(input_path: &str, output_path: &str) -> anyhow::Result<()> {
-    let start = Instant::now();
+/// Decode one artifact of a compressed folder and check it against its source hash
+///
+/// The file is written under a `.part` name and only renamed into place once its hash
+/// matches, so a failed entry never leaves a partial or corrupt file under the real name.
+fn restore_entry(
+    artifact: &Path,
+    entry: &ManifestEntry,
+    destination: &SafeDestination,
+    compressor: &dyn Compressor,
+    limits: &DecompressLimits,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<()> {
+    let read = Cell::new(0);
+    let mut reader = CountingReader {
+        inner: ProgressReader::new(Cancellable(File::open(artifact).at(artifact)?, cancel), artifact, progress),
+        count: &read,
+    };
+    let restored_path = destination.resolve(&entry.source)?;
+    let part_entry = part_path(&extract::normalize_entry(&entry.source)?);
+    let file = destination.create_file(&part_entry.to_string_lossy())?;
+    let part = PartFile::new(destination.resolve(&part_entry.to_string_lossy())?);
+    let mut writer = LimitedWriter { inner: BufWriter::new(file), written: 0, limits: *limits, read: &read, tripped: None };
+    decode_limited(compressor, &mut reader, &mut writer, limits.max_window_log, cancel)
+        .map_err(|err| CompressorError::decode(artifact, Some(read.get()), err))?;
+    let file = writer.inner.into_inner().map_err(|e| e.into_error()).at(part.path())?;
+    durable::sync_file(&file).at(part.path())?;
+    drop(file);
+    let actual = sha256_file(part.path())?;
+    if actual != entry.source_hash {
+        return Err(CompressorError::Integrity { path: restored_path, expected: entry.source_hash.clone(), actual });
+    }
+    destination.commit_file(&entry.source, part)?;
+    Ok(())
+}
 
-    let mut input_file = File::open(input_path)?;
-    let mut decoder = Decoder::new(&mut input_file)?;
-    
-    let mut output_file = File::create(output_path)?;
-    std::io::copy(&mut decoder, &mut output_file)?;
-    
-    let duration = start.elapsed();
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    pub original_size: u64,
+    pub compressed_size: u64,
+    pub duration_secs: f64,
+    /// Part of `duration_secs` spent waiting for data to reach stable storage
+    pub sync_secs: f64,
+    /// Inputs left alone: existing outputs under `--skip-existing`, or finished by a resumed job
+    pub skipped_files: usize,
+}
 
-    // Get sizes
-    let compressed_size = metadata(input_path)?.len();
-    let decompressed_size = metadata(output_path)?.len();
-
-    // Integrity check: verify decompressed file against manifest if present
-    use std::fs;
-    let manifest_path = Path::new(input_path).parent().map(|p| p.join("manifest-sha256.txt"));
-    if let Some(manifest_path) = manifest_path {
-        if manifest_path.exists() {
-            let manifest = fs::read_to_string(&manifest_path)?;
-            for line in manifest.lines() {
-                let mut parts = line.split_whitespace();
-                let hash = parts.next();
-                let file = parts.next();
-                if let (Some(hash), Some(file)) = (hash, file) {
-                    let out_file = Path::new(output_path);
-                    if out_file.file_name().map(|n| n == file).unwrap_or(false) {
-                        let actual = sha256_file(out_file)?;
-                        if actual != hash {
-                            println!("Hash mismatch for {}: {} != {}", file, actual, hash);
-                            anyhow::bail!("Decompressed file hash mismatch");
-                        } else {
-                            println!("Verified {} by SHA-256 hash.", file);
-                        }
-                    }
-                }
-            }
-        }
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path).at(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = file.read(&mut buf).at(path)?;
+        if n == 0 { break; }
+        hasher.update(&buf[..n]);
     }
+    Ok(hex::encode(hasher.finalize()))
+}
 
-    let ratio = decompressed_size as f64 / compressed_size as f64;
-    let speed = (decompressed_size as f64 / 1_048_576.0) / duration.as_secs_f64(); // MB/s
+/// Compress one file with zstd, replacing `output_path` if it exists
+pub fn compress_single_file(
+    input_path: &Path,
+    output_path: &Path,
+    progress: &dyn ProgressSink,
+    level: i32
+) -> Result<Stats> {
+    CompressOptions::new()
+        .level(level)
+        .threads(1)
+        .overwrite(OverwritePolicy::Force)
+        .progress(progress)
+        .run(input_path, output_path)
+        .map(|report| report.stats)
+}
 
-    println!("\n{}", "📊 Decompression complete!".bold().green());
+/// What a compress job did: totals, each file it wrote, and the manifest it left
+#[derive(Debug, Default)]
+pub struct CompressReport {
+    pub stats: Stats,
+    /// Files compressed by this run; skipped and unchanged ones are not listed
+    pub files: Vec<FileResult>,
+    /// The SHA-256 manifest written for a folder input
+    pub manifest: Option<PathBuf>,
+    /// Outputs checked after compression, by hash or by decoding them again
+    pub verified: usize,
+    /// What changed since the last run, for incremental jobs
+    pub changes: Option<ChangeSummary>,
+    /// Inputs deleted once their outputs were written
+    pub removed_sources: usize,
+}
 
-    println!(
-        "{} {:.2} MB",
-        "Compressed size:     ".blue(),
-        compressed_size as f64 / 1_048_576.0
-    );
-    println!(
-        "{} {:.2} MB",
-        "Decompressed size:   ".blue(),
-        decompressed_size as f64 / 1_048_576.0
-    );
-    println!(
-        "{} {:.2}%",
-        "Expansion ratio:     ".yellow(),
-        ratio * 100.0
-    );
-    println!(
-        "{} {:.2} s",
-        "Time taken:".magenta(),
-        duration.as_secs_f64()
-    );
-    println!(
-        "{} {:.2} MB/s",
-        "Throughput:".cyan(),
-        speed
-    );
+/// One input of a compress job and the artifact written for it
+#[derive(Debug)]
+pub struct FileResult {
+    pub source: PathBuf,
+    pub output: PathBuf,
+    pub stats: Stats,
+}
 
-    Ok(())
-}
\ No newline at end of file
+/// Compress a file or directory with zstd, replacing existing outputs, and decode every
+/// artifact again to check it against its source
+pub fn compress_path(input_path: &str, output_path: &str, threads: usize, level: i32, progress: &dyn ProgressSink) -> Result<CompressReport> {
+    CompressOptions::new()
+        .level(level)
+        .threads(threads)
+        .overwrite(OverwritePolicy::Force)
+        .hash(HashCheck::Verify)
+        .progress(progress)
+        .run(input_path, output_path)
+}
+
+/// What a decompress job produced
+///
+/// Sizes cover the files restored by this run. Dedup archives share chunks between files,
+/// so their sizes are left at zero.
+#[derive(Debug, Default)]
+pub struct DecompressReport {
+    /// Files restored
+    pub files: usize,
+    /// Files left alone because their output already existed
+    pub skipped_files: usize,
+    pub compressed_size: u64,
+    pub decompressed_size: u64,
+    pub duration_secs: f64,
+    /// Whether restored content was checked against recorded hashes
+    pub verified: bool,
+    /// Compressed inputs deleted once restored
+    pub removed_sources: usize,
+}
+
+/// Decompress one zstd file without limits, replacing `output_path` if it exists
+pub fn decompress_file(input_path: &str, output_path: &str) -> Result<DecompressReport> {
+    DecompressOptions::new()
+        .format(&ZstdCompressor)
+        .overwrite(OverwritePolicy::Force)
+        .run(input_path, output_path)
+}
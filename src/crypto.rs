@@ -0,0 +1,448 @@
+use std::{
+    fs,
+    io::{self, BufRead, Read, Write},
+    ops::RangeInclusive,
+    path::Path,
+    sync::Mutex,
+};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{
+        stream::{DecryptorBE32, EncryptorBE32},
+        KeyInit, Payload,
+    },
+    XChaCha20Poly1305,
+};
+use zeroize::Zeroizing;
+
+use crate::compressor::{Compressor, StreamEncoder};
+use crate::error::{CompressorError, PathContext, Result};
+
+/// Extension appended to the compressor's own extension (`file.zst.enc`)
+pub const ENCRYPTED_EXTENSION: &str = "enc";
+
+const MAGIC: &[u8; 8] = b"RZCRYPT\x01";
+const ALG_XCHACHA20POLY1305_STREAM: u8 = 1;
+const KDF_KEY_FILE: u8 = 0;
+const KDF_ARGON2ID: u8 = 1;
+/// Plaintext bytes per authenticated chunk, as a power of two (64 KiB)
+const CHUNK_LOG: u8 = 16;
+const TAG_LEN: usize = 16;
+/// XChaCha20's 24-byte nonce minus the 5 bytes STREAM uses for the counter and last flag
+const NONCE_PREFIX_LEN: usize = 19;
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+/// Largest Argon2 costs accepted from a header, far above what `for_secret` writes, so a
+/// crafted file cannot make decryption take gigabytes of memory or minutes of CPU
+const MAX_M_COST_KIB: u32 = 1 << 20;
+const MAX_T_COST: u32 = 16;
+const MAX_P_COST: u32 = 16;
+
+type Key = Zeroizing<[u8; KEY_LEN]>;
+
+/// Key material supplied by the user
+pub enum Secret {
+    Passphrase(Zeroizing<String>),
+    Key(Key),
+}
+
+impl Secret {
+    pub fn passphrase(passphrase: String) -> Self {
+        Secret::Passphrase(Zeroizing::new(passphrase))
+    }
+
+    /// Load a raw key file: either exactly 32 bytes or 64 hex characters
+    pub fn from_key_file(path: &Path) -> Result<Self> {
+        Ok(Secret::Key(read_key_file(path)?))
+    }
+}
+
+/// Read a 32-byte key stored either raw or as 64 hex characters
+pub(crate) fn read_key_file(path: &Path) -> Result<Key> {
+    let bytes = Zeroizing::new(
+        fs::read(path).at(path)?,
+    );
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    if bytes.len() == KEY_LEN {
+        key.copy_from_slice(&bytes);
+    } else {
+        let text = std::str::from_utf8(&bytes).unwrap_or_default().trim();
+        hex::decode_to_slice(text, &mut key[..]).map_err(|_| {
+            CompressorError::Crypto(format!("Key file {} must hold 32 raw bytes or 64 hex characters", path.display()))
+        })?;
+    }
+    Ok(key)
+}
+
+/// How the key of an encrypted file was derived
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kdf {
+    KeyFile,
+    Argon2id { m_cost: u32, t_cost: u32, p_cost: u32, salt: [u8; SALT_LEN] },
+}
+
+impl Kdf {
+    fn derive(&self, secret: &Secret) -> Result<Key> {
+        match (self, secret) {
+            (Kdf::KeyFile, Secret::Key(key)) => Ok(key.clone()),
+            (Kdf::Argon2id { m_cost, t_cost, p_cost, salt }, Secret::Passphrase(passphrase)) => {
+                let params = Params::new(*m_cost, *t_cost, *p_cost, Some(KEY_LEN))
+                    .map_err(|e| CompressorError::Crypto(format!("Invalid Argon2 parameters: {}", e)))?;
+                let mut key = Zeroizing::new([0u8; KEY_LEN]);
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(passphrase.as_bytes(), salt, &mut key[..])
+                    .map_err(|e| CompressorError::Crypto(format!("Key derivation failed: {}", e)))?;
+                Ok(key)
+            }
+            (Kdf::KeyFile, Secret::Passphrase(_)) => {
+                Err(CompressorError::Crypto("File was encrypted with a key file, not a passphrase".to_string()))
+            }
+            (Kdf::Argon2id { .. }, Secret::Key(_)) => {
+                Err(CompressorError::Crypto("File was encrypted with a passphrase, not a key file".to_string()))
+            }
+        }
+    }
+
+    fn for_secret(secret: &Secret) -> io::Result<Self> {
+        Ok(match secret {
+            Secret::Key(_) => Kdf::KeyFile,
+            Secret::Passphrase(_) => {
+                let mut salt = [0u8; SALT_LEN];
+                getrandom::getrandom(&mut salt).map_err(io::Error::other)?;
+                Kdf::Argon2id {
+                    m_cost: Params::DEFAULT_M_COST,
+                    t_cost: Params::DEFAULT_T_COST,
+                    p_cost: Params::DEFAULT_P_COST,
+                    salt,
+                }
+            }
+        })
+    }
+}
+
+/// Self-describing header written in front of every encrypted stream
+///
+/// The serialized header is passed as associated data to every chunk, so altering any
+/// field (including the KDF parameters) fails authentication of the first chunk.
+struct Header {
+    kdf: Kdf,
+    chunk_log: u8,
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+}
+
+impl Header {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(ALG_XCHACHA20POLY1305_STREAM);
+        match self.kdf {
+            Kdf::KeyFile => out.push(KDF_KEY_FILE),
+            Kdf::Argon2id { .. } => out.push(KDF_ARGON2ID),
+        }
+        out.push(self.chunk_log);
+        out.push(0);
+        if let Kdf::Argon2id { m_cost, t_cost, p_cost, salt } = self.kdf {
+            out.extend_from_slice(&m_cost.to_le_bytes());
+            out.extend_from_slice(&t_cost.to_le_bytes());
+            out.extend_from_slice(&p_cost.to_le_bytes());
+            out.extend_from_slice(&salt);
+        }
+        out.extend_from_slice(&self.nonce_prefix);
+        out
+    }
+
+    fn read(input: &mut dyn Read) -> io::Result<Self> {
+        let mut fixed = [0u8; 12];
+        input.read_exact(&mut fixed)?;
+        if &fixed[..8] != MAGIC {
+            return Err(invalid_data("not an encrypted stream (bad magic)"));
+        }
+        if fixed[8] != ALG_XCHACHA20POLY1305_STREAM {
+            return Err(invalid_data("unsupported encryption algorithm"));
+        }
+        let chunk_log = fixed[10];
+        if !(10..=24).contains(&chunk_log) {
+            return Err(invalid_data("invalid chunk size in encryption header"));
+        }
+        let kdf = match fixed[9] {
+            KDF_KEY_FILE => Kdf::KeyFile,
+            KDF_ARGON2ID => {
+                let mut params = [0u8; 12 + SALT_LEN];
+                input.read_exact(&mut params)?;
+                let word = |i: usize| u32::from_le_bytes(params[i..i + 4].try_into().unwrap());
+                if word(0) > MAX_M_COST_KIB || word(4) > MAX_T_COST || word(8) > MAX_P_COST {
+                    return Err(invalid_data(format!(
+                        "Argon2 costs in encryption header exceed the limits (memory {} KiB, time {}, lanes {})",
+                        word(0),
+                        word(4),
+                        word(8)
+                    )));
+                }
+                Kdf::Argon2id {
+                    m_cost: word(0),
+                    t_cost: word(4),
+                    p_cost: word(8),
+                    salt: params[12..].try_into().unwrap(),
+                }
+            }
+            _ => return Err(invalid_data("unsupported key derivation")),
+        };
+        let mut nonce_prefix = [0u8; NONCE_PREFIX_LEN];
+        input.read_exact(&mut nonce_prefix)?;
+        Ok(Header { kdf, chunk_log, nonce_prefix })
+    }
+}
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Read the key derivation recorded in the header of an encrypted file
+pub fn read_kdf(path: &Path) -> Result<Kdf> {
+    let mut file = fs::File::open(path).at(path)?;
+    Ok(Header::read(&mut file).map_err(|e| CompressorError::decode(path, Some(0), e.into()))?.kdf)
+}
+
+/// Encrypts everything written to it in authenticated chunks; call `finish` when done
+pub struct EncryptingWriter<W: Write> {
+    inner: W,
+    encryptor: Option<EncryptorBE32<XChaCha20Poly1305>>,
+    aad: Vec<u8>,
+    buf: Vec<u8>,
+    chunk_size: usize,
+}
+
+impl<W: Write> EncryptingWriter<W> {
+    fn new(mut inner: W, key: &Key, kdf: Kdf) -> io::Result<Self> {
+        let mut nonce_prefix = [0u8; NONCE_PREFIX_LEN];
+        getrandom::getrandom(&mut nonce_prefix).map_err(io::Error::other)?;
+        let header = Header { kdf, chunk_log: CHUNK_LOG, nonce_prefix };
+        let aad = header.to_bytes();
+        inner.write_all(&aad)?;
+        let aead = XChaCha20Poly1305::new(key[..].into());
+        Ok(EncryptingWriter {
+            inner,
+            encryptor: Some(EncryptorBE32::from_aead(aead, nonce_prefix[..].into())),
+            aad,
+            buf: Vec::new(),
+            chunk_size: 1 << CHUNK_LOG,
+        })
+    }
+
+    /// Seal the final chunk and return the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        let encryptor = self.encryptor.take().ok_or_else(|| io::Error::other("stream already finished"))?;
+        let sealed = encryptor
+            .encrypt_last(Payload { msg: &self.buf, aad: &self.aad })
+            .map_err(|_| io::Error::other("encryption failed"))?;
+        self.inner.write_all(&sealed)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for EncryptingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        let encryptor = self.encryptor.as_mut().ok_or_else(|| io::Error::other("stream already finished"))?;
+        // chunks are sealed in place and the sealed prefix dropped once, so a large write
+        // costs one pass over the buffer; at least one byte is kept back so the final chunk
+        // is never empty unless the input is
+        let mut sealed_to = 0;
+        let mut result = Ok(());
+        while self.buf.len() - sealed_to > self.chunk_size {
+            let chunk = &self.buf[sealed_to..sealed_to + self.chunk_size];
+            result = encryptor
+                .encrypt_next(Payload { msg: chunk, aad: &self.aad })
+                .map_err(|_| io::Error::other("encryption failed"))
+                .and_then(|sealed| self.inner.write_all(&sealed));
+            if result.is_err() {
+                break;
+            }
+            sealed_to += self.chunk_size;
+        }
+        self.buf.drain(..sealed_to);
+        result.map(|()| buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl StreamEncoder for EncryptingWriter<Box<dyn StreamEncoder + '_>> {
+    fn finish(self: Box<Self>) -> Result<()> {
+        (*self).finish()?.finish()
+    }
+}
+
+/// Decrypts and authenticates a stream produced by `EncryptingWriter`
+///
+/// Every chunk is verified before any of its plaintext is returned; a modified, reordered
+/// or truncated stream fails with `InvalidData` naming the offending chunk.
+pub struct DecryptingReader<R: Read> {
+    inner: R,
+    decryptor: Option<DecryptorBE32<XChaCha20Poly1305>>,
+    aad: Vec<u8>,
+    sealed_chunk: usize,
+    pending: Vec<u8>,
+    plain: Zeroizing<Vec<u8>>,
+    pos: usize,
+    chunk_index: u64,
+    eof: bool,
+}
+
+impl<R: Read> DecryptingReader<R> {
+    fn new(mut inner: R, keys: &KeyCache, secret: &Secret) -> Result<Self> {
+        let header = Header::read(&mut inner)?;
+        let key = keys.get_or_derive(header.kdf, secret)?;
+        let aead = XChaCha20Poly1305::new(key[..].into());
+        Ok(DecryptingReader {
+            inner,
+            decryptor: Some(DecryptorBE32::from_aead(aead, header.nonce_prefix[..].into())),
+            aad: header.to_bytes(),
+            sealed_chunk: (1usize << header.chunk_log) + TAG_LEN,
+            pending: Vec::new(),
+            plain: Zeroizing::new(Vec::new()),
+            pos: 0,
+            chunk_index: 0,
+            eof: false,
+        })
+    }
+
+    fn next_chunk(&mut self) -> io::Result<()> {
+        // one byte of lookahead tells a full middle chunk from a full last chunk
+        while !self.eof && self.pending.len() <= self.sealed_chunk {
+            let start = self.pending.len();
+            self.pending.resize(self.sealed_chunk + 1, 0);
+            match self.inner.read(&mut self.pending[start..]) {
+                Ok(n) => {
+                    self.pending.truncate(start + n);
+                    self.eof = n == 0;
+                }
+                Err(e) => {
+                    self.pending.truncate(start);
+                    if e.kind() != io::ErrorKind::Interrupted {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        let tampered = |index: u64| {
+            io::Error::other(CompressorError::Crypto(format!(
+                "authentication failed for encrypted chunk {} (wrong key, or data was modified or truncated)",
+                index
+            )))
+        };
+        let index = self.chunk_index;
+        let plain = if self.pending.len() > self.sealed_chunk {
+            let rest = self.pending.split_off(self.sealed_chunk);
+            let sealed = std::mem::replace(&mut self.pending, rest);
+            let decryptor = self.decryptor.as_mut().ok_or_else(|| tampered(index))?;
+            decryptor.decrypt_next(Payload { msg: &sealed, aad: &self.aad }).map_err(|_| tampered(index))?
+        } else {
+            let decryptor = self.decryptor.take().ok_or_else(|| tampered(index))?;
+            let sealed = std::mem::take(&mut self.pending);
+            decryptor.decrypt_last(Payload { msg: &sealed, aad: &self.aad }).map_err(|_| tampered(index))?
+        };
+        self.plain = Zeroizing::new(plain);
+        self.pos = 0;
+        self.chunk_index += 1;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.plain.len() {
+            if self.decryptor.is_none() {
+                return Ok(0);
+            }
+            self.next_chunk()?;
+        }
+        let n = buf.len().min(self.plain.len() - self.pos);
+        buf[..n].copy_from_slice(&self.plain[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Derived keys by KDF, so a folder job runs Argon2 once rather than once per file
+#[derive(Default)]
+struct KeyCache(Mutex<Vec<(Kdf, Key)>>);
+
+impl KeyCache {
+    fn get_or_derive(&self, kdf: Kdf, secret: &Secret) -> Result<Key> {
+        let mut keys = self.0.lock().unwrap();
+        if let Some((_, key)) = keys.iter().find(|(k, _)| *k == kdf) {
+            return Ok(key.clone());
+        }
+        let key = kdf.derive(secret)?;
+        keys.push((kdf, key.clone()));
+        Ok(key)
+    }
+
+    /// The key used for new files, derived on first use with a fresh salt
+    fn for_encryption(&self, secret: &Secret) -> Result<(Kdf, Key)> {
+        if let Some(entry) = self.0.lock().unwrap().first() {
+            return Ok(entry.clone());
+        }
+        let kdf = Kdf::for_secret(secret)?;
+        Ok((kdf, self.get_or_derive(kdf, secret)?))
+    }
+}
+
+/// Wraps another compressor, encrypting its output with XChaCha20-Poly1305 in STREAM mode
+pub struct EncryptedCompressor {
+    inner: Box<dyn Compressor>,
+    secret: Secret,
+    keys: KeyCache,
+    extension: String,
+}
+
+impl EncryptedCompressor {
+    pub fn new(inner: Box<dyn Compressor>, secret: Secret) -> Self {
+        let extension = format!("{}.{}", inner.extension(), ENCRYPTED_EXTENSION);
+        EncryptedCompressor { inner, secret, keys: KeyCache::default(), extension }
+    }
+}
+
+impl Compressor for EncryptedCompressor {
+    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write, level: i32) -> Result<()> {
+        let (kdf, key) = self.keys.for_encryption(&self.secret)?;
+        let mut writer = EncryptingWriter::new(output, &key, kdf)?;
+        self.inner.compress(input, &mut writer, level)?;
+        writer.finish()?;
+        Ok(())
+    }
+    fn decompress(&self, input: &mut dyn Read, output: &mut dyn Write) -> Result<()> {
+        let mut reader = DecryptingReader::new(input, &self.keys, &self.secret)?;
+        self.inner.decompress(&mut reader, output)?;
+        // the decoder may stop at the end of its frame: authenticate the final chunk anyway
+        io::copy(&mut reader, &mut io::sink())?;
+        Ok(())
+    }
+    fn decompress_window_limited(&self, input: &mut dyn Read, output: &mut dyn Write, max_window_log: u32) -> Result<()> {
+        let mut reader = DecryptingReader::new(input, &self.keys, &self.secret)?;
+        self.inner.decompress_window_limited(&mut reader, output, max_window_log)?;
+        io::copy(&mut reader, &mut io::sink())?;
+        Ok(())
+    }
+    fn encoder<'a>(&'a self, output: Box<dyn StreamEncoder + 'a>, level: i32) -> Result<Box<dyn StreamEncoder + 'a>> {
+        let (kdf, key) = self.keys.for_encryption(&self.secret)?;
+        let writer = EncryptingWriter::new(output, &key, kdf)?;
+        self.inner.encoder(Box::new(writer), level)
+    }
+    fn decoder<'a>(&'a self, input: Box<dyn BufRead + 'a>) -> Result<Box<dyn Read + 'a>> {
+        let reader = DecryptingReader::new(input, &self.keys, &self.secret)?;
+        self.inner.decoder(Box::new(io::BufReader::new(reader)))
+    }
+    fn extension(&self) -> &str { &self.extension }
+    fn encryption(&self) -> Option<&'static str> { Some("xchacha20poly1305") }
+    fn level_range(&self) -> Option<RangeInclusive<i32>> { self.inner.level_range() }
+    fn default_level(&self) -> i32 { self.inner.default_level() }
+    fn supports_dictionaries(&self) -> bool { self.inner.supports_dictionaries() }
+    fn supports_multithreading(&self) -> bool { self.inner.supports_multithreading() }
+    // every chunk is authenticated
+    fn has_checksum(&self) -> bool { true }
+}
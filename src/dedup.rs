@@ -0,0 +1,400 @@
+use std::{
+    cell::Cell,
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::cancel::{self, CancelToken, Cancellable, PartFile};
+use crate::compressor::{self, Compressor, DecompressReport};
+use crate::durable;
+use crate::error::{CompressorError, PathContext, Result};
+use crate::limits::{CountingReader, LimitedWriter};
+use crate::manifest;
+use crate::options::DecompressOptions;
+use crate::overwrite;
+use crate::progress::ProgressSink;
+use crate::registry::{self, Format};
+use crate::extract::{self, SafeDestination};
+
+/// Index describing how the files of a dedup archive are assembled from chunks
+pub const DEDUP_INDEX_FILE: &str = "dedup-index.tsv";
+
+/// Directory (inside the archive) holding one compressed file per unique chunk
+pub const CHUNK_DIR: &str = "chunks";
+
+const INDEX_MAGIC: &str = "# rustzip dedup v1";
+
+const MIN_CHUNK: usize = 16 * 1024;
+const MAX_CHUNK: usize = 256 * 1024;
+/// Cut when the top 16 bits of the gear hash are zero: ~64 KiB average past `MIN_CHUNK`
+const CUT_MASK: u64 = !(u64::MAX >> 16);
+
+/// Gear hash table, filled with splitmix64 so chunk boundaries are stable across builds
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// Length of the first chunk in `data`; `data` must hold `MAX_CHUNK` bytes unless at EOF
+fn cut_point(data: &[u8]) -> usize {
+    if data.len() <= MIN_CHUNK {
+        return data.len();
+    }
+    let end = data.len().min(MAX_CHUNK);
+    let mut hash = 0u64;
+    for (i, &b) in data[..end].iter().enumerate().skip(MIN_CHUNK) {
+        hash = (hash << 1).wrapping_add(GEAR[b as usize]);
+        if hash & CUT_MASK == 0 {
+            return i + 1;
+        }
+    }
+    end
+}
+
+/// Splits a stream into content-defined chunks using a rolling gear hash
+pub struct Chunker<R: Read> {
+    inner: R,
+    buf: Vec<u8>,
+    eof: bool,
+}
+
+impl<R: Read> Chunker<R> {
+    pub fn new(inner: R) -> Self {
+        Chunker { inner, buf: Vec::with_capacity(MAX_CHUNK), eof: false }
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        while !self.eof && self.buf.len() < MAX_CHUNK {
+            let start = self.buf.len();
+            self.buf.resize(MAX_CHUNK, 0);
+            match self.inner.read(&mut self.buf[start..]) {
+                Ok(n) => {
+                    self.buf.truncate(start + n);
+                    self.eof = n == 0;
+                }
+                Err(e) => {
+                    self.buf.truncate(start);
+                    if e.kind() != io::ErrorKind::Interrupted {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for Chunker<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(e) = self.fill() {
+            return Some(Err(e));
+        }
+        if self.buf.is_empty() {
+            return None;
+        }
+        let rest = self.buf.split_off(cut_point(&self.buf));
+        Some(Ok(std::mem::replace(&mut self.buf, rest)))
+    }
+}
+
+/// Totals for a dedup archive run
+#[derive(Debug, Default)]
+pub struct DedupStats {
+    pub files: usize,
+    /// Bytes of input, before deduplication
+    pub original_size: u64,
+    /// Bytes of input left after removing duplicate chunks
+    pub unique_size: u64,
+    /// Bytes written to the chunk store for the unique chunks
+    pub compressed_size: u64,
+    pub total_chunks: usize,
+    pub unique_chunks: usize,
+    pub duration_secs: f64,
+    /// Part of `duration_secs` spent waiting for data to reach stable storage
+    pub sync_secs: f64,
+}
+
+impl DedupStats {
+    /// Unique bytes as a fraction of input bytes
+    pub fn dedup_ratio(&self) -> f64 {
+        self.unique_size as f64 / self.original_size.max(1) as f64
+    }
+
+    /// Stored bytes as a fraction of unique bytes
+    pub fn compression_ratio(&self) -> f64 {
+        self.compressed_size as f64 / self.unique_size.max(1) as f64
+    }
+}
+
+/// Whether `path` is a directory written by `compress_dedup`
+pub fn is_dedup_archive(path: &Path) -> bool {
+    path.join(DEDUP_INDEX_FILE).is_file()
+}
+
+fn chunk_path(root: &Path, hash: &str, ext: &str) -> std::path::PathBuf {
+    root.join(CHUNK_DIR).join(&hash[..2]).join(format!("{}.{}", hash, ext))
+}
+
+/// Compress a file or directory into a deduplicating chunk store
+///
+/// Every file is cut into content-defined chunks; each distinct chunk is compressed once
+/// into `chunks/` and the index records the chunk list needed to rebuild every file.
+/// Chunks already present from an earlier run into the same archive are reused. The index
+/// is written whole once every file is stored, so an interrupted run leaves the previous
+/// index, if any, in place. The run stops with `Cancelled` once `cancel` is cancelled.
+pub fn compress_dedup(
+    input_path: &str,
+    output_path: &str,
+    level: i32,
+    compressor: &dyn Compressor,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<DedupStats> {
+    let start = Instant::now();
+    let input = Path::new(input_path);
+    let output = Path::new(output_path);
+    let (files, total_bytes, root) = if input.is_file() {
+        let size = fs::metadata(input).at(input)?.len();
+        (vec![input.to_path_buf()], size, input.parent().unwrap_or(Path::new("")))
+    } else if input.is_dir() {
+        let (files, total) = compressor::collect_files(input);
+        (files, total, input)
+    } else {
+        return Err(CompressorError::InvalidInput(format!("{} is not a file or directory", input.display())));
+    };
+    fs::create_dir_all(output).at(output)?;
+
+    progress.job_started(files.len(), total_bytes);
+    let mut stats = DedupStats::default();
+    let mut seen = HashSet::new();
+    let index_path = output.join(DEDUP_INDEX_FILE);
+    let mut index = format!("{}\t{}\n", INDEX_MAGIC, compressor.extension());
+    for file in files {
+        progress.file_started(&file, fs::metadata(&file).map(|m| m.len()).unwrap_or(0));
+        let mut add_file = || -> Result<()> {
+            let rel = file.strip_prefix(root).unwrap().to_string_lossy().to_string();
+            let reader = Cancellable(File::open(&file).at(&file)?, cancel);
+            let mut file_hasher = Sha256::new();
+            let mut size = 0u64;
+            let mut chunks = Vec::new();
+            for chunk in Chunker::new(reader) {
+                let chunk = chunk.at(&file)?;
+                file_hasher.update(&chunk);
+                size += chunk.len() as u64;
+                stats.total_chunks += 1;
+                let hash = hex::encode(Sha256::digest(&chunk));
+                if seen.insert(hash.clone()) {
+                    stats.unique_chunks += 1;
+                    stats.unique_size += chunk.len() as u64;
+                    stats.compressed_size += store_chunk(output, &hash, &chunk, level, compressor, &mut stats.sync_secs)?;
+                }
+                chunks.push(format!("{}:{}", hash, chunk.len()));
+                progress.file_progress(&file, size);
+            }
+            let (rel, hash) = (manifest::escape(&rel), hex::encode(file_hasher.finalize()));
+            index += &format!("{}\t{}\t{}\t{}\n", rel, size, hash, chunks.join(","));
+            stats.files += 1;
+            stats.original_size += size;
+            progress.file_finished(&file, size);
+            Ok(())
+        };
+        if let Err(err) = add_file() {
+            let err = cancel::map_cancelled(err, cancel);
+            progress.file_failed(&file, &err);
+            progress.job_finished(Some(&err));
+            return Err(err);
+        }
+    }
+    stats.sync_secs += durable::write_atomic(&index_path, index.as_bytes())?;
+    progress.job_finished(None);
+    stats.duration_secs = start.elapsed().as_secs_f64();
+    Ok(stats)
+}
+
+/// Write one chunk to the store unless already there, returning its stored size
+///
+/// A chunk file left by an earlier run is only reused if it decodes to `data`'s hash;
+/// anything else under that name is replaced.
+fn store_chunk(
+    root: &Path,
+    hash: &str,
+    data: &[u8],
+    level: i32,
+    compressor: &dyn Compressor,
+    sync_secs: &mut f64,
+) -> Result<u64> {
+    let path = chunk_path(root, hash, compressor.extension());
+    if chunk_matches(&path, hash, compressor) {
+        return Ok(fs::metadata(&path).at(&path)?.len());
+    }
+    fs::create_dir_all(path.parent().unwrap()).at(path.parent().unwrap())?;
+    let part = PartFile::new(path.with_extension(format!("{}.part", compressor.extension())));
+    let mut out = BufWriter::new(File::create(part.path()).at(part.path())?);
+    compressor.compress(&mut &data[..], &mut out, level)?;
+    *sync_secs += durable::sync_file(&out.into_inner()?).at(part.path())?;
+    *sync_secs += part.commit(&path).at(&path)?;
+    Ok(fs::metadata(&path).at(&path)?.len())
+}
+
+/// Whether the stored chunk at `path` decodes to content hashing to `hash`
+fn chunk_matches(path: &Path, hash: &str, compressor: &dyn Compressor) -> bool {
+    let Ok(file) = File::open(path) else { return false };
+    let mut out = HashingWriter { inner: io::sink(), hasher: Sha256::new() };
+    compressor.decompress(&mut BufReader::new(file), &mut out).is_ok() && hex::encode(out.hasher.finalize()) == hash
+}
+
+/// Rebuild every file of a dedup archive under `output_path`, returning the number of files
+///
+/// Existing files are never replaced; see [`DecompressOptions`] for the other policies.
+pub fn extract_dedup(input_path: &str, output_path: &str, progress: &dyn ProgressSink) -> Result<usize> {
+    let options = DecompressOptions::new().progress(progress);
+    extract_dedup_at(Path::new(input_path), Path::new(output_path), &options).map(|report| report.files)
+}
+
+/// One file of a dedup index
+struct IndexEntry<'a> {
+    lineno: usize,
+    rel: String,
+    size: u64,
+    hash: &'a str,
+    chunks: &'a str,
+}
+
+/// Rebuild a dedup archive as `options` say, holding each restored file to their limits
+/// across all its chunks
+///
+/// Every output is checked against the overwrite policy before the first file is written,
+/// as for compressed folders. Each file is written under a `.part` name and only renamed
+/// into place once its hash matches the index, so a failed file never leaves a partial one
+/// under the real name.
+pub(crate) fn extract_dedup_at(archive: &Path, output: &Path, options: &DecompressOptions) -> Result<DecompressReport> {
+    let (limits, progress, cancel) = (&options.limits, options.progress, &options.cancel);
+    let index_path = archive.join(DEDUP_INDEX_FILE);
+    let text = fs::read_to_string(&index_path).at(&index_path)?;
+    let mut lines = text.lines();
+    let ext = lines
+        .next()
+        .and_then(|header| header.strip_prefix(INDEX_MAGIC))
+        .map(|rest| rest.trim_start_matches('\t'))
+        .ok_or_else(|| CompressorError::corrupt(&index_path, "not a dedup archive index"))?;
+    let compressor = registry::global()
+        .for_extension(ext)
+        .map(Format::compressor)
+        .ok_or_else(|| CompressorError::UnsupportedFormat(ext.to_string()))?;
+
+    let mut entries = Vec::new();
+    for (i, line) in lines.enumerate() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [rel, size, hash, chunks] = fields[..] else {
+            return Err(CompressorError::corrupt(&index_path, format!("malformed line {}", i + 2)));
+        };
+        let size = size.parse().unwrap_or(0);
+        entries.push(IndexEntry { lineno: i + 2, rel: manifest::unescape(rel), size, hash, chunks });
+    }
+    let destination = SafeDestination::new(output)?;
+    let mut jobs = Vec::new();
+    for entry in &entries {
+        jobs.push((index_path.clone(), destination.resolve(&entry.rel)?));
+    }
+    let skipped: HashSet<PathBuf> = overwrite::plan_outputs(jobs, options.overwrite)?
+        .skipped
+        .into_iter()
+        .map(|(_, restored)| restored)
+        .collect();
+
+    let start = Instant::now();
+    progress.job_started(entries.len(), entries.iter().map(|entry| entry.size).sum());
+    let mut report = DecompressReport { skipped_files: skipped.len(), verified: true, ..Default::default() };
+    let mut restore_all = || -> Result<()> {
+        for entry in &entries {
+            let path = destination.resolve(&entry.rel)?;
+            if skipped.contains(&path) {
+                progress.file_skipped(&path, entry.size);
+                continue;
+            }
+            progress.file_started(&path, entry.size);
+            let restore = || -> Result<()> {
+                let part_entry = compressor::part_path(&extract::normalize_entry(&entry.rel)?).to_string_lossy().to_string();
+                let file = destination.create_file(&part_entry)?;
+                let part = PartFile::new(destination.resolve(&part_entry)?);
+                let read = Cell::new(0);
+                let mut out = LimitedWriter {
+                    inner: HashingWriter { inner: BufWriter::new(file), hasher: Sha256::new() },
+                    written: 0,
+                    limits: *limits,
+                    read: &read,
+                    tripped: None,
+                };
+                let mut done = 0;
+                for chunk in entry.chunks.split(',').filter(|c| !c.is_empty()) {
+                    let (hash, len) = chunk.split_once(':').unwrap_or((chunk, ""));
+                    // the hash becomes a path below chunks/, so it must be exactly that
+                    if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                        return Err(CompressorError::corrupt(&index_path, format!("bad chunk hash {:?} on line {}", hash, entry.lineno)));
+                    }
+                    let chunk_file = chunk_path(archive, hash, ext);
+                    let mut reader = CountingReader { inner: Cancellable(File::open(&chunk_file).at(&chunk_file)?, cancel), count: &read };
+                    compressor::decode_limited(compressor, &mut reader, &mut out, limits.max_window_log, cancel)
+                        .map_err(|err| CompressorError::decode(&chunk_file, None, err))?;
+                    done += len.parse::<u64>().unwrap_or(0);
+                    progress.file_progress(&path, done);
+                }
+                let HashingWriter { inner, hasher } = out.inner;
+                let file = inner.into_inner().map_err(|e| e.into_error()).at(part.path())?;
+                durable::sync_file(&file).at(part.path())?;
+                drop(file);
+                let actual = hex::encode(hasher.finalize());
+                if actual != entry.hash {
+                    return Err(CompressorError::Integrity { path: path.clone(), expected: entry.hash.to_string(), actual });
+                }
+                destination.commit_file(&entry.rel, part)?;
+                Ok(())
+            };
+            if let Err(err) = restore() {
+                progress.file_failed(&path, &err);
+                return Err(err);
+            }
+            progress.file_finished(&path, entry.size);
+            report.files += 1;
+        }
+        Ok(())
+    };
+    let result = restore_all();
+    progress.job_finished(result.as_ref().err());
+    result?;
+    report.duration_secs = start.elapsed().as_secs_f64();
+    Ok(report)
+}
+
+struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,46 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    time::Instant,
+};
+
+use crate::cancel::PartFile;
+use crate::compressor::part_path;
+use crate::error::{PathContext, Result};
+
+/// Flush a finished file's data and metadata to stable storage, returning the seconds spent
+pub(crate) fn sync_file(file: &File) -> io::Result<f64> {
+    let start = Instant::now();
+    file.sync_all()?;
+    Ok(start.elapsed().as_secs_f64())
+}
+
+/// Make files created or renamed in `dir` survive a crash, returning the seconds spent
+///
+/// A rename is only durable once the directory holding it is synced. Windows offers no
+/// way to sync a directory handle, so this is a no-op there.
+pub(crate) fn sync_dir(dir: &Path) -> io::Result<f64> {
+    let start = Instant::now();
+    #[cfg(unix)]
+    {
+        let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(start.elapsed().as_secs_f64())
+}
+
+/// Replace `path` with `contents` so a crash leaves either the old file or the new one
+///
+/// The contents go to a `.part` file that is synced and then renamed over `path`, and the
+/// directory is synced last. Returns the seconds spent syncing.
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> Result<f64> {
+    let part = PartFile::new(part_path(path));
+    let mut file = File::create(part.path()).at(part.path())?;
+    file.write_all(contents).at(part.path())?;
+    let sync_secs = sync_file(&file).at(part.path())?;
+    drop(file);
+    Ok(sync_secs + part.commit(path).at(path)?)
+}
@@ -0,0 +1,153 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::cancel::Cancelled;
+use crate::extract::UnsafeEntry;
+use crate::limits::LimitError;
+use crate::overwrite::OutputConflict;
+
+/// Everything the library can fail with
+///
+/// Callers match on the variant instead of the message: a missing input is `Io` with
+/// `NotFound`, a damaged stream is `CorruptData`, a restored file that does not hash to
+/// what was recorded is `Integrity`.
+#[derive(Debug, thiserror::Error)]
+pub enum CompressorError {
+    /// Reading or writing a file failed
+    #[error("{}", io_message(.path))]
+    Io {
+        path: Option<PathBuf>,
+        #[source]
+        source: io::Error,
+    },
+    /// No compressor handles this extension or format name
+    #[error("unsupported format: {0}")]
+    UnsupportedFormat(String),
+    /// The input is not a valid stream, archive, index or recovery file
+    #[error("corrupt data{}{}: {reason}", path_suffix(.path), offset_suffix(.offset))]
+    CorruptData {
+        path: Option<PathBuf>,
+        /// Position in the compressed input where decoding stopped, when known
+        offset: Option<u64>,
+        reason: String,
+    },
+    /// Content does not hash to what a manifest or index recorded
+    #[error("integrity check failed for {}: expected {expected}, found {actual}", .path.display())]
+    Integrity { path: PathBuf, expected: String, actual: String },
+    /// A decompression limit was crossed
+    #[error(transparent)]
+    LimitExceeded(#[from] LimitError),
+    /// The operation was cancelled through a `CancelToken`
+    #[error("operation cancelled")]
+    Cancelled,
+    /// An archive entry would be written outside its destination
+    #[error(transparent)]
+    UnsafeEntry(#[from] UnsafeEntry),
+    /// Planned outputs collide with each other, the inputs, or existing files
+    #[error(transparent)]
+    OutputConflict(#[from] OutputConflict),
+    /// Encryption or decryption failed: wrong key, wrong passphrase, tampered ciphertext
+    #[error("{0}")]
+    Crypto(String),
+    /// A manifest signature is missing, malformed or made with another key
+    #[error("{0}")]
+    Signature(String),
+    /// The request itself cannot be carried out, e.g. a folder-only mode given a file
+    #[error("{0}")]
+    InvalidInput(String),
+}
+
+pub type Result<T, E = CompressorError> = std::result::Result<T, E>;
+
+impl CompressorError {
+    /// I/O failure on `path`, unwrapping cancellation and limit errors raised inside readers
+    pub fn io(path: impl AsRef<Path>, source: io::Error) -> Self {
+        match Self::from(source) {
+            CompressorError::Io { path: None, source } => CompressorError::Io { path: Some(path.as_ref().to_path_buf()), source },
+            other => other,
+        }
+    }
+
+    /// Decoder failure while reading `path`, `offset` compressed bytes in
+    ///
+    /// Malformed or truncated input becomes `CorruptData`; anything else keeps its own variant.
+    pub fn decode(path: impl AsRef<Path>, offset: Option<u64>, err: CompressorError) -> Self {
+        Self::decoding(Some(path.as_ref().to_path_buf()), offset, err)
+    }
+
+    /// Decoder failure on an in-memory buffer
+    pub(crate) fn decode_buffer(err: CompressorError) -> Self {
+        Self::decoding(None, None, err)
+    }
+
+    fn decoding(path: Option<PathBuf>, offset: Option<u64>, err: CompressorError) -> Self {
+        match err {
+            CompressorError::Io { source, .. }
+                if matches!(source.kind(), io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof) =>
+            {
+                CompressorError::CorruptData { path, offset, reason: source.to_string() }
+            }
+            other => other,
+        }
+    }
+
+    pub(crate) fn corrupt(path: impl AsRef<Path>, reason: impl Into<String>) -> Self {
+        CompressorError::CorruptData { path: Some(path.as_ref().to_path_buf()), offset: None, reason: reason.into() }
+    }
+}
+
+impl From<io::Error> for CompressorError {
+    fn from(source: io::Error) -> Self {
+        if source.get_ref().is_some_and(|inner| inner.is::<Cancelled>()) {
+            return CompressorError::Cancelled;
+        }
+        if let Some(limit) = source.get_ref().and_then(|inner| inner.downcast_ref::<LimitError>()) {
+            return CompressorError::LimitExceeded(limit.clone());
+        }
+        // typed errors raised inside a reader or writer travel through `io::Error::other`
+        if source.get_ref().is_some_and(|inner| inner.is::<CompressorError>()) {
+            if let Some(Ok(err)) = source.into_inner().map(|inner| inner.downcast::<CompressorError>()) {
+                return *err;
+            }
+            unreachable!("checked above");
+        }
+        CompressorError::Io { path: None, source }
+    }
+}
+
+impl<W> From<io::IntoInnerError<W>> for CompressorError {
+    fn from(err: io::IntoInnerError<W>) -> Self {
+        err.into_error().into()
+    }
+}
+
+impl From<Cancelled> for CompressorError {
+    fn from(_: Cancelled) -> Self {
+        CompressorError::Cancelled
+    }
+}
+
+/// Attach the path an I/O error happened on
+pub(crate) trait PathContext<T> {
+    fn at(self, path: impl AsRef<Path>) -> Result<T>;
+}
+
+impl<T> PathContext<T> for io::Result<T> {
+    fn at(self, path: impl AsRef<Path>) -> Result<T> {
+        self.map_err(|source| CompressorError::io(path, source))
+    }
+}
+
+fn io_message(path: &Option<PathBuf>) -> String {
+    path.as_ref().map(|p| format!("I/O error on {}", p.display())).unwrap_or_else(|| "I/O error".into())
+}
+
+fn path_suffix(path: &Option<PathBuf>) -> String {
+    path.as_ref().map(|p| format!(" in {}", p.display())).unwrap_or_default()
+}
+
+fn offset_suffix(offset: &Option<u64>) -> String {
+    offset.map(|o| format!(" at byte {}", o)).unwrap_or_default()
+}
@@ -0,0 +1,130 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::cancel::PartFile;
+use crate::error::{PathContext, Result};
+
+/// Why an archive entry was refused
+#[derive(Debug, thiserror::Error)]
+pub enum UnsafeEntry {
+    #[error("empty entry name")]
+    Empty,
+    #[error("entry {0:?} contains a NUL byte")]
+    Nul(String),
+    #[error("entry {0:?} is an absolute path")]
+    Absolute(String),
+    #[error("entry {0:?} escapes the destination")]
+    Escapes(String),
+    #[error("entry {entry:?} passes through symlink {}", path.display())]
+    Symlink { entry: String, path: PathBuf },
+    #[error("entry {entry:?} would replace {}, which is not a regular file", path.display())]
+    NotRegularFile { entry: String, path: PathBuf },
+}
+
+/// Turn an archive entry name into a plain relative path, or refuse it
+///
+/// `\` is treated as a separator too, `.` components are dropped and `a/../b` collapses
+/// to `b`. Absolute paths, drive prefixes and any `..` reaching above the root are refused.
+pub fn normalize_entry(entry: &str) -> Result<PathBuf, UnsafeEntry> {
+    if entry.contains('\0') {
+        return Err(UnsafeEntry::Nul(entry.to_string()));
+    }
+    let unified = entry.replace('\\', "/");
+    let path = Path::new(&unified);
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return Err(UnsafeEntry::Escapes(entry.to_string()));
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return Err(UnsafeEntry::Absolute(entry.to_string())),
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        return Err(UnsafeEntry::Empty);
+    }
+    Ok(normalized)
+}
+
+/// Destination directory that archive entries are written into
+///
+/// Files are only ever created below the root: parents are created one component at a time
+/// and symlinks met on the way are refused, so a link planted in the destination (or by an
+/// earlier entry) cannot redirect a later write outside it.
+pub struct SafeDestination {
+    root: PathBuf,
+}
+
+impl SafeDestination {
+    /// Use `root` as destination, creating it if needed
+    pub fn new(root: &Path) -> Result<Self> {
+        fs::create_dir_all(root).at(root)?;
+        let root = root.canonicalize().at(root)?;
+        Ok(SafeDestination { root })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Where `entry` would be written, without touching the filesystem
+    pub fn resolve(&self, entry: &str) -> Result<PathBuf, UnsafeEntry> {
+        Ok(self.root.join(normalize_entry(entry)?))
+    }
+
+    /// Create (or replace) the regular file for `entry`
+    pub fn create_file(&self, entry: &str) -> Result<File> {
+        let rel = normalize_entry(entry)?;
+        let mut path = self.root.clone();
+        let mut components = rel.components().peekable();
+        while let Some(component) = components.next() {
+            path.push(component);
+            let existing = match fs::symlink_metadata(&path) {
+                Ok(meta) => Some(meta.file_type()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e).at(&path),
+            };
+            if existing.is_some_and(|t| t.is_symlink()) {
+                return Err(UnsafeEntry::Symlink { entry: entry.to_string(), path }.into());
+            }
+            if components.peek().is_some() {
+                if existing.is_none() {
+                    fs::create_dir(&path).at(&path)?;
+                }
+                continue;
+            }
+            match existing {
+                Some(t) if t.is_file() => fs::remove_file(&path).at(&path)?,
+                Some(_) => return Err(UnsafeEntry::NotRegularFile { entry: entry.to_string(), path }.into()),
+                None => {}
+            }
+        }
+        // create_new never follows a symlink that appeared since the checks above
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .at(&path)
+    }
+
+    /// Rename the finished `part`, created with `create_file` in the same directory, to `entry`
+    ///
+    /// What `create_file` refuses to replace is refused here as well. Returns the seconds
+    /// spent syncing the directory.
+    pub(crate) fn commit_file(&self, entry: &str, part: PartFile) -> Result<f64> {
+        let path = self.resolve(entry)?;
+        match fs::symlink_metadata(&path) {
+            Ok(meta) if meta.file_type().is_symlink() => Err(UnsafeEntry::Symlink { entry: entry.to_string(), path }.into()),
+            Ok(meta) if !meta.is_file() => Err(UnsafeEntry::NotRegularFile { entry: entry.to_string(), path }.into()),
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e).at(&path),
+            _ => part.commit(&path).at(&path),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use walkdir::WalkDir;
+
+use crate::durable;
+use crate::error::{CompressorError, PathContext, Result};
+use crate::manifest::{self, ManifestEntry};
+
+/// Journal of the entries a folder job has finished, kept next to its output until it completes
+pub const JOURNAL_FILE: &str = "compress-journal.tsv";
+
+const JOURNAL_MAGIC: &str = "# rustzip journal v1";
+
+/// Append-only journal, one index line per finished entry
+pub(crate) struct Journal {
+    file: File,
+    path: PathBuf,
+}
+
+impl Journal {
+    /// Start a fresh journal for a job writing `extension` artifacts at `level`
+    pub fn create(output: &Path, extension: &str, level: i32) -> Result<Self> {
+        fs::create_dir_all(output).at(output)?;
+        let path = output.join(JOURNAL_FILE);
+        let mut file = File::create(&path).at(&path)?;
+        writeln!(file, "{}\t{}\t{}", JOURNAL_MAGIC, extension, level).at(&path)?;
+        durable::sync_file(&file).at(&path)?;
+        durable::sync_dir(output).at(output)?;
+        Ok(Journal { file, path })
+    }
+
+    /// Keep appending to the journal of an interrupted job
+    ///
+    /// A line the crash cut short is dropped first; appending after it would glue the next
+    /// entry onto it and leave a journal the following resume cannot read.
+    pub fn reopen(output: &Path) -> Result<Self> {
+        let path = output.join(JOURNAL_FILE);
+        let file = OpenOptions::new().append(true).open(&path).at(&path)?;
+        let text = fs::read(&path).at(&path)?;
+        let complete = text.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        if complete < text.len() {
+            file.set_len(complete as u64).at(&path)?;
+            durable::sync_file(&file).at(&path)?;
+        }
+        Ok(Journal { file, path })
+    }
+
+    /// Record a finished entry, synced so it survives a crash; returns the seconds spent syncing
+    pub fn record(&mut self, entry: &ManifestEntry) -> Result<f64> {
+        writeln!(self.file, "{}", manifest::index_line(entry)).at(&self.path)?;
+        durable::sync_file(&self.file).at(&self.path)
+    }
+
+    /// Drop the journal once the job's manifest has been written
+    pub fn finish(self) -> Result<()> {
+        drop(self.file);
+        fs::remove_file(&self.path).at(&self.path)
+    }
+}
+
+/// Entries finished by an interrupted job, keyed by source, or `None` without a journal
+///
+/// Fails if the journal was written for a different format or level, since resuming would
+/// mix artifacts that an uninterrupted run would never produce.
+pub(crate) fn load(output: &Path, extension: &str, level: i32) -> Result<Option<BTreeMap<String, ManifestEntry>>> {
+    let path = output.join(JOURNAL_FILE);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).at(&path),
+    };
+    let mut lines = text.split_inclusive('\n');
+    let header = lines
+        .next()
+        .and_then(|line| line.trim_end_matches('\n').strip_prefix(JOURNAL_MAGIC))
+        .ok_or_else(|| CompressorError::corrupt(&path, "not a job journal"))?;
+    let expected = format!("\t{}\t{}", extension, level);
+    if header != expected {
+        return Err(CompressorError::InvalidInput(format!(
+            "{} belongs to a job with different settings ({}); rerun with those or without --resume",
+            path.display(),
+            header.trim().replace('\t', " level ")
+        )));
+    }
+    let mut done = BTreeMap::new();
+    // a last line without its newline was cut short by the crash that interrupted the job
+    for line in lines.filter_map(|line| line.strip_suffix('\n')) {
+        let entry = manifest::parse_index_line(line).map_err(|reason| CompressorError::corrupt(&path, reason))?;
+        done.insert(entry.source.clone(), entry);
+    }
+    Ok(Some(done))
+}
+
+/// Delete temporary `<name>.<ext>.part` files left in `output` by an interrupted job
+///
+/// Other `.part` files are not ours, which matters when compressing in place.
+pub(crate) fn discard_part_files(output: &Path, ext: &str) -> Result<usize> {
+    let suffix = format!(".{}.part", ext);
+    let mut removed = 0;
+    for entry in WalkDir::new(output).into_iter().filter_map(Result::ok) {
+        if entry.file_type().is_file() && entry.file_name().to_string_lossy().ends_with(&suffix) {
+            fs::remove_file(entry.path()).at(entry.path())?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
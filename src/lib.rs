@@ -1 +1,23 @@
+#[cfg(feature = "async")]
+pub mod async_io;
+pub mod buffer;
+pub mod cancel;
 pub mod compressor;
+pub mod crypto;
+pub mod dedup;
+mod durable;
+pub mod error;
+pub mod extract;
+pub mod journal;
+pub mod limits;
+pub mod manifest;
+pub mod options;
+pub mod overwrite;
+pub mod parity;
+pub mod progress;
+pub mod recipients;
+pub mod registry;
+pub mod salvage;
+pub mod signing;
+pub mod source;
+pub mod stream;
@@ -0,0 +1,100 @@
+use std::{
+    cell::Cell,
+    io::{self, Read, Write},
+};
+
+/// Bounds on what a single decompression may produce, for untrusted inputs
+///
+/// Every limit is enforced while streaming, so a crafted input is stopped as soon as it
+/// crosses one rather than after the damage is done.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DecompressLimits {
+    /// Maximum number of decompressed bytes
+    pub max_output_bytes: Option<u64>,
+    /// Maximum decompressed bytes per compressed byte read so far
+    pub max_ratio: Option<f64>,
+    /// Maximum zstd window as a power of two (zstd's own default is 27, i.e. 128 MiB)
+    pub max_window_log: Option<u32>,
+}
+
+impl DecompressLimits {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_output_bytes.is_none() && self.max_ratio.is_none() && self.max_window_log.is_none()
+    }
+}
+
+/// A decompression was stopped because it crossed one of its `DecompressLimits`
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum LimitError {
+    #[error("decompressed output exceeds the limit of {limit} bytes")]
+    OutputTooLarge { limit: u64 },
+    #[error("expansion ratio exceeds {limit}:1 ({written} bytes from {read} compressed bytes)")]
+    RatioExceeded { limit: f64, written: u64, read: u64 },
+    #[error("frame needs a window larger than the limit of 2^{max_window_log} bytes")]
+    WindowTooLarge { max_window_log: u32 },
+}
+
+/// Counts the compressed bytes pulled through it
+pub(crate) struct CountingReader<'a, R: Read> {
+    pub inner: R,
+    pub count: &'a Cell<u64>,
+}
+
+impl<R: Read> Read for CountingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.set(self.count.get() + n as u64);
+        Ok(n)
+    }
+}
+
+/// Fails the write that would cross the output or ratio limit, remembering why
+pub(crate) struct LimitedWriter<'a, W: Write> {
+    pub inner: W,
+    pub written: u64,
+    pub limits: DecompressLimits,
+    /// Compressed bytes read so far, shared with the `CountingReader` feeding the decoder
+    pub read: &'a Cell<u64>,
+    pub tripped: Option<LimitError>,
+}
+
+impl<W: Write> LimitedWriter<'_, W> {
+    fn check(&self, written: u64) -> Option<LimitError> {
+        if let Some(limit) = self.limits.max_output_bytes.filter(|limit| written > *limit) {
+            return Some(LimitError::OutputTooLarge { limit });
+        }
+        let read = self.read.get();
+        match self.limits.max_ratio {
+            Some(limit) if written as f64 > limit * read.max(1) as f64 => {
+                Some(LimitError::RatioExceeded { limit, written, read })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<W: Write> Write for LimitedWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(err) = self.check(self.written + buf.len() as u64) {
+            self.tripped = Some(err.clone());
+            return Err(io::Error::other(err));
+        }
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Whether `err` is zstd refusing a frame because of `window_log_max`
+///
+/// The zstd crate turns error codes into I/O errors carrying only the code's name, so this
+/// compares against the name zstd gives `ZSTD_error_frameParameter_windowTooLarge`.
+pub(crate) fn is_window_error(err: &io::Error) -> bool {
+    use zstd::zstd_safe::{self, zstd_sys::ZSTD_ErrorCode};
+    // zstd returns error `e` as the size_t `-e`
+    let code = 0usize.wrapping_sub(ZSTD_ErrorCode::ZSTD_error_frameParameter_windowTooLarge as usize);
+    err.get_ref().is_some_and(|inner| inner.to_string() == zstd_safe::get_error_name(code))
+}
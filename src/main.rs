@@ -1,36 +1,512 @@
 mod cli;
-mod compressor;
 
 use cli::CliArgs;
-use compressor::{Compressor, ZstdCompressor, GzipCompressor, Lz4Compressor};
-use std::path::Path;
+use parallel_compressor::cancel;
+use parallel_compressor::compressor::{self, ChangeSummary, Compressor, DecompressReport, Stats};
+use parallel_compressor::crypto::{self, EncryptedCompressor, Kdf, Secret};
+use parallel_compressor::dedup::{self, DedupStats};
+use parallel_compressor::error::CompressorError;
+use parallel_compressor::extract;
+use parallel_compressor::limits::DecompressLimits;
+use parallel_compressor::manifest::{self, Manifest, VerifyReport};
+use parallel_compressor::options::{CompressMode, CompressOptions, DecompressOptions, Filters};
+use parallel_compressor::overwrite::{self, OverwritePolicy};
+use parallel_compressor::parity;
+use parallel_compressor::progress::IndicatifProgress;
+use parallel_compressor::recipients::{self, RecipientCompressor};
+use parallel_compressor::registry::{self, Format};
+use parallel_compressor::salvage::{self, SalvageReport};
+use parallel_compressor::signing;
+use parallel_compressor::source::SourcePolicy;
+use std::path::{Path, PathBuf};
 use clap::Parser;
+use colored::*;
 
-fn main() -> anyhow::Result<()> {
+fn main() {
     let args = CliArgs::parse();
+    // first Ctrl-C stops the job cleanly, a second one exits at once
+    let _ = ctrlc::set_handler(|| {
+        if cancel::global().is_cancelled() {
+            std::process::exit(130);
+        }
+        cancel::global().cancel();
+        eprintln!("\n{}", "Cancelling, cleaning up... (press Ctrl-C again to abort)".yellow());
+    });
+    if let Err(err) = run(args) {
+        if matches!(err.downcast_ref::<CompressorError>(), Some(CompressorError::Cancelled)) {
+            eprintln!("{}", "Cancelled.".yellow());
+            std::process::exit(130);
+        }
+        eprintln!("Error: {:?}", err);
+        std::process::exit(1);
+    }
+}
 
+fn run(args: CliArgs) -> anyhow::Result<()> {
     match args.subcommand {
-        cli::SubCommand::Compress { input, output, threads, level, format } => {
-            let format = format.to_lowercase();
-            let compressor: Box<dyn Compressor> = match format.as_str() {
-                "zst" => Box::new(ZstdCompressor),
-                "gz" => Box::new(GzipCompressor),
-                "lz4" => Box::new(Lz4Compressor),
-                _ => anyhow::bail!("Unknown format: {}", format),
+        cli::SubCommand::Compress { input, output, in_place, threads, level, clamp_level, format, incremental, delete, dedup, encrypt, key_file, recipient, recipients_file, sign_key, parity: redundancy, resume, force, no_clobber: _, skip_existing, rm_source, keep: _, verify, include, exclude } => {
+            let (input, output) = match in_place {
+                // a folder is its own output root; a single file is compressed beside itself
+                Some(path) if Path::new(&path).is_dir() => (path.clone(), path),
+                Some(path) => {
+                    let parent = Path::new(&path).parent().unwrap_or(Path::new("")).to_string_lossy().to_string();
+                    (path, if parent.is_empty() { ".".to_string() } else { parent })
+                }
+                None => (input.expect("clap requires --input"), output.expect("clap requires --output")),
             };
-            compressor::compress_path_with(&input, &output, threads, level, &*compressor)?;
-        }
-        cli::SubCommand::Decompress {input, output } => {
-            let ext = Path::new(&input).extension().and_then(|e| e.to_str()).unwrap_or("");
-            let compressor: Box<dyn Compressor> = match ext {
-                "zst" => Box::new(ZstdCompressor),
-                "gz" => Box::new(GzipCompressor),
-                "lz4" => Box::new(Lz4Compressor),
-                _ => anyhow::bail!("Unknown file extension: {}", ext),
+            let overwrite = overwrite_policy(force, skip_existing);
+            let source_policy = source_policy(rm_source, verify);
+            let sign_key = match sign_key {
+                Some(path) if Path::new(&input).is_dir() => Some(signing::read_signing_key(Path::new(&path))?),
+                Some(_) => anyhow::bail!("--sign-key needs a folder input (single files have no manifest)"),
+                None => None,
+            };
+            let level = pick_level(format, level, clamp_level)?;
+            let compressor = format.create();
+            let compressor: Box<dyn Compressor> = if let Some(key_file) = key_file {
+                Box::new(EncryptedCompressor::new(compressor, Secret::from_key_file(Path::new(&key_file))?))
+            } else if !recipient.is_empty() || !recipients_file.is_empty() {
+                let mut keys = recipient.iter().map(|r| recipients::parse_recipient(r)).collect::<Result<Vec<_>, _>>()?;
+                for file in &recipients_file {
+                    keys.extend(recipients::read_recipients_file(Path::new(file))?);
+                }
+                Box::new(RecipientCompressor::for_recipients(compressor, keys))
+            } else if encrypt {
+                Box::new(EncryptedCompressor::new(compressor, Secret::passphrase(read_passphrase(true)?)))
+            } else {
+                compressor
+            };
+            if dedup {
+                let stats = dedup::compress_dedup(&input, &output, level, &*compressor, &IndicatifProgress::new(), cancel::global())?;
+                print_dedup_stats(&stats);
+            } else {
+                let mode = match (incremental, resume) {
+                    (true, _) => CompressMode::Incremental { delete },
+                    (_, true) => CompressMode::Resume,
+                    _ => CompressMode::Full,
+                };
+                let filters = include.into_iter().fold(Filters::new(), Filters::include);
+                let filters = exclude.into_iter().fold(filters, Filters::exclude);
+                let progress = IndicatifProgress::new();
+                let report = CompressOptions::new()
+                    .format(&*compressor)
+                    .level(level)
+                    .threads(threads)
+                    .overwrite(overwrite)
+                    .mode(mode)
+                    .filters(filters)
+                    .remove_source(source_policy)
+                    .progress(&progress)
+                    .cancel(cancel::global())
+                    .run(&input, &output)
+                    .map_err(|err| report_cancelled(err, Path::new(&output)))?;
+                match (&report.changes, mode) {
+                    (Some(summary), _) => print_change_summary(summary),
+                    (None, CompressMode::Resume) => {
+                        println!("Resumed: {} files finished by the interrupted run were kept.", report.stats.skipped_files);
+                    }
+                    (None, _) => print_compress_stats(&report.stats),
+                }
+                if source_policy != SourcePolicy::Keep && Path::new(&input).is_dir() {
+                    println!("Removed {} source files.", report.removed_sources);
+                }
+            }
+            if let Some(redundancy) = redundancy {
+                let protected = if Path::new(&input).is_file() {
+                    compressor::single_output_path(Path::new(&input), Path::new(&output), &*compressor)
+                } else {
+                    Path::new(&output).to_path_buf()
+                };
+                let written = parity::protect_path(&protected, redundancy)?;
+                println!("Recovery data ({}% redundancy) written for {} files.", redundancy, written);
+            }
+            if let Some(key) = sign_key {
+                signing::sign_manifest(Path::new(&output), &key)?;
+                println!("Manifest signed: {}", Path::new(&output).join(signing::SIGNATURE_FILE).display());
+            }
+        }
+        cli::SubCommand::Decompress {
+            input,
+            output,
+            in_place,
+            key_file,
+            identity,
+            trusted_key,
+            salvage,
+            max_output,
+            max_ratio,
+            max_window_log,
+            force,
+            no_clobber: _,
+            skip_existing,
+            rm_source,
+            keep: _,
+            verify,
+        } => {
+            let overwrite = overwrite_policy(force, skip_existing);
+            let source_policy = source_policy(rm_source, verify);
+            let (input, output) = match in_place {
+                Some(path) if Path::new(&path).is_dir() => (path.clone(), path),
+                Some(path) => {
+                    let output = restored_name(Path::new(&path))?.to_string_lossy().to_string();
+                    (path, output)
+                }
+                None => (input.expect("clap requires --input"), output.expect("clap requires --output")),
             };
-            compressor::decompress_file_with(&input, &output, &*compressor)?;
+            let input_dir = Path::new(&input).is_dir();
+            let trusted_key = trusted_key.map(|path| signing::read_verifying_key(Path::new(&path))).transpose()?;
+            let dedup_archive = dedup::is_dedup_archive(Path::new(&input));
+            if dedup_archive && source_policy != SourcePolicy::Keep {
+                anyhow::bail!("--rm-source is not supported for dedup archives");
+            }
+            let single_file = !input_dir && !dedup_archive;
+            if salvage && !single_file {
+                anyhow::bail!("--salvage is only supported for single compressed files");
+            }
+            if single_file {
+                // checked before asking for a passphrase the run would not need
+                let job = (Path::new(&input).to_path_buf(), Path::new(&output).to_path_buf());
+                if !overwrite::plan_outputs(vec![job], overwrite)?.skipped.is_empty() {
+                    println!("Skipped: {} already exists.", output);
+                    return Ok(());
+                }
+            }
+            if salvage {
+                if let Some(key) = &trusted_key {
+                    signing::verify_artifact(Path::new(&input), key)?;
+                }
+                let compressor = decompressor_for(Path::new(&input), key_file, &identity)?;
+                let report = salvage::salvage_file_with(&input, &output, &*compressor, cancel::global())?;
+                print_salvage_report(&report);
+                if !report.is_complete() {
+                    anyhow::bail!("Salvage incomplete: {} compressed bytes lost", report.lost_bytes());
+                }
+                return Ok(());
+            }
+            // dedup archives name their own compressor; encrypted inputs need theirs built here
+            let compressor = if dedup_archive {
+                None
+            } else if input_dir {
+                // one run writes every artifact with the same compressor
+                match Manifest::load(Path::new(&input))?.entries.values().next() {
+                    Some(entry) => {
+                        let artifact = Path::new(&input).join(extract::normalize_entry(&entry.output)?);
+                        Some(decompressor_for(&artifact, key_file, &identity)?)
+                    }
+                    None => None,
+                }
+            } else {
+                Some(decompressor_for(Path::new(&input), key_file, &identity)?)
+            };
+            let progress = IndicatifProgress::new();
+            let mut options = DecompressOptions::new()
+                .limits(DecompressLimits { max_output_bytes: max_output, max_ratio, max_window_log })
+                .overwrite(overwrite)
+                .remove_source(source_policy)
+                .progress(&progress)
+                .cancel(cancel::global());
+            if let Some(compressor) = &compressor {
+                options = options.format(&**compressor);
+            }
+            if let Some(key) = &trusted_key {
+                options = options.trusted_key(key);
+            }
+            let report = options.run(&input, &output)?;
+            if trusted_key.is_some() {
+                println!("Verified {} against the signed manifest.", input);
+            }
+            if dedup_archive {
+                if report.skipped_files > 0 {
+                    println!("Restored {} files from dedup archive ({} existing files skipped).", report.files, report.skipped_files);
+                } else {
+                    println!("Restored {} files from dedup archive.", report.files);
+                }
+            } else if input_dir {
+                if report.skipped_files > 0 {
+                    println!("Restored {} files into {} ({} existing files skipped).", report.files, output, report.skipped_files);
+                } else {
+                    println!("Restored {} files into {}.", report.files, output);
+                }
+                if source_policy != SourcePolicy::Keep {
+                    println!("Removed {} compressed files.", report.removed_sources);
+                }
+            } else {
+                print_decompress_report(&report);
+            }
+        }
+        cli::SubCommand::Verify { input, trusted_key } => {
+            let dir = Path::new(&input);
+            let listing = match trusted_key {
+                Some(key) => {
+                    let listing = signing::read_signed_listing(dir, &signing::read_verifying_key(Path::new(&key))?)?;
+                    println!("{}", "Manifest signature is valid.".green());
+                    listing
+                }
+                None => manifest::read_listing(dir)?,
+            };
+            let report = manifest::check_listing(dir, &listing)?;
+            print_verify_report(&report);
+            if !report.is_ok() {
+                anyhow::bail!("Verification failed");
+            }
+        }
+        cli::SubCommand::Repair { input } => {
+            let reports = parity::repair_path(Path::new(&input))?;
+            for (artifact, report) in &reports {
+                if report.was_intact() {
+                    println!("{} {}", "OK      ".green(), artifact.display());
+                } else {
+                    println!(
+                        "{} {} ({} damaged blocks rebuilt{})",
+                        "REPAIRED".yellow().bold(),
+                        artifact.display(),
+                        report.damaged_blocks,
+                        if report.resized { ", length restored" } else { "" }
+                    );
+                }
+                if report.damaged_parity > 0 {
+                    println!("         {} damaged recovery blocks, consider regenerating with --parity", report.damaged_parity);
+                }
+            }
+            if reports.is_empty() {
+                println!("No .par recovery files found under {}", input);
+            }
+        }
+        cli::SubCommand::Keygen { output } => {
+            let public = signing::generate_keypair(Path::new(&output))?;
+            println!("Secret key written to {}", output);
+            println!("Public key written to {}", public.display());
         }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+fn overwrite_policy(force: bool, skip_existing: bool) -> OverwritePolicy {
+    match (force, skip_existing) {
+        (true, _) => OverwritePolicy::Force,
+        (_, true) => OverwritePolicy::SkipExisting,
+        _ => OverwritePolicy::NoClobber,
+    }
+}
+
+/// The level to compress at: `level` checked against `format`, or moved into its range with
+/// a warning under `--clamp-level`, or the format's default
+fn pick_level(format: &Format, level: Option<i32>, clamp: bool) -> anyhow::Result<i32> {
+    let Some(level) = level else {
+        return Ok(format.compressor().default_level());
+    };
+    let err = match format.check_level(level) {
+        Ok(level) => return Ok(level),
+        Err(err) => err,
+    };
+    let clamped = format.compressor().clamp_level(level);
+    match (format.compressor().level_range(), clamp) {
+        (Some(_), false) => anyhow::bail!("{} (pass --clamp-level to use the nearest supported level)", err),
+        (None, false) => anyhow::bail!("{}, drop --level", err),
+        (Some(_), true) => eprintln!("{}", format!("Warning: {}; using level {}.", err, clamped).yellow()),
+        (None, true) => eprintln!("{}", format!("Warning: {}; ignoring --level.", err).yellow()),
+    }
+    Ok(clamped)
+}
+
+fn source_policy(rm_source: bool, verify: bool) -> SourcePolicy {
+    match (rm_source, verify) {
+        (true, true) => SourcePolicy::RemoveVerified,
+        (true, false) => SourcePolicy::Remove,
+        _ => SourcePolicy::Keep,
+    }
+}
+
+/// Where an in-place decompression of `input` writes: its name without the format extension
+/// (and the `.enc` / `.age` one outside it)
+fn restored_name(input: &Path) -> anyhow::Result<PathBuf> {
+    let outer = input.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let inner = match outer == crypto::ENCRYPTED_EXTENSION || outer == recipients::AGE_EXTENSION {
+        true => input.with_extension(""),
+        false => input.to_path_buf(),
+    };
+    let ext = inner.extension().and_then(|e| e.to_str()).unwrap_or("");
+    if registry::global().for_extension(ext).is_none() {
+        anyhow::bail!("{} has no compressed-file extension to strip", input.display());
+    }
+    Ok(inner.with_extension(""))
+}
+
+/// Pick the compressor for `input` from its extensions, unwrapping `.enc` / `.age` first
+fn decompressor_for(input: &Path, key_file: Option<String>, identities: &[String]) -> anyhow::Result<Box<dyn Compressor>> {
+    let outer = input.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let encrypted = outer == crypto::ENCRYPTED_EXTENSION || outer == recipients::AGE_EXTENSION;
+    let inner_path = if encrypted { Path::new(input.file_stem().unwrap_or_default()) } else { input };
+    let ext = inner_path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let compressor = registry::global().for_extension(ext).map(Format::create)
+        .ok_or_else(|| anyhow::anyhow!("Unknown file extension: {}", ext))?;
+    if outer == crypto::ENCRYPTED_EXTENSION {
+        let secret = match (crypto::read_kdf(input)?, key_file) {
+            (Kdf::KeyFile, Some(key_file)) => Secret::from_key_file(Path::new(&key_file))?,
+            (Kdf::KeyFile, None) => anyhow::bail!("{} was encrypted with a key file, pass --key-file", input.display()),
+            (Kdf::Argon2id { .. }, _) => Secret::passphrase(read_passphrase(false)?),
+        };
+        Ok(Box::new(EncryptedCompressor::new(compressor, secret)))
+    } else if outer == recipients::AGE_EXTENSION {
+        if identities.is_empty() {
+            anyhow::bail!("{} is encrypted to age recipients, pass --identity", input.display());
+        }
+        let mut keys = Vec::new();
+        for file in identities {
+            keys.extend(recipients::read_identity_file(Path::new(file))?);
+        }
+        Ok(Box::new(RecipientCompressor::for_identities(compressor, keys)))
+    } else {
+        Ok(compressor)
+    }
+}
+
+/// Passphrase from `RUSTZIP_PASSPHRASE`, or prompted on the terminal
+fn read_passphrase(confirm: bool) -> anyhow::Result<String> {
+    if let Ok(passphrase) = std::env::var("RUSTZIP_PASSPHRASE") {
+        return Ok(passphrase);
+    }
+    let passphrase = rpassword::prompt_password("Passphrase: ")?;
+    if passphrase.is_empty() {
+        anyhow::bail!("Passphrase must not be empty");
+    }
+    if confirm && rpassword::prompt_password("Confirm passphrase: ")? != passphrase {
+        anyhow::bail!("Passphrases do not match");
+    }
+    Ok(passphrase)
+}
+
+/// Say how far a cancelled folder job got, from the manifest it saved on the way out
+fn report_cancelled(err: CompressorError, output: &Path) -> CompressorError {
+    if !matches!(err, CompressorError::Cancelled) || !output.join(manifest::INDEX_FILE).is_file() {
+        return err;
+    }
+    if let Ok(saved) = Manifest::load(output) {
+        let done: u64 = saved.entries.values().map(|e| e.size).sum();
+        println!("\n{}", "⏹ Cancelled".bold().yellow());
+        println!("{} {} files ({:.2} MB)", "Completed:        ".blue(), saved.entries.len(), done as f64 / 1_048_576.0);
+        println!("{} {}", "Manifest:         ".blue(), output.join(manifest::MANIFEST_FILE).display());
+    }
+    err
+}
+
+fn print_verify_report(report: &VerifyReport) {
+    for file in &report.mismatched {
+        println!("{} {}", "MISMATCH".red().bold(), file);
+    }
+    for file in &report.missing {
+        println!("{} {}", "MISSING ".red().bold(), file);
+    }
+    println!(
+        "{} verified, {} mismatched, {} missing",
+        report.verified.len(),
+        report.mismatched.len(),
+        report.missing.len()
+    );
+}
+
+fn print_change_summary(summary: &ChangeSummary) {
+    println!("\n{}", "🔁 Incremental compression summary".bold().green());
+    println!("{} {}", "Added:    ".blue(), summary.added.len());
+    println!("{} {}", "Modified: ".yellow(), summary.modified.len());
+    if summary.pruned {
+        println!("{} {}", "Removed:  ".red(), summary.removed.len());
+    } else {
+        println!(
+            "{} {} (outputs kept, pass --delete to remove them)",
+            "Removed:  ".red(),
+            summary.removed.len()
+        );
+    }
+    println!("{} {}", "Unchanged:".dimmed(), summary.unchanged.len());
+}
+
+fn print_salvage_report(report: &SalvageReport) {
+    println!("\n{}", "🩹 Salvage summary".bold().yellow());
+    println!(
+        "{} {:.2} MB",
+        "Recovered:        ".blue(),
+        report.recovered_bytes as f64 / 1_048_576.0
+    );
+    println!("{} {}", "Decode segments:  ".blue(), report.segments);
+    for range in &report.lost_ranges {
+        println!("{} bytes {}..{} of the input", "Lost:             ".red(), range.start, range.end);
+    }
+    if report.truncated {
+        println!("{}", "Input ends mid-frame; the tail of the last frame is missing.".red());
+    }
+    if report.is_complete() {
+        println!("{}", "No damage found.".green());
+    }
+}
+
+fn print_dedup_stats(stats: &DedupStats) {
+    println!("\n{}", "🧩 Dedup archive summary".bold().green());
+    println!("{} {}", "Files:            ".blue(), stats.files);
+    println!(
+        "{} {} ({} unique)",
+        "Chunks:           ".blue(),
+        stats.total_chunks,
+        stats.unique_chunks
+    );
+    println!(
+        "{} {:.2} MB",
+        "Total original:   ".blue(),
+        stats.original_size as f64 / 1_048_576.0
+    );
+    println!(
+        "{} {:.2} MB",
+        "Stored:           ".blue(),
+        stats.compressed_size as f64 / 1_048_576.0
+    );
+    println!("{} {:.2}%", "Dedup ratio:      ".yellow(), stats.dedup_ratio() * 100.0);
+    println!("{} {:.2}%", "Compression ratio:".yellow(), stats.compression_ratio() * 100.0);
+    println!("{} {:.2} s", "Total time:       ".magenta(), stats.duration_secs);
+    println!("{} {:.2} s", "Fsync time:       ".magenta(), stats.sync_secs);
+}
+
+fn print_compress_stats(stats: &Stats) {
+    println!("\n{}", "📦 Compression summary".bold().green());
+    println!(
+        "{} {:.2} MB",
+        "Total original:   ".blue(),
+        stats.original_size as f64 / 1_048_576.0
+    );
+    println!(
+        "{} {:.2} MB",
+        "Total compressed: ".blue(),
+        stats.compressed_size as f64 / 1_048_576.0
+    );
+    let ratio = stats.compressed_size as f64 / stats.original_size.max(1) as f64;
+    println!("{} {:.2}%", "Overall ratio:    ".yellow(), ratio * 100.0);
+    println!("{} {:.2} s", "Total time:       ".magenta(), stats.duration_secs);
+    println!(
+        "{} {:.2} s ({:.1}% of total)",
+        "Fsync time:       ".magenta(),
+        stats.sync_secs,
+        stats.sync_secs / stats.duration_secs.max(1e-9) * 100.0
+    );
+    if stats.skipped_files > 0 {
+        println!("{} {} (output already existed)", "Skipped files:    ".yellow(), stats.skipped_files);
+    }
+}
+
+fn print_decompress_report(report: &DecompressReport) {
+    println!("\n{}", "📊 Decompression complete!".bold().green());
+    println!(
+        "{} {:.2} MB",
+        "Compressed size:     ".blue(),
+        report.compressed_size as f64 / 1_048_576.0
+    );
+    println!(
+        "{} {:.2} MB",
+        "Decompressed size:   ".blue(),
+        report.decompressed_size as f64 / 1_048_576.0
+    );
+    let ratio = report.decompressed_size as f64 / report.compressed_size.max(1) as f64;
+    println!("{} {:.2}%", "Expansion ratio:     ".yellow(), ratio * 100.0);
+    println!("{} {:.2} s", "Time taken:          ".magenta(), report.duration_secs);
+    let speed = (report.decompressed_size as f64 / 1_048_576.0) / report.duration_secs.max(1e-9);
+    println!("{} {:.2} MB/s", "Throughput:          ".cyan(), speed);
+}
@@ -0,0 +1,238 @@
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fs,
+    path::Path,
+    time::UNIX_EPOCH,
+};
+
+use crate::durable;
+use crate::error::{CompressorError, PathContext, Result};
+
+/// Plain `hash  file` listing of every compressed artifact
+pub const MANIFEST_FILE: &str = "manifest-sha256.txt";
+
+/// Tab-separated index describing the source each artifact was produced from
+pub const INDEX_FILE: &str = "manifest-index.tsv";
+
+const INDEX_HEADER: &str = "# source_sha256\tsize\tmtime_ns\toutput_sha256\tsource\toutput\tencryption";
+const NO_ENCRYPTION: &str = "none";
+
+/// One compressed artifact and the source file it was produced from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Source path relative to the input root
+    pub source: String,
+    /// Artifact path relative to the output root
+    pub output: String,
+    /// Source size in bytes when it was compressed
+    pub size: u64,
+    /// Source modification time (nanoseconds since the Unix epoch)
+    pub mtime_ns: u64,
+    /// SHA-256 of the original source content
+    pub source_hash: String,
+    /// SHA-256 of the compressed artifact
+    pub hash: String,
+    /// Encryption scheme wrapping the artifact, if any
+    pub encryption: Option<String>,
+}
+
+/// Manifest of a compressed folder, keyed by source path
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub entries: BTreeMap<String, ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn insert(&mut self, entry: ManifestEntry) {
+        self.entries.insert(entry.source.clone(), entry);
+    }
+
+    /// Load the index from `dir`, returning an empty manifest if none was written yet
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(INDEX_FILE);
+        if !path.exists() {
+            return Ok(Manifest::default());
+        }
+        let text = fs::read_to_string(&path).at(&path)?;
+        Self::parse(&text, &path)
+    }
+
+    /// Parse the text of an index read from `path`
+    pub(crate) fn parse(text: &str, path: &Path) -> Result<Self> {
+        let mut manifest = Manifest::default();
+        for (lineno, line) in text.lines().enumerate() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let entry = parse_index_line(line)
+                .map_err(|reason| CompressorError::corrupt(path, format!("line {}: {}", lineno + 1, reason)))?;
+            manifest.insert(entry);
+        }
+        Ok(manifest)
+    }
+
+    /// Write both the SHA-256 listing and the index into `dir`, synced to stable storage
+    ///
+    /// Each file is replaced atomically, so a crash leaves the old or the new version of
+    /// it, never a torn one. Returns the seconds spent syncing.
+    pub fn save(&self, dir: &Path) -> Result<f64> {
+        fs::create_dir_all(dir).at(dir)?;
+        let mut outputs: Vec<&ManifestEntry> = self.entries.values().collect();
+        outputs.sort_by(|a, b| a.output.cmp(&b.output));
+
+        let listing: String = outputs.iter().map(|entry| listing_line(&entry.hash, &entry.output) + "\n").collect();
+        let mut sync_secs = durable::write_atomic(&dir.join(MANIFEST_FILE), listing.as_bytes())?;
+
+        let mut index = format!("{}\n", INDEX_HEADER);
+        for entry in self.entries.values() {
+            index += &index_line(entry);
+            index.push('\n');
+        }
+        sync_secs += durable::write_atomic(&dir.join(INDEX_FILE), index.as_bytes())?;
+        Ok(sync_secs)
+    }
+}
+
+/// One entry in the tab-separated index format
+pub(crate) fn index_line(entry: &ManifestEntry) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        entry.source_hash,
+        entry.size,
+        entry.mtime_ns,
+        entry.hash,
+        escape(&entry.source),
+        escape(&entry.output),
+        entry.encryption.as_deref().unwrap_or(NO_ENCRYPTION)
+    )
+}
+
+/// Parse a line written by `index_line`, or say what is wrong with it
+pub(crate) fn parse_index_line(line: &str) -> std::result::Result<ManifestEntry, String> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    // indexes written before encryption support have no seventh column
+    if fields.len() != 6 && fields.len() != 7 {
+        return Err(format!("expected 6 or 7 fields, found {}", fields.len()));
+    }
+    Ok(ManifestEntry {
+        source_hash: fields[0].to_string(),
+        size: fields[1].parse().map_err(|_| format!("bad size {:?}", fields[1]))?,
+        mtime_ns: fields[2].parse().map_err(|_| format!("bad mtime {:?}", fields[2]))?,
+        hash: fields[3].to_string(),
+        source: unescape(fields[4]),
+        output: unescape(fields[5]),
+        encryption: fields.get(6).filter(|e| **e != NO_ENCRYPTION).map(|e| e.to_string()),
+    })
+}
+
+/// One `hash  file` line of a SHA-256 listing
+///
+/// Like `sha256sum`, a name holding a backslash or line break is escaped and the line
+/// marked with a leading backslash, so the listing stays checkable with `sha256sum -c`.
+pub(crate) fn listing_line(hash: &str, file: &str) -> String {
+    if file.contains(['\\', '\n', '\r']) {
+        format!("\\{}  {}", hash, escape(file))
+    } else {
+        format!("{}  {}", hash, file)
+    }
+}
+
+/// Parse the `hash  file` lines of a SHA-256 listing
+pub fn parse_listing(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .filter_map(|line| match line.strip_prefix('\\') {
+            Some(escaped) => escaped.split_once("  ").map(|(hash, file)| (hash.to_string(), unescape(file))),
+            None => line.split_once("  ").map(|(hash, file)| (hash.to_string(), file.to_string())),
+        })
+        .collect()
+}
+
+/// Backslash-escape the characters that would split an index field or listing line
+pub(crate) fn escape(field: &str) -> Cow<'_, str> {
+    if !field.contains(['\\', '\t', '\n', '\r']) {
+        return Cow::Borrowed(field);
+    }
+    let mut escaped = String::with_capacity(field.len() + 2);
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Undo `escape`; unknown sequences are kept as written, as older indexes stored names raw
+pub(crate) fn unescape(field: &str) -> String {
+    let mut text = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.clone().next() {
+            Some(next @ ('\\' | 't' | 'n' | 'r')) => {
+                chars.next();
+                text.push(match next {
+                    't' => '\t',
+                    'n' => '\n',
+                    'r' => '\r',
+                    _ => '\\',
+                });
+            }
+            _ => text.push('\\'),
+        }
+    }
+    text
+}
+
+/// Read the SHA-256 listing in `dir` without checking any signature
+pub fn read_listing(dir: &Path) -> Result<Vec<(String, String)>> {
+    let path = dir.join(MANIFEST_FILE);
+    let text = fs::read_to_string(&path).at(&path)?;
+    Ok(parse_listing(&text))
+}
+
+/// Outcome of re-hashing every artifact named in a listing
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub verified: Vec<String>,
+    pub mismatched: Vec<String>,
+    pub missing: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.mismatched.is_empty() && self.missing.is_empty()
+    }
+}
+
+/// Hash every artifact of `listing` (relative to `dir`) and compare with the recorded hash
+pub fn check_listing(dir: &Path, listing: &[(String, String)]) -> Result<VerifyReport> {
+    let mut report = VerifyReport::default();
+    for (hash, file) in listing {
+        let path = dir.join(file);
+        if !path.is_file() {
+            report.missing.push(file.clone());
+        } else if crate::compressor::sha256_file(&path)? == *hash {
+            report.verified.push(file.clone());
+        } else {
+            report.mismatched.push(file.clone());
+        }
+    }
+    Ok(report)
+}
+
+/// Modification time of `meta` in nanoseconds since the Unix epoch (0 if unavailable)
+pub fn mtime_ns(meta: &fs::Metadata) -> u64 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
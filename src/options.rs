@@ -0,0 +1,407 @@
+use std::path::Path;
+
+use ed25519_dalek::VerifyingKey;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use rayon::ThreadPool;
+
+use crate::cancel::CancelToken;
+use crate::compressor::{self, CompressReport, Compressor, DecompressReport, ZstdCompressor};
+use crate::dedup;
+use crate::error::{CompressorError, Result};
+use crate::extract;
+use crate::limits::DecompressLimits;
+use crate::manifest;
+use crate::overwrite::{self, OverwritePolicy};
+use crate::progress::{NoProgress, ProgressSink};
+use crate::registry::{self, Format};
+use crate::signing;
+use crate::source::{self, SourcePolicy};
+
+/// How a compress job treats what an earlier run left in its output
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CompressMode {
+    /// Compress every input, handling existing outputs according to the overwrite policy
+    #[default]
+    Full,
+    /// Continue an interrupted folder job, skipping files it already finished
+    ///
+    /// The outputs left by the interrupted job are its own, so they are replaced whatever
+    /// the overwrite policy; `SkipExisting` is refused, since it would keep half a job.
+    Resume,
+    /// Recompress only the files of a folder that changed since the last run; with `delete`,
+    /// the outputs of files that no longer exist are removed
+    Incremental { delete: bool },
+}
+
+/// What is done with the hashes of each file a compress job writes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HashCheck {
+    /// Record source and artifact SHA-256 in the manifest
+    #[default]
+    Record,
+    /// Also decode every artifact once written and compare it with its source hash
+    Verify,
+}
+
+/// Glob patterns choosing which files of a folder input are compressed
+///
+/// Patterns match the path relative to the folder and `*` also matches `/`, so `*.log`
+/// takes logs at any depth. A file is compressed when it matches an include pattern, or
+/// there are none, and no exclude pattern. Single-file inputs are never filtered.
+#[derive(Clone, Debug, Default)]
+pub struct Filters {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl Filters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.include.push(pattern.into());
+        self
+    }
+
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    pub(crate) fn compile(&self) -> Result<FileFilter> {
+        Ok(FileFilter {
+            include: match self.include.is_empty() {
+                true => None,
+                false => Some(glob_set(&self.include)?),
+            },
+            exclude: glob_set(&self.exclude)?,
+        })
+    }
+}
+
+/// Compiled [`Filters`]
+pub(crate) struct FileFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl FileFilter {
+    /// Whether the file at `rel`, relative to the folder input, is compressed
+    pub fn accepts(&self, rel: &Path) -> bool {
+        self.include.as_ref().is_none_or(|set| set.is_match(rel)) && !self.exclude.is_match(rel)
+    }
+}
+
+fn glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern)
+            .map_err(|err| CompressorError::InvalidInput(format!("bad pattern {:?}: {}", pattern, err.kind())))?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|err| CompressorError::InvalidInput(err.to_string()))
+}
+
+/// Settings for compressing a file or folder: chain the setters, then [`run`](Self::run)
+///
+/// Defaults are zstd at its default level on a pool of one thread per core built for the run,
+/// refusing to overwrite existing outputs, keeping sources, reporting no progress and
+/// ignoring cancellation until a token is supplied.
+#[derive(Clone)]
+pub struct CompressOptions<'a> {
+    pub(crate) compressor: &'a dyn Compressor,
+    pub(crate) level: Option<i32>,
+    pub(crate) threads: usize,
+    pub(crate) pool: Option<&'a ThreadPool>,
+    pub(crate) overwrite: OverwritePolicy,
+    pub(crate) mode: CompressMode,
+    pub(crate) hash: HashCheck,
+    pub(crate) filters: Filters,
+    pub(crate) source: SourcePolicy,
+    pub(crate) progress: &'a dyn ProgressSink,
+    pub(crate) cancel: CancelToken,
+}
+
+impl Default for CompressOptions<'_> {
+    fn default() -> Self {
+        CompressOptions {
+            compressor: &ZstdCompressor,
+            level: None,
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            pool: None,
+            overwrite: OverwritePolicy::default(),
+            mode: CompressMode::default(),
+            hash: HashCheck::default(),
+            filters: Filters::default(),
+            source: SourcePolicy::default(),
+            progress: &NoProgress,
+            cancel: CancelToken::new(),
+        }
+    }
+}
+
+impl<'a> CompressOptions<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compressor to write with; wrap one in an encrypting compressor to encrypt
+    pub fn format(mut self, compressor: &'a dyn Compressor) -> Self {
+        self.compressor = compressor;
+        self
+    }
+
+    /// Level to compress at instead of the format's default
+    ///
+    /// The level reaches the format unchanged. gzip and zstd fall back to their nearest level
+    /// and lz4 ignores levels; check one first with [`Format::check_level`](crate::registry::Format::check_level).
+    pub fn level(mut self, level: i32) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    /// Size of the pool built for each run; ignored when a pool is supplied
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Compress files on `pool`, for hosts that share one pool between jobs
+    pub fn thread_pool(mut self, pool: &'a ThreadPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    pub fn overwrite(mut self, overwrite: OverwritePolicy) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    pub fn mode(mut self, mode: CompressMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn hash(mut self, hash: HashCheck) -> Self {
+        self.hash = hash;
+        self
+    }
+
+    pub fn filters(mut self, filters: Filters) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    /// What happens to each input once its output is written
+    pub fn remove_source(mut self, source: SourcePolicy) -> Self {
+        self.source = source;
+        self
+    }
+
+    pub fn progress(mut self, progress: &'a dyn ProgressSink) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Stop the run at the next buffer read once `token` is cancelled
+    ///
+    /// Finished outputs stay, partial ones are removed, and a folder job saves the manifest
+    /// of what it completed before returning `Cancelled`.
+    pub fn cancel(mut self, token: &CancelToken) -> Self {
+        self.cancel = token.clone();
+        self
+    }
+
+    /// Level the job compresses at
+    pub(crate) fn effective_level(&self) -> i32 {
+        self.level.unwrap_or_else(|| self.compressor.default_level())
+    }
+
+    /// Compress `input`, a file or a folder, into `output`
+    ///
+    /// A file is written to `output`, or inside it when `output` is a directory. A folder is
+    /// mirrored under `output` next to a manifest; passing the folder itself as `output`
+    /// compresses it in place.
+    pub fn run(&self, input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<CompressReport> {
+        let (input, output) = (input.as_ref(), output.as_ref());
+        let incremental = matches!(self.mode, CompressMode::Incremental { .. });
+        if incremental && self.source != SourcePolicy::Keep {
+            // the next incremental run compares against the sources, so they must stay
+            return Err(CompressorError::InvalidInput("incremental runs cannot remove their sources".to_string()));
+        }
+        if matches!(self.mode, CompressMode::Resume) && self.overwrite == OverwritePolicy::SkipExisting {
+            return Err(CompressorError::InvalidInput("resumed runs replace the outputs of the job they continue".to_string()));
+        }
+        let job = || match self.mode {
+            CompressMode::Full => compressor::compress_journaled(input, output, self, false),
+            CompressMode::Resume => compressor::compress_journaled(input, output, self, true),
+            CompressMode::Incremental { delete } => compressor::compress_incremental(input, output, self, delete),
+        };
+        let mut report = match self.pool {
+            Some(pool) => pool.install(job)?,
+            None => compressor::build_pool(self.threads)?.install(job)?,
+        };
+        if self.source == SourcePolicy::Keep {
+            return Ok(report);
+        }
+        if input.is_dir() {
+            report.removed_sources = source::replace_compressed_folder(input, output, self.compressor, self.source)?;
+        } else if let [file] = &report.files[..] {
+            source::replace_compressed(input, &file.output, self.compressor, self.source)?;
+            report.removed_sources = 1;
+        }
+        Ok(report)
+    }
+}
+
+/// Settings for restoring a compressed file, compressed folder or dedup archive
+///
+/// Defaults pick the format from the input's extension, apply no limits, refuse to
+/// overwrite existing files, keep the compressed input, report no progress and cannot be
+/// cancelled until a token is supplied.
+#[derive(Clone)]
+pub struct DecompressOptions<'a> {
+    pub(crate) compressor: Option<&'a dyn Compressor>,
+    pub(crate) limits: DecompressLimits,
+    pub(crate) overwrite: OverwritePolicy,
+    pub(crate) source: SourcePolicy,
+    pub(crate) trusted_key: Option<&'a VerifyingKey>,
+    pub(crate) progress: &'a dyn ProgressSink,
+    pub(crate) cancel: CancelToken,
+}
+
+impl Default for DecompressOptions<'_> {
+    fn default() -> Self {
+        DecompressOptions {
+            compressor: None,
+            limits: DecompressLimits::default(),
+            overwrite: OverwritePolicy::default(),
+            source: SourcePolicy::default(),
+            trusted_key: None,
+            progress: &NoProgress,
+            cancel: CancelToken::new(),
+        }
+    }
+}
+
+impl<'a> DecompressOptions<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compressor to read with instead of one chosen by extension; needed for encrypted inputs
+    pub fn format(mut self, compressor: &'a dyn Compressor) -> Self {
+        self.compressor = Some(compressor);
+        self
+    }
+
+    /// Limits on what each restored file may decode to, whether the input is a single file,
+    /// a compressed folder or a dedup archive
+    pub fn limits(mut self, limits: DecompressLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn overwrite(mut self, overwrite: OverwritePolicy) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// What happens to each compressed input once it is restored and checked
+    pub fn remove_source(mut self, source: SourcePolicy) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Only restore what a manifest signed with `key` vouches for
+    ///
+    /// A folder is restored from its signed index alone, after every artifact has been
+    /// checked against the signed listing. A single file must be listed in the signed
+    /// manifest of an enclosing folder, and the bytes it is decoded from are the ones hashed
+    /// against it. Dedup archives carry no signature and are refused.
+    pub fn trusted_key(mut self, key: &'a VerifyingKey) -> Self {
+        self.trusted_key = Some(key);
+        self
+    }
+
+    pub fn progress(mut self, progress: &'a dyn ProgressSink) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Stop the run at the next buffer read once `token` is cancelled, removing the file
+    /// being restored
+    pub fn cancel(mut self, token: &CancelToken) -> Self {
+        self.cancel = token.clone();
+        self
+    }
+
+    /// Restore `input` to `output`
+    ///
+    /// A folder holding a manifest index is restored file by file and each file is checked
+    /// against its source hash. A single file whose output exists is skipped under
+    /// `SkipExisting`, which the report shows as one skipped file.
+    pub fn run(&self, input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<DecompressReport> {
+        let (input, output) = (input.as_ref(), output.as_ref());
+        if dedup::is_dedup_archive(input) {
+            if self.trusted_key.is_some() {
+                return Err(CompressorError::Signature("dedup archives cannot be checked against a signed manifest".to_string()));
+            }
+            if self.source != SourcePolicy::Keep {
+                return Err(CompressorError::InvalidInput("dedup archives cannot be removed after extraction".to_string()));
+            }
+            return dedup::extract_dedup_at(input, output, self);
+        }
+        if input.is_dir() {
+            let manifest = match self.trusted_key {
+                Some(key) => {
+                    let manifest = signing::read_signed_manifest(input, key)?;
+                    let listing: Vec<(String, String)> =
+                        manifest.entries.values().map(|entry| (entry.hash.clone(), entry.output.clone())).collect();
+                    let report = manifest::check_listing(input, &listing)?;
+                    if let Some(file) = report.mismatched.iter().chain(&report.missing).next() {
+                        return Err(CompressorError::Signature(format!("{} does not match the signed manifest", file)));
+                    }
+                    manifest
+                }
+                None => compressor::load_index(input)?,
+            };
+            // one run writes every artifact with the same compressor
+            let compressor = match (self.compressor, manifest.entries.values().next()) {
+                (Some(compressor), _) => compressor,
+                (None, Some(entry)) => detect(&input.join(extract::normalize_entry(&entry.output)?))?,
+                (None, None) => &ZstdCompressor,
+            };
+            let mut report =
+                compressor::restore_folder(input, output, &manifest, compressor, self)?;
+            report.removed_sources = source::replace_decompressed_folder(input, output, &manifest, self.source)?;
+            return Ok(report);
+        }
+        let signed_hash = self.trusted_key.map(|key| signing::signed_hash(input, key)).transpose()?;
+
+        let job = (input.to_path_buf(), output.to_path_buf());
+        if !overwrite::plan_outputs(vec![job], self.overwrite)?.skipped.is_empty() {
+            return Ok(DecompressReport { skipped_files: 1, ..Default::default() });
+        }
+        let compressor = match self.compressor {
+            Some(compressor) => compressor,
+            None => detect(input)?,
+        };
+        let mut report = compressor::decompress_one(input, output, compressor, &self.limits, self.progress, &self.cancel, signed_hash.as_deref())?;
+        if self.source != SourcePolicy::Keep {
+            source::replace_decompressed(input, output, compressor, self.source)?;
+            report.removed_sources = 1;
+        }
+        Ok(report)
+    }
+}
+
+/// Registered compressor for the extension of `path`
+fn detect(path: &Path) -> Result<&'static dyn Compressor> {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    registry::global()
+        .for_extension(ext)
+        .map(Format::compressor)
+        .ok_or_else(|| CompressorError::UnsupportedFormat(ext.to_string()))
+}
@@ -0,0 +1,75 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+/// What to do when an output file already exists
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// Refuse to start if any output exists
+    #[default]
+    NoClobber,
+    /// Replace existing outputs
+    Force,
+    /// Leave existing outputs alone and skip the inputs that would produce them
+    SkipExisting,
+}
+
+/// Planned outputs that cannot all be written
+#[derive(Debug, thiserror::Error)]
+pub enum OutputConflict {
+    #[error("{} and {} would both be written to {}", first.display(), second.display(), output.display())]
+    Collision { first: PathBuf, second: PathBuf, output: PathBuf },
+    #[error("the output of {} would overwrite input file {}", input.display(), output.display())]
+    OverwritesInput { input: PathBuf, output: PathBuf },
+    #[error(
+        "{} output(s) already exist, e.g. {} (use --force to overwrite or --skip-existing)",
+        existing.len(),
+        existing[0].display()
+    )]
+    Exists { existing: Vec<PathBuf> },
+}
+
+/// Outcome of checking a batch of `(input, output)` jobs before any work starts
+#[derive(Debug, Default)]
+pub struct OutputPlan {
+    /// Jobs to run
+    pub jobs: Vec<(PathBuf, PathBuf)>,
+    /// Jobs left out because their output exists and the policy says to skip
+    pub skipped: Vec<(PathBuf, PathBuf)>,
+}
+
+/// Check every planned output up front
+///
+/// Two inputs mapping to one output, or an output landing on one of the inputs, is always
+/// an error. Outputs that already exist are then handled according to `policy`.
+pub fn plan_outputs(jobs: Vec<(PathBuf, PathBuf)>, policy: OverwritePolicy) -> Result<OutputPlan, OutputConflict> {
+    let inputs: HashSet<&PathBuf> = jobs.iter().map(|(input, _)| input).collect();
+    let mut seen: HashMap<&PathBuf, &PathBuf> = HashMap::new();
+    for (input, output) in &jobs {
+        if let Some(first) = seen.insert(output, input) {
+            return Err(OutputConflict::Collision { first: first.clone(), second: input.clone(), output: output.clone() });
+        }
+        if inputs.contains(output) {
+            return Err(OutputConflict::OverwritesInput { input: input.clone(), output: output.clone() });
+        }
+    }
+
+    let mut plan = OutputPlan::default();
+    let mut existing = Vec::new();
+    for (input, output) in jobs {
+        // symlink_metadata: a dangling link in the way still counts as existing
+        if policy != OverwritePolicy::Force && output.symlink_metadata().is_ok() {
+            if policy == OverwritePolicy::SkipExisting {
+                plan.skipped.push((input, output));
+                continue;
+            }
+            existing.push(output.clone());
+        }
+        plan.jobs.push((input, output));
+    }
+    if !existing.is_empty() {
+        return Err(OutputConflict::Exists { existing });
+    }
+    Ok(plan)
+}
@@ -0,0 +1,372 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use sha2::{Digest, Sha256};
+
+use crate::cancel::PartFile;
+use crate::durable;
+use crate::error::{CompressorError, PathContext, Result};
+use crate::manifest;
+
+/// Extension of the recovery file written next to each artifact (`file.zst.par`)
+pub const PARITY_EXTENSION: &str = "par";
+
+const MAGIC: &[u8; 8] = b"RZPAR\x00\x00\x01";
+/// magic + file length + block size + data blocks per stripe + redundancy + artifact hash
+const HEADER_LEN: usize = 8 + 8 + 4 + 4 + 4 + 32;
+const SUM_LEN: usize = 32;
+/// Data blocks per stripe, so the redundancy percentage maps directly to parity blocks
+const DATA_PER_STRIPE: u32 = 100;
+const MIN_BLOCK: u64 = 512;
+const MAX_BLOCK: u64 = 1024 * 1024;
+
+/// Pick a power-of-two block size so a file spans roughly one stripe (512 B to 1 MB)
+fn choose_block_size(file_len: u64) -> u32 {
+    file_len
+        .div_ceil(DATA_PER_STRIPE as u64)
+        .next_power_of_two()
+        .clamp(MIN_BLOCK, MAX_BLOCK) as u32
+}
+
+/// How an artifact is cut into blocks and grouped into Reed-Solomon stripes
+///
+/// Stripes are interleaved: stripe `s` holds blocks `s`, `s + stripes`, `s + 2 * stripes`...
+/// so a long run of damaged blocks is spread over many stripes instead of exhausting one.
+struct Layout {
+    file_len: u64,
+    block_size: u32,
+    data_per_stripe: u32,
+    redundancy: u32,
+    blocks: u64,
+    stripes: u64,
+}
+
+impl Layout {
+    fn new(file_len: u64, block_size: u32, data_per_stripe: u32, redundancy: u32) -> Self {
+        let blocks = file_len.div_ceil(block_size as u64);
+        let stripes = blocks.div_ceil(data_per_stripe as u64);
+        Layout { file_len, block_size, data_per_stripe, redundancy, blocks, stripes }
+    }
+
+    fn data_blocks(&self, stripe: u64) -> usize {
+        (self.blocks - stripe).div_ceil(self.stripes) as usize
+    }
+
+    fn parity_blocks(&self, stripe: u64) -> usize {
+        (self.data_blocks(stripe) * self.redundancy as usize).div_ceil(100).max(1)
+    }
+
+    fn block_index(&self, stripe: u64, k: usize) -> u64 {
+        stripe + k as u64 * self.stripes
+    }
+
+    /// Bytes of the artifact stored in `block` (only the last block may be short)
+    fn block_len(&self, block: u64) -> usize {
+        let start = block * self.block_size as u64;
+        (self.file_len - start).min(self.block_size as u64) as usize
+    }
+
+    fn total_parity(&self) -> usize {
+        (0..self.stripes).map(|s| self.parity_blocks(s)).sum()
+    }
+
+    fn table_len(&self) -> usize {
+        HEADER_LEN + (self.blocks as usize + self.total_parity()) * SUM_LEN + SUM_LEN
+    }
+}
+
+fn checksum(block: &[u8]) -> [u8; SUM_LEN] {
+    Sha256::digest(block).into()
+}
+
+fn codec(cache: &mut HashMap<(usize, usize), ReedSolomon>, data: usize, parity: usize) -> Result<&ReedSolomon> {
+    match cache.entry((data, parity)) {
+        Entry::Occupied(entry) => Ok(entry.into_mut()),
+        Entry::Vacant(entry) => Ok(entry.insert(
+            ReedSolomon::new(data, parity).map_err(|e| CompressorError::InvalidInput(format!("Reed-Solomon setup failed: {:?}", e)))?,
+        )),
+    }
+}
+
+/// Read block `block` of `file` into `buf`, zero-padding past the end of the data
+fn read_block(file: &mut File, layout: &Layout, block: u64, buf: &mut [u8]) -> io::Result<()> {
+    buf.fill(0);
+    file.seek(SeekFrom::Start(block * layout.block_size as u64))?;
+    let len = layout.block_len(block);
+    let mut filled = 0;
+    while filled < len {
+        match file.read(&mut buf[filled..len]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Path of the recovery file for `artifact`
+pub fn parity_path(artifact: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.{}", artifact.display(), PARITY_EXTENSION))
+}
+
+/// Write Reed-Solomon recovery data for `artifact` able to rebuild `redundancy`% damaged blocks
+pub fn create_recovery_file(artifact: &Path, redundancy: u32) -> Result<PathBuf> {
+    if !(1..=100).contains(&redundancy) {
+        return Err(CompressorError::InvalidInput(format!("Redundancy must be between 1 and 100 percent, got {}", redundancy)));
+    }
+    let mut file = File::open(artifact).at(artifact)?;
+    let file_len = file.metadata().at(artifact)?.len();
+    let layout = Layout::new(file_len, choose_block_size(file_len), DATA_PER_STRIPE, redundancy);
+    let bs = layout.block_size as usize;
+
+    // checksum every block and the whole artifact in one sequential pass
+    let mut data_sums = Vec::with_capacity(layout.blocks as usize);
+    let mut hasher = Sha256::new();
+    let mut block = vec![0u8; bs];
+    for i in 0..layout.blocks {
+        read_block(&mut file, &layout, i, &mut block).at(artifact)?;
+        hasher.update(&block[..layout.block_len(i)]);
+        data_sums.push(checksum(&block));
+    }
+    let artifact_hash: [u8; 32] = hasher.finalize().into();
+
+    let out_path = parity_path(artifact);
+    let part = PartFile::new(PathBuf::from(format!("{}.part", out_path.display())));
+    let tmp_path = part.path().to_path_buf();
+    let mut out = BufWriter::new(File::create(&tmp_path).at(&tmp_path)?);
+    // parity blocks follow the table, whose size is known up front
+    out.write_all(&vec![0u8; layout.table_len()]).at(&tmp_path)?;
+    let mut parity_sums = Vec::with_capacity(layout.total_parity());
+    let mut codecs = HashMap::new();
+    for stripe in 0..layout.stripes {
+        let (data, parity) = (layout.data_blocks(stripe), layout.parity_blocks(stripe));
+        let mut shards = vec![vec![0u8; bs]; data + parity];
+        for (k, shard) in shards.iter_mut().take(data).enumerate() {
+            read_block(&mut file, &layout, layout.block_index(stripe, k), shard).at(artifact)?;
+        }
+        codec(&mut codecs, data, parity)?
+            .encode(&mut shards)
+            .map_err(|e| CompressorError::InvalidInput(format!("Reed-Solomon encoding failed: {:?}", e)))?;
+        for shard in &shards[data..] {
+            parity_sums.push(checksum(shard));
+            out.write_all(shard).at(&tmp_path)?;
+        }
+    }
+
+    let mut table = Vec::with_capacity(layout.table_len());
+    table.extend_from_slice(MAGIC);
+    table.extend_from_slice(&layout.file_len.to_le_bytes());
+    table.extend_from_slice(&layout.block_size.to_le_bytes());
+    table.extend_from_slice(&layout.data_per_stripe.to_le_bytes());
+    table.extend_from_slice(&layout.redundancy.to_le_bytes());
+    table.extend_from_slice(&artifact_hash);
+    data_sums.iter().chain(&parity_sums).for_each(|sum| table.extend_from_slice(sum));
+    let table_hash = checksum(&table);
+    table.extend_from_slice(&table_hash);
+    out.seek(SeekFrom::Start(0)).at(&tmp_path)?;
+    out.write_all(&table).at(&tmp_path)?;
+    let out = out.into_inner().map_err(|e| e.into_error()).at(&tmp_path)?;
+    durable::sync_file(&out).at(&tmp_path)?;
+    drop(out);
+    part.commit(&out_path).at(&out_path)?;
+    Ok(out_path)
+}
+
+/// Recovery data loaded from a `.par` file
+struct RecoveryFile {
+    file: File,
+    layout: Layout,
+    artifact_hash: String,
+    data_sums: Vec<[u8; SUM_LEN]>,
+    parity_sums: Vec<[u8; SUM_LEN]>,
+}
+
+impl RecoveryFile {
+    fn open(path: &Path) -> Result<Self> {
+        let mut file = File::open(path).at(path)?;
+        let mut header = [0u8; HEADER_LEN];
+        file.read_exact(&mut header).map_err(|_| CompressorError::corrupt(path, "recovery file is truncated"))?;
+        if &header[..8] != MAGIC {
+            return Err(CompressorError::corrupt(path, "not a recovery file"));
+        }
+        let word = |i: usize| u32::from_le_bytes(header[i..i + 4].try_into().unwrap());
+        let file_len = u64::from_le_bytes(header[8..16].try_into().unwrap());
+        let (block_size, data_per_stripe, redundancy) = (word(16), word(20), word(24));
+        let layout = Layout::new(file_len, block_size, data_per_stripe, redundancy);
+        // sanity-check before trusting the header to size any allocation
+        if !(MIN_BLOCK..=MAX_BLOCK).contains(&(block_size as u64))
+            || !(1..=128).contains(&data_per_stripe)
+            || !(1..=100).contains(&redundancy)
+            || layout.table_len() as u64 > file.metadata().at(path)?.len()
+        {
+            return Err(CompressorError::corrupt(path, "invalid recovery file header"));
+        }
+        let mut table = header.to_vec();
+        table.resize(layout.table_len(), 0);
+        file.read_exact(&mut table[HEADER_LEN..]).map_err(|_| CompressorError::corrupt(path, "recovery file is truncated"))?;
+        let (body, stored_hash) = table.split_at(table.len() - SUM_LEN);
+        if checksum(body)[..] != *stored_hash {
+            return Err(CompressorError::corrupt(path, "recovery file is itself damaged (table checksum mismatch)"));
+        }
+        let mut sums = body[HEADER_LEN..]
+            .chunks_exact(SUM_LEN)
+            .map(|c| <[u8; SUM_LEN]>::try_from(c).unwrap());
+        let data_sums = sums.by_ref().take(layout.blocks as usize).collect();
+        let parity_sums = sums.collect();
+        Ok(RecoveryFile {
+            file,
+            artifact_hash: hex::encode(&header[28..60]),
+            layout,
+            data_sums,
+            parity_sums,
+        })
+    }
+}
+
+/// SHA-256 of the artifact a recovery file was generated for
+pub fn recovery_artifact_hash(par: &Path) -> Result<String> {
+    Ok(RecoveryFile::open(par)?.artifact_hash)
+}
+
+/// What `repair_file` found and fixed
+#[derive(Debug, Default)]
+pub struct RepairReport {
+    /// Data blocks whose checksum did not match
+    pub damaged_blocks: usize,
+    /// Parity blocks that were themselves damaged (and therefore unused)
+    pub damaged_parity: usize,
+    /// The artifact had the wrong length and was truncated or extended
+    pub resized: bool,
+}
+
+impl RepairReport {
+    pub fn was_intact(&self) -> bool {
+        self.damaged_blocks == 0 && !self.resized
+    }
+}
+
+/// Check `artifact` against its `.par` file and rebuild any damaged blocks in place
+pub fn repair_file(artifact: &Path) -> Result<RepairReport> {
+    let par = parity_path(artifact);
+    let mut recovery = RecoveryFile::open(&par)?;
+    let mut report = RepairReport::default();
+    let layout = &recovery.layout;
+    let bs = layout.block_size as usize;
+    if fs::metadata(artifact).at(artifact)?.len() == layout.file_len
+        && crate::compressor::sha256_file(artifact)? == recovery.artifact_hash
+    {
+        return Ok(report);
+    }
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(artifact)
+        .at(artifact)?;
+    if file.metadata().at(artifact)?.len() != layout.file_len {
+        // missing bytes read back as zeros and fail their checksum below
+        file.set_len(layout.file_len).at(artifact)?;
+        report.resized = true;
+    }
+
+    let mut codecs = HashMap::new();
+    let mut parity_index = 0;
+    let parity_start = layout.table_len() as u64;
+    for stripe in 0..layout.stripes {
+        let (data, parity) = (layout.data_blocks(stripe), layout.parity_blocks(stripe));
+        let mut shards: Vec<Option<Vec<u8>>> = Vec::with_capacity(data + parity);
+        let mut damaged = Vec::new();
+        for k in 0..data {
+            let block = layout.block_index(stripe, k);
+            let mut buf = vec![0u8; bs];
+            read_block(&mut file, layout, block, &mut buf).at(artifact)?;
+            if checksum(&buf) == recovery.data_sums[block as usize] {
+                shards.push(Some(buf));
+            } else {
+                damaged.push(k);
+                shards.push(None);
+            }
+        }
+        recovery.file.seek(SeekFrom::Start(parity_start + (parity_index * bs) as u64)).at(&par)?;
+        for _ in 0..parity {
+            let mut buf = vec![0u8; bs];
+            let intact = recovery.file.read_exact(&mut buf).is_ok()
+                && checksum(&buf) == recovery.parity_sums[parity_index];
+            if !intact {
+                report.damaged_parity += 1;
+            }
+            shards.push(intact.then_some(buf));
+            parity_index += 1;
+        }
+        if damaged.is_empty() {
+            continue;
+        }
+        report.damaged_blocks += damaged.len();
+        let missing = shards.iter().filter(|s| s.is_none()).count();
+        if missing > parity {
+            return Err(CompressorError::CorruptData {
+                path: Some(artifact.to_path_buf()),
+                offset: Some(layout.block_index(stripe, damaged[0]) * bs as u64),
+                reason: format!("cannot repair: stripe {} has {} damaged blocks but only {} can be rebuilt", stripe, missing, parity),
+            });
+        }
+        codec(&mut codecs, data, parity)?
+            .reconstruct_data(&mut shards)
+            .map_err(|e| CompressorError::corrupt(artifact, format!("Reed-Solomon reconstruction failed: {:?}", e)))?;
+        for k in damaged {
+            let block = layout.block_index(stripe, k);
+            file.seek(SeekFrom::Start(block * bs as u64)).at(artifact)?;
+            file.write_all(&shards[k].as_ref().unwrap()[..layout.block_len(block)]).at(artifact)?;
+        }
+    }
+    file.sync_all().at(artifact)?;
+    drop(file);
+
+    let actual = crate::compressor::sha256_file(artifact)?;
+    if actual != recovery.artifact_hash {
+        return Err(CompressorError::Integrity { path: artifact.to_path_buf(), expected: recovery.artifact_hash, actual });
+    }
+    Ok(report)
+}
+
+/// Add recovery data to `output`: one artifact, or every artifact listed in a folder manifest
+///
+/// Existing `.par` files that still match their artifact's manifest hash and were written
+/// at `redundancy` are kept; others are regenerated.
+pub fn protect_path(output: &Path, redundancy: u32) -> Result<usize> {
+    if output.is_file() {
+        create_recovery_file(output, redundancy)?;
+        return Ok(1);
+    }
+    let mut written = 0;
+    for (hash, file) in manifest::read_listing(output)? {
+        let artifact = output.join(&file);
+        let par = parity_path(&artifact);
+        if RecoveryFile::open(&par).is_ok_and(|r| r.artifact_hash == hash && r.layout.redundancy == redundancy) {
+            continue;
+        }
+        create_recovery_file(&artifact, redundancy)?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Repair `path`: one artifact, or every artifact in a folder that has a `.par` file
+pub fn repair_path(path: &Path) -> Result<Vec<(PathBuf, RepairReport)>> {
+    if path.is_file() {
+        return Ok(vec![(path.to_path_buf(), repair_file(path)?)]);
+    }
+    let mut reports = Vec::new();
+    let (files, _) = crate::compressor::collect_files(path);
+    for par in files.iter().filter(|f| f.extension().is_some_and(|e| e == PARITY_EXTENSION)) {
+        let artifact = par.with_extension("");
+        reports.push((artifact.clone(), repair_file(&artifact)?));
+    }
+    Ok(reports)
+}
@@ -0,0 +1,198 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{mpsc::Sender, Mutex},
+};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+use crate::error::CompressorError;
+
+/// Receives progress events from compress and decompress jobs
+///
+/// Every method has a no-op default, so a sink only implements what it shows. A folder job
+/// announces its totals once, then reports each file as started, advanced, and finished,
+/// skipped or failed, and ends with `job_finished`. Sizes and positions are in bytes of the
+/// file being read: sources when compressing, artifacts when decompressing. Dedup archives
+/// share chunks between files, so their extraction counts restored bytes instead.
+pub trait ProgressSink: Send + Sync {
+    /// A job over `files` inputs totalling `bytes` is starting
+    fn job_started(&self, _files: usize, _bytes: u64) {}
+    fn file_started(&self, _path: &Path, _size: u64) {}
+    /// `done` bytes of `path` have been read so far
+    fn file_progress(&self, _path: &Path, _done: u64) {}
+    fn file_finished(&self, _path: &Path, _size: u64) {}
+    /// `path` was left alone: its output already existed, or a resumed run had finished it
+    fn file_skipped(&self, _path: &Path, _size: u64) {}
+    fn file_failed(&self, _path: &Path, _error: &CompressorError) {}
+    /// The job is over, successfully when `error` is `None`
+    fn job_finished(&self, _error: Option<&CompressorError>) {}
+}
+
+/// Discards every event
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoProgress;
+
+impl ProgressSink for NoProgress {}
+
+/// Terminal progress bars: one per file in flight plus a total bar for folder jobs
+#[derive(Default)]
+pub struct IndicatifProgress {
+    mp: MultiProgress,
+    total: Mutex<Option<ProgressBar>>,
+    files: Mutex<HashMap<PathBuf, ProgressBar>>,
+}
+
+impl IndicatifProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ProgressSink for IndicatifProgress {
+    fn job_started(&self, _files: usize, bytes: u64) {
+        let bar = self.mp.add(ProgressBar::new(bytes));
+        bar.set_style(
+            ProgressStyle::with_template(
+                "{msg:.bold} [{bar:40.green/black}] {bytes}/{total_bytes} {percent:>3}%"
+            ).unwrap()
+            .progress_chars("=> ")
+        );
+        bar.set_message("Total progress");
+        *self.total.lock().unwrap() = Some(bar);
+    }
+
+    fn file_started(&self, path: &Path, size: u64) {
+        let bar = self.mp.add(ProgressBar::new(size));
+        bar.set_prefix(format!("{}", path.file_name().unwrap_or_default().to_string_lossy()));
+        bar.set_style(
+            ProgressStyle::with_template(
+                "{prefix:.dim}  [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} {percent:>3}%"
+            ).unwrap()
+            .progress_chars("=> ")
+        );
+        self.files.lock().unwrap().insert(path.to_path_buf(), bar);
+    }
+
+    fn file_progress(&self, path: &Path, done: u64) {
+        if let Some(bar) = self.files.lock().unwrap().get(path) {
+            bar.set_position(done);
+        }
+    }
+
+    fn file_finished(&self, path: &Path, size: u64) {
+        if let Some(bar) = self.files.lock().unwrap().remove(path) {
+            bar.finish_with_message("done");
+        }
+        self.file_skipped(path, size);
+    }
+
+    fn file_skipped(&self, _path: &Path, size: u64) {
+        if let Some(total) = &*self.total.lock().unwrap() {
+            total.inc(size);
+        }
+    }
+
+    fn file_failed(&self, path: &Path, _error: &CompressorError) {
+        if let Some(bar) = self.files.lock().unwrap().remove(path) {
+            bar.abandon();
+        }
+    }
+
+    fn job_finished(&self, error: Option<&CompressorError>) {
+        match (error, self.total.lock().unwrap().take()) {
+            // leave the terminal usable for the error message
+            (Some(_), _) => {
+                let _ = self.mp.clear();
+            }
+            (None, Some(total)) => total.finish_with_message("All files done"),
+            (None, None) => {}
+        }
+    }
+}
+
+/// One progress event, as sent by [`ChannelProgress`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProgressEvent {
+    JobStarted { files: usize, bytes: u64 },
+    FileStarted { path: PathBuf, size: u64 },
+    FileProgress { path: PathBuf, done: u64 },
+    FileFinished { path: PathBuf, size: u64 },
+    FileSkipped { path: PathBuf, size: u64 },
+    FileFailed { path: PathBuf, error: String },
+    /// `error` holds the message of the error that ended the job, if any
+    JobFinished { error: Option<String> },
+}
+
+/// Forwards every event to a channel, for GUIs and services that render progress themselves
+///
+/// Events are dropped once the receiver hangs up; the job carries on.
+pub struct ChannelProgress {
+    sender: Sender<ProgressEvent>,
+}
+
+impl ChannelProgress {
+    pub fn new(sender: Sender<ProgressEvent>) -> Self {
+        ChannelProgress { sender }
+    }
+
+    fn send(&self, event: ProgressEvent) {
+        let _ = self.sender.send(event);
+    }
+}
+
+impl ProgressSink for ChannelProgress {
+    fn job_started(&self, files: usize, bytes: u64) {
+        self.send(ProgressEvent::JobStarted { files, bytes });
+    }
+
+    fn file_started(&self, path: &Path, size: u64) {
+        self.send(ProgressEvent::FileStarted { path: path.to_path_buf(), size });
+    }
+
+    fn file_progress(&self, path: &Path, done: u64) {
+        self.send(ProgressEvent::FileProgress { path: path.to_path_buf(), done });
+    }
+
+    fn file_finished(&self, path: &Path, size: u64) {
+        self.send(ProgressEvent::FileFinished { path: path.to_path_buf(), size });
+    }
+
+    fn file_skipped(&self, path: &Path, size: u64) {
+        self.send(ProgressEvent::FileSkipped { path: path.to_path_buf(), size });
+    }
+
+    fn file_failed(&self, path: &Path, error: &CompressorError) {
+        self.send(ProgressEvent::FileFailed { path: path.to_path_buf(), error: error.to_string() });
+    }
+
+    fn job_finished(&self, error: Option<&CompressorError>) {
+        self.send(ProgressEvent::JobFinished { error: error.map(ToString::to_string) });
+    }
+}
+
+/// Reader that reports how far into `path` it has read
+pub(crate) struct ProgressReader<'a, R> {
+    pub inner: R,
+    pub path: &'a Path,
+    pub sink: &'a dyn ProgressSink,
+    pub done: u64,
+}
+
+impl<'a, R> ProgressReader<'a, R> {
+    pub fn new(inner: R, path: &'a Path, sink: &'a dyn ProgressSink) -> Self {
+        ProgressReader { inner, path, sink, done: 0 }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.done += n as u64;
+            self.sink.file_progress(self.path, self.done);
+        }
+        Ok(n)
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    fs,
+    io::{self, BufRead, Read, Write},
+    ops::RangeInclusive,
+    path::Path,
+    str::FromStr,
+};
+
+use crate::compressor::{Compressor, StreamEncoder};
+use crate::error::{CompressorError, PathContext, Result};
+
+/// Extension appended to the compressor's own extension (`file.zst.age`)
+pub const AGE_EXTENSION: &str = "age";
+
+/// Parse an `age1...` X25519 recipient
+pub fn parse_recipient(text: &str) -> Result<age::x25519::Recipient> {
+    age::x25519::Recipient::from_str(text.trim())
+        .map_err(|e| CompressorError::InvalidInput(format!("Invalid recipient {}: {}", text.trim(), e)))
+}
+
+/// Read a recipients file: one `age1...` key per line, `#` comments and blank lines ignored
+pub fn read_recipients_file(path: &Path) -> Result<Vec<age::x25519::Recipient>> {
+    let text = fs::read_to_string(path).at(path)?;
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            parse_recipient(line).map_err(|e| CompressorError::InvalidInput(format!("{} (in {})", e, path.display())))
+        })
+        .collect()
+}
+
+/// Read an age identity file (as written by `age-keygen`): one `AGE-SECRET-KEY-1...` per line
+///
+/// Identities are parsed up front rather than through `age::IdentityFile`, whose boxed
+/// identities cannot be shared with worker threads.
+pub fn read_identity_file(path: &Path) -> Result<Vec<age::x25519::Identity>> {
+    let text = fs::read_to_string(path).at(path)?;
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            age::x25519::Identity::from_str(line)
+                .map_err(|e| CompressorError::Crypto(format!("Unsupported identity in {}: {}", path.display(), e)))
+        })
+        .collect()
+}
+
+/// Wraps another compressor, encrypting its output to X25519 recipients in the age format
+///
+/// Outputs are plain age files, so `age -d -i key.txt file.zst.age > file.zst` also works.
+/// Only holders of a matching identity can decrypt; the writer needs public keys alone.
+pub struct RecipientCompressor {
+    inner: Box<dyn Compressor>,
+    recipients: Vec<age::x25519::Recipient>,
+    identities: Vec<age::x25519::Identity>,
+    extension: String,
+}
+
+impl RecipientCompressor {
+    /// Compressor that encrypts to `recipients`
+    pub fn for_recipients(inner: Box<dyn Compressor>, recipients: Vec<age::x25519::Recipient>) -> Self {
+        Self::new(inner, recipients, Vec::new())
+    }
+
+    /// Compressor that decrypts with any of `identities`
+    pub fn for_identities(inner: Box<dyn Compressor>, identities: Vec<age::x25519::Identity>) -> Self {
+        Self::new(inner, Vec::new(), identities)
+    }
+
+    fn new(
+        inner: Box<dyn Compressor>,
+        recipients: Vec<age::x25519::Recipient>,
+        identities: Vec<age::x25519::Identity>,
+    ) -> Self {
+        let extension = format!("{}.{}", inner.extension(), AGE_EXTENSION);
+        RecipientCompressor { inner, recipients, identities, extension }
+    }
+}
+
+impl RecipientCompressor {
+    fn encryptor(&self) -> Result<age::Encryptor> {
+        age::Encryptor::with_recipients(self.recipients.iter().map(|r| r as &dyn age::Recipient))
+            .map_err(|e| CompressorError::Crypto(format!("Cannot encrypt to recipients: {}", e)))
+    }
+}
+
+impl StreamEncoder for age::stream::StreamWriter<Box<dyn StreamEncoder + '_>> {
+    fn finish(self: Box<Self>) -> Result<()> {
+        (*self).finish()?.finish()
+    }
+}
+
+impl Compressor for RecipientCompressor {
+    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write, level: i32) -> Result<()> {
+        let encryptor = self.encryptor()?;
+        let mut writer = encryptor.wrap_output(output)?;
+        self.inner.compress(input, &mut writer, level)?;
+        writer.finish()?;
+        Ok(())
+    }
+    fn decompress(&self, input: &mut dyn Read, output: &mut dyn Write) -> Result<()> {
+        let decryptor = age::Decryptor::new(input)
+            .map_err(not_age)?;
+        let mut reader = decryptor
+            .decrypt(self.identities.iter().map(|i| i as &dyn age::Identity))
+            .map_err(|e| CompressorError::Crypto(format!("Cannot decrypt: {}", e)))?;
+        self.inner.decompress(&mut reader, output)?;
+        // the decoder may stop at the end of its frame: authenticate the final chunk anyway
+        io::copy(&mut reader, &mut io::sink())?;
+        Ok(())
+    }
+    fn decompress_window_limited(&self, input: &mut dyn Read, output: &mut dyn Write, max_window_log: u32) -> Result<()> {
+        let decryptor = age::Decryptor::new(input)
+            .map_err(not_age)?;
+        let mut reader = decryptor
+            .decrypt(self.identities.iter().map(|i| i as &dyn age::Identity))
+            .map_err(|e| CompressorError::Crypto(format!("Cannot decrypt: {}", e)))?;
+        self.inner.decompress_window_limited(&mut reader, output, max_window_log)?;
+        io::copy(&mut reader, &mut io::sink())?;
+        Ok(())
+    }
+    fn encoder<'a>(&'a self, output: Box<dyn StreamEncoder + 'a>, level: i32) -> Result<Box<dyn StreamEncoder + 'a>> {
+        let writer = self.encryptor()?.wrap_output(output)?;
+        self.inner.encoder(Box::new(writer), level)
+    }
+    fn decoder<'a>(&'a self, input: Box<dyn BufRead + 'a>) -> Result<Box<dyn Read + 'a>> {
+        let decryptor = age::Decryptor::new_buffered(input)
+            .map_err(not_age)?;
+        let reader = decryptor
+            .decrypt(self.identities.iter().map(|i| i as &dyn age::Identity))
+            .map_err(|e| CompressorError::Crypto(format!("Cannot decrypt: {}", e)))?;
+        self.inner.decoder(Box::new(io::BufReader::new(reader)))
+    }
+    fn extension(&self) -> &str { &self.extension }
+    fn encryption(&self) -> Option<&'static str> { Some("age-x25519") }
+    fn level_range(&self) -> Option<RangeInclusive<i32>> { self.inner.level_range() }
+    fn default_level(&self) -> i32 { self.inner.default_level() }
+    fn supports_dictionaries(&self) -> bool { self.inner.supports_dictionaries() }
+    fn supports_multithreading(&self) -> bool { self.inner.supports_multithreading() }
+    // age authenticates each 64 KiB payload chunk
+    fn has_checksum(&self) -> bool { true }
+}
+
+fn not_age(err: age::DecryptError) -> CompressorError {
+    CompressorError::CorruptData { path: None, offset: Some(0), reason: format!("not a valid age file: {}", err) }
+}
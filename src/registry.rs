@@ -0,0 +1,179 @@
+use std::sync::{OnceLock, PoisonError, RwLock, RwLockReadGuard};
+
+use crate::compressor::{Compressor, GzipCompressor, Lz4Compressor, ZstdCompressor};
+use crate::error::{CompressorError, Result};
+
+type Factory = Box<dyn Fn() -> Box<dyn Compressor> + Send + Sync>;
+
+/// A compression format the CLI and detection know about: its names and a factory for it
+///
+/// The extension, magic bytes and levels come from the compressor the factory builds.
+pub struct Format {
+    name: String,
+    aliases: Vec<String>,
+    factory: Factory,
+    compressor: Box<dyn Compressor>,
+}
+
+impl Format {
+    pub fn new(name: impl Into<String>, factory: impl Fn() -> Box<dyn Compressor> + Send + Sync + 'static) -> Self {
+        let compressor = factory();
+        Format { name: name.into(), aliases: Vec::new(), factory: Box::new(factory), compressor }
+    }
+
+    /// Another name `--format` accepts for this format
+    pub fn alias(mut self, alias: impl Into<String>) -> Self {
+        self.aliases.push(alias.into());
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    pub fn extension(&self) -> &str {
+        self.compressor.extension()
+    }
+
+    pub fn magic(&self) -> &'static [u8] {
+        self.compressor.magic()
+    }
+
+    /// `level` if the format accepts it, otherwise `InvalidInput` naming the valid range
+    pub fn check_level(&self, level: i32) -> Result<i32> {
+        match self.compressor.level_range() {
+            Some(range) if range.contains(&level) => Ok(level),
+            Some(range) => Err(CompressorError::InvalidInput(format!(
+                "{} levels are {} to {} (default {}), got {}",
+                self.name,
+                range.start(),
+                range.end(),
+                self.compressor.default_level(),
+                level
+            ))),
+            None => Err(CompressorError::InvalidInput(format!("{} has no compression levels", self.name))),
+        }
+    }
+
+    /// A new compressor for this format, for wrapping in an encrypting one
+    pub fn create(&self) -> Box<dyn Compressor> {
+        (self.factory)()
+    }
+
+    /// Shared compressor for this format
+    pub fn compressor(&self) -> &dyn Compressor {
+        &*self.compressor
+    }
+
+    fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.name.as_str()).chain(self.aliases.iter().map(String::as_str))
+    }
+
+    /// One-line description for `--help`
+    fn describe(&self) -> String {
+        let mut text = self.name.clone();
+        if !self.aliases.is_empty() {
+            text += &format!(" ({})", self.aliases.join(", "));
+        }
+        match self.compressor.level_range() {
+            Some(range) => {
+                text + &format!(": levels {} to {}, default {}", range.start(), range.end(), self.compressor.default_level())
+            }
+            None => text + ": no levels",
+        }
+    }
+}
+
+/// The formats known to the CLI, by name, alias, extension and magic bytes
+///
+/// Downstream crates add theirs with [`register`] before parsing arguments or detecting
+/// inputs. Registered formats live for the rest of the process.
+#[derive(Default)]
+pub struct FormatRegistry {
+    formats: Vec<&'static Format>,
+}
+
+impl FormatRegistry {
+    /// An empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry holding zstd, gzip and lz4
+    pub fn with_builtins() -> Self {
+        static BUILTINS: OnceLock<Vec<Format>> = OnceLock::new();
+        let builtins = BUILTINS.get_or_init(|| {
+            vec![
+                Format::new("zstd", || Box::new(ZstdCompressor)).alias("zst").alias("zstandard"),
+                Format::new("gzip", || Box::new(GzipCompressor)).alias("gz"),
+                Format::new("lz4", || Box::new(Lz4Compressor)),
+            ]
+        });
+        FormatRegistry { formats: builtins.iter().collect() }
+    }
+
+    /// Add `format`, refusing names, aliases or extensions another format already uses
+    pub fn register(&mut self, format: Format) -> Result<&'static Format> {
+        for name in format.names() {
+            if self.get(name).is_some() {
+                return Err(CompressorError::InvalidInput(format!("format name {:?} is already registered", name)));
+            }
+        }
+        if self.for_extension(format.extension()).is_some() {
+            return Err(CompressorError::InvalidInput(format!("extension {:?} is already registered", format.extension())));
+        }
+        let format: &'static Format = Box::leak(Box::new(format));
+        self.formats.push(format);
+        Ok(format)
+    }
+
+    /// Format called `name` or one of its aliases, ignoring case
+    pub fn get(&self, name: &str) -> Option<&'static Format> {
+        self.formats.iter().copied().find(|f| f.names().any(|n| n.eq_ignore_ascii_case(name)))
+    }
+
+    /// Format writing files with extension `ext`
+    pub fn for_extension(&self, ext: &str) -> Option<&'static Format> {
+        self.formats.iter().copied().find(|f| f.extension() == ext)
+    }
+
+    /// Format of data starting with `data`, recognised by its magic bytes
+    pub fn for_magic(&self, data: &[u8]) -> Option<&'static Format> {
+        self.formats.iter().copied().find(|f| !f.magic().is_empty() && data.starts_with(f.magic()))
+    }
+
+    /// Bytes to read before [`for_magic`](Self::for_magic) can recognise every format
+    pub fn magic_len(&self) -> usize {
+        self.formats.iter().map(|f| f.magic().len()).max().unwrap_or(0)
+    }
+
+    pub fn formats(&self) -> impl Iterator<Item = &'static Format> + '_ {
+        self.formats.iter().copied()
+    }
+
+    /// Every format with its aliases and levels, for `--help`
+    pub fn help(&self) -> String {
+        self.formats.iter().map(|f| f.describe()).collect::<Vec<_>>().join("; ")
+    }
+}
+
+fn registry() -> &'static RwLock<FormatRegistry> {
+    static GLOBAL: OnceLock<RwLock<FormatRegistry>> = OnceLock::new();
+    GLOBAL.get_or_init(|| RwLock::new(FormatRegistry::with_builtins()))
+}
+
+/// The process-wide registry that detection and the CLI use
+///
+/// Drop the guard before calling [`register`] on the same thread.
+pub fn global() -> RwLockReadGuard<'static, FormatRegistry> {
+    registry().read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Add `format` to the process-wide registry
+pub fn register(format: Format) -> Result<&'static Format> {
+    registry().write().unwrap_or_else(PoisonError::into_inner).register(format)
+}
@@ -0,0 +1,169 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+
+use crate::cancel::{CancelToken, Cancellable};
+use crate::compressor::Compressor;
+use crate::error::{PathContext, Result};
+
+/// What a salvage run managed to recover
+#[derive(Debug, Default)]
+pub struct SalvageReport {
+    /// Decompressed bytes written to the output
+    pub recovered_bytes: u64,
+    /// Compressed byte ranges that could not be decoded and were skipped
+    ///
+    /// A range starts at the offset the decoder had consumed up to when it failed. Decoders
+    /// take input a buffer at a time, so that can lie up to one 64 KiB read buffer past the
+    /// damaged byte; the end, where decoding restarted, is exact.
+    pub lost_ranges: Vec<Range<u64>>,
+    /// The input ended in the middle of a frame
+    pub truncated: bool,
+    /// Number of places decoding (re)started
+    pub segments: usize,
+}
+
+impl SalvageReport {
+    pub fn is_complete(&self) -> bool {
+        self.lost_ranges.is_empty() && !self.truncated
+    }
+
+    pub fn lost_bytes(&self) -> u64 {
+        self.lost_ranges.iter().map(|r| r.end - r.start).sum()
+    }
+}
+
+/// Decode as much of a damaged file as possible
+///
+/// Decoding runs until the first error; the input is then scanned for the compressor's
+/// frame magic from the point the decoder had reached and decoding restarts there.
+/// Everything decoded before an error is kept, so the last block before damage may be
+/// incomplete. Formats without a frame magic (encrypted wrappers) cannot resync and
+/// stop at the first error.
+///
+/// Resyncing needs another frame to restart at. A single-frame file, which is what
+/// `compress` writes, recovers nothing past its first damaged byte: zstd blocks depend on
+/// the window and entropy tables built by the blocks before them, so there is no block
+/// boundary decoding could restart from.
+///
+/// The input is streamed, never loaded whole, and the run stops with `Cancelled` once
+/// `cancel` is cancelled.
+pub fn salvage_file_with(input_path: &str, output_path: &str, compressor: &dyn Compressor, cancel: &CancelToken) -> Result<SalvageReport> {
+    let len = fs::metadata(input_path).at(input_path)?.len();
+    let mut out = CountingWriter { inner: BufWriter::new(File::create(output_path).at(output_path)?), count: 0 };
+    let magic = compressor.magic();
+    let mut report = SalvageReport::default();
+    let mut pos = 0;
+    while pos < len {
+        report.segments += 1;
+        let mut input = PositionReader { inner: BufReader::with_capacity(SCAN_BUFFER, Cancellable(open_at(input_path, pos)?, cancel)), pos };
+        let result = compressor
+            .decoder(Box::new(&mut input))
+            .and_then(|mut decoder| Ok(io::copy(&mut decoder, &mut out)?));
+        // decoders consume input only as they reach it, so this is where decoding stopped
+        let stopped = input.pos;
+        if result.is_ok() {
+            break;
+        }
+        cancel.check()?;
+        let search_from = stopped.max(pos + 1);
+        let next = if magic.is_empty() || search_from >= len {
+            None
+        } else {
+            find_magic(input_path, search_from, magic, cancel)?
+        };
+        match next {
+            Some(next) => {
+                report.lost_ranges.push(stopped..next);
+                pos = next;
+            }
+            None if stopped >= len => {
+                report.truncated = true;
+                break;
+            }
+            None => {
+                report.lost_ranges.push(stopped..len);
+                break;
+            }
+        }
+    }
+    out.inner.flush().at(output_path)?;
+    report.recovered_bytes = out.count;
+    Ok(report)
+}
+
+/// Bytes read from the input at a time, both for decoding and for scanning for magic
+const SCAN_BUFFER: usize = 64 * 1024;
+
+fn open_at(path: &str, offset: u64) -> Result<File> {
+    let mut file = File::open(path).at(path)?;
+    file.seek(SeekFrom::Start(offset)).at(path)?;
+    Ok(file)
+}
+
+/// Offset of the first `magic` at or after `from`, reading one bounded buffer at a time
+fn find_magic(path: &str, from: u64, magic: &[u8], cancel: &CancelToken) -> Result<Option<u64>> {
+    let mut file = Cancellable(open_at(path, from)?, cancel);
+    let mut window = Vec::with_capacity(SCAN_BUFFER + magic.len());
+    // offset of window[0] in the file
+    let mut start = from;
+    loop {
+        let kept = window.len();
+        window.resize(kept + SCAN_BUFFER, 0);
+        let n = file.read(&mut window[kept..]).at(path)?;
+        window.truncate(kept + n);
+        if let Some(offset) = window.windows(magic.len()).position(|w| w == magic) {
+            return Ok(Some(start + offset as u64));
+        }
+        if n == 0 {
+            return Ok(None);
+        }
+        // keep the tail in case the magic straddles two reads
+        let drop = window.len().saturating_sub(magic.len() - 1);
+        window.drain(..drop);
+        start += drop as u64;
+    }
+}
+
+/// Buffered reader that tracks the file offset of the next byte it hands out
+struct PositionReader<R: Read> {
+    inner: BufReader<R>,
+    pos: u64,
+}
+
+impl<R: Read> Read for PositionReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read> BufRead for PositionReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+        self.pos += amt as u64;
+    }
+}
+
+struct CountingWriter<W: Write> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,160 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+use crate::crypto::read_key_file;
+use crate::durable;
+use crate::error::{CompressorError, PathContext, Result};
+use crate::manifest::{self, Manifest, INDEX_FILE, MANIFEST_FILE};
+
+/// Detached Ed25519 signature over `manifest-sha256.txt` and the index beside it
+///
+/// The signed bytes are the listing followed by one more listing line for
+/// `manifest-index.tsv`, so restoring from the index is as trustworthy as the listing.
+/// Signatures made before the index was covered still verify the listing alone.
+pub const SIGNATURE_FILE: &str = "manifest-sha256.txt.sig";
+
+type Listing = Vec<(String, String)>;
+
+/// Generate a signing key at `secret_path` and its public key next to it as `<secret_path>.pub`
+pub fn generate_keypair(secret_path: &Path) -> Result<PathBuf> {
+    let mut seed = zeroize::Zeroizing::new([0u8; 32]);
+    getrandom::getrandom(&mut seed[..]).map_err(|e| CompressorError::Crypto(format!("No system randomness: {}", e)))?;
+    let key = SigningKey::from_bytes(&seed);
+    let public_path = PathBuf::from(format!("{}.pub", secret_path.display()));
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(secret_path)
+        .at(secret_path)?;
+    std::io::Write::write_all(&mut file, format!("{}\n", hex::encode(seed.as_slice())).as_bytes()).at(secret_path)?;
+    durable::sync_file(&file).at(secret_path)?;
+    durable::write_atomic(&public_path, format!("{}\n", hex::encode(key.verifying_key().as_bytes())).as_bytes())?;
+    Ok(public_path)
+}
+
+pub fn read_signing_key(path: &Path) -> Result<SigningKey> {
+    Ok(SigningKey::from_bytes(&*read_key_file(path)?))
+}
+
+pub fn read_verifying_key(path: &Path) -> Result<VerifyingKey> {
+    VerifyingKey::from_bytes(&*read_key_file(path)?)
+        .map_err(|_| CompressorError::Signature(format!("{} is not a valid Ed25519 public key", path.display())))
+}
+
+/// Sign the manifest and index in `dir`, writing the detached signature beside them
+pub fn sign_manifest(dir: &Path, key: &SigningKey) -> Result<()> {
+    let manifest_path = dir.join(MANIFEST_FILE);
+    let bytes = fs::read(&manifest_path).at(&manifest_path)?;
+    let index = read_index(dir)?;
+    let signature = key.sign(&signed_bytes(&bytes, index.as_deref()));
+    durable::write_atomic(&dir.join(SIGNATURE_FILE), format!("{}\n", hex::encode(signature.to_bytes())).as_bytes())?;
+    Ok(())
+}
+
+/// Read the manifest in `dir`, returning its entries only if the signature checks out
+pub fn read_signed_listing(dir: &Path, key: &VerifyingKey) -> Result<Vec<(String, String)>> {
+    Ok(read_signed(dir, key)?.0)
+}
+
+/// Read the index in `dir`, returning it only if the signature covers it and the listing
+///
+/// Fails unless every entry's artifact appears in the signed listing with the same hash,
+/// so the names and source hashes a restore uses are exactly the ones that were signed.
+pub fn read_signed_manifest(dir: &Path, key: &VerifyingKey) -> Result<Manifest> {
+    let index_path = dir.join(INDEX_FILE);
+    let (listing, index) = read_signed(dir, key)?;
+    let index = index.ok_or_else(|| {
+        CompressorError::Signature(format!("The signature does not cover {}; sign the folder again", index_path.display()))
+    })?;
+    let text = String::from_utf8(index).map_err(|_| CompressorError::corrupt(&index_path, "index is not valid UTF-8"))?;
+    let manifest = Manifest::parse(&text, &index_path)?;
+    for entry in manifest.entries.values() {
+        if !listing.iter().any(|(hash, file)| *hash == entry.hash && *file == entry.output) {
+            return Err(CompressorError::Signature(format!("{} is not listed in the signed manifest", entry.output)));
+        }
+    }
+    Ok(manifest)
+}
+
+/// Verify the signature in `dir`, returning the listing and, if the signature covers it, the index
+///
+/// Both are parsed from the same bytes that were verified so they cannot change in between.
+fn read_signed(dir: &Path, key: &VerifyingKey) -> Result<(Listing, Option<Vec<u8>>)> {
+    let manifest_path = dir.join(MANIFEST_FILE);
+    let sig_path = dir.join(SIGNATURE_FILE);
+    let bytes = fs::read(&manifest_path).at(&manifest_path)?;
+    let index = read_index(dir)?;
+    let sig_hex = fs::read_to_string(&sig_path)
+        .map_err(|_| CompressorError::Signature(format!("Manifest is not signed: {} is missing", sig_path.display())))?;
+    let mut sig_bytes = [0u8; 64];
+    hex::decode_to_slice(sig_hex.trim(), &mut sig_bytes)
+        .map_err(|_| CompressorError::Signature(format!("Malformed signature in {}", sig_path.display())))?;
+    let signature = Signature::from_bytes(&sig_bytes);
+    let index = if index.as_ref().is_some_and(|index| key.verify_strict(&signed_bytes(&bytes, Some(index)), &signature).is_ok()) {
+        index
+    } else {
+        // signed before the index was covered: the listing alone is trusted
+        key.verify_strict(&bytes, &signature)
+            .map_err(|_| CompressorError::Signature("Manifest signature does not match the trusted key".into()))?;
+        None
+    };
+    let text = String::from_utf8(bytes).map_err(|_| CompressorError::corrupt(&manifest_path, "manifest is not valid UTF-8"))?;
+    Ok((manifest::parse_listing(&text), index))
+}
+
+fn read_index(dir: &Path) -> Result<Option<Vec<u8>>> {
+    let index_path = dir.join(INDEX_FILE);
+    match fs::read(&index_path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).at(&index_path),
+    }
+}
+
+/// The listing, plus a line for the index when there is one
+fn signed_bytes(listing: &[u8], index: Option<&[u8]>) -> Vec<u8> {
+    let mut bytes = listing.to_vec();
+    if let Some(index) = index {
+        let line = manifest::listing_line(&hex::encode(Sha256::digest(index)), INDEX_FILE);
+        bytes.extend_from_slice(format!("{}\n", line).as_bytes());
+    }
+    bytes
+}
+
+/// Check `artifact` against the signed manifest of the nearest enclosing folder
+///
+/// A later read of the file may see other bytes; decompression checks the bytes it decodes
+/// against [`signed_hash`] instead.
+pub fn verify_artifact(artifact: &Path, key: &VerifyingKey) -> Result<()> {
+    let expected = signed_hash(artifact, key)?;
+    let actual = crate::compressor::sha256_file(artifact)?;
+    if actual != expected {
+        return Err(CompressorError::Integrity { path: artifact.to_path_buf(), expected, actual });
+    }
+    Ok(())
+}
+
+/// Hash the signed manifest of the nearest enclosing folder records for `artifact`
+pub(crate) fn signed_hash(artifact: &Path, key: &VerifyingKey) -> Result<String> {
+    let artifact = fs::canonicalize(artifact).at(artifact)?;
+    let dir = artifact
+        .ancestors()
+        .skip(1)
+        .find(|dir| dir.join(MANIFEST_FILE).is_file())
+        .ok_or_else(|| CompressorError::Signature(format!("No manifest found for {}", artifact.display())))?;
+    let listing = read_signed_listing(dir, key)?;
+    let rel = artifact.strip_prefix(dir).unwrap().to_string_lossy().to_string();
+    let (expected, _) = listing
+        .iter()
+        .find(|(_, file)| *file == rel)
+        .ok_or_else(|| CompressorError::Signature(format!("{} is not listed in the signed manifest", rel)))?;
+    Ok(expected.clone())
+}
@@ -0,0 +1,165 @@
+use std::{
+    fs::{self, File, FileTimes},
+    io::{self, Write},
+    path::Path,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::compressor::{self, Compressor};
+use crate::durable;
+use crate::error::{CompressorError, PathContext, Result};
+use crate::extract::{self, SafeDestination};
+use crate::manifest::{self, Manifest, INDEX_FILE, MANIFEST_FILE};
+use crate::parity;
+use crate::signing::SIGNATURE_FILE;
+
+/// What happens to an input once its output is written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SourcePolicy {
+    /// Leave inputs in place
+    #[default]
+    Keep,
+    /// Delete each input once its output is on disk, like `gzip` does
+    Remove,
+    /// Like `Remove`, but only after decoding the output again and comparing hashes
+    RemoveVerified,
+}
+
+/// Replace `source` with the artifact compressed from it
+///
+/// The artifact takes over the source's permissions and times before the source is deleted.
+/// With `RemoveVerified`, the artifact is decoded and must hash to the source's content.
+pub fn replace_compressed(source: &Path, artifact: &Path, compressor: &dyn Compressor, policy: SourcePolicy) -> Result<()> {
+    if policy == SourcePolicy::RemoveVerified {
+        check_decodes_to(artifact, compressor, compressor::sha256_file(source)?)?;
+    }
+    replace(source, artifact, policy)
+}
+
+/// Replace the compressed `source` with the file decompressed from it
+///
+/// With `RemoveVerified`, `source` is decoded a second time and must hash to what was written.
+pub fn replace_decompressed(source: &Path, restored: &Path, compressor: &dyn Compressor, policy: SourcePolicy) -> Result<()> {
+    if policy == SourcePolicy::RemoveVerified {
+        check_decodes_to(source, compressor, compressor::sha256_file(restored)?)?;
+    }
+    replace(source, restored, policy)
+}
+
+/// Delete the sources of a folder job once `output` holds their artifacts; returns how many went
+///
+/// Only sources whose size and mtime still match the manifest are touched, so a file edited
+/// while the job ran is kept, and only once their artifact exists and hashes to what the
+/// manifest records; an artifact that does not fails with `Integrity`. With `RemoveVerified`,
+/// each artifact is also decoded and checked against the recorded source hash.
+pub fn replace_compressed_folder(input: &Path, output: &Path, compressor: &dyn Compressor, policy: SourcePolicy) -> Result<usize> {
+    if policy == SourcePolicy::Keep {
+        return Ok(0);
+    }
+    let mut removed = 0;
+    for entry in Manifest::load(output)?.entries.values() {
+        let source = input.join(extract::normalize_entry(&entry.source)?);
+        let artifact = output.join(extract::normalize_entry(&entry.output)?);
+        let Ok(meta) = fs::metadata(&source) else { continue };
+        if meta.len() != entry.size || manifest::mtime_ns(&meta) != entry.mtime_ns || !artifact.is_file() {
+            continue;
+        }
+        // entries kept by `--skip-existing` come from an earlier run, so check what is on disk now
+        let actual = compressor::sha256_file(&artifact)?;
+        if actual != entry.hash {
+            return Err(CompressorError::Integrity { path: artifact, expected: entry.hash.clone(), actual });
+        }
+        if policy == SourcePolicy::RemoveVerified {
+            check_decodes_to(&artifact, compressor, entry.source_hash.clone())?;
+        }
+        replace(&source, &artifact, policy)?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// Delete the artifacts of a compressed folder once `output` holds their restored files
+///
+/// An artifact only goes once its restored file hashes to the recorded source hash, so
+/// files left alone by `--skip-existing` keep theirs. When every artifact is gone the
+/// manifest, its signature and any recovery data go too. Returns the artifacts removed.
+///
+/// `manifest` is the index of `input` the restore used.
+pub fn replace_decompressed_folder(input: &Path, output: &Path, manifest: &Manifest, policy: SourcePolicy) -> Result<usize> {
+    if policy == SourcePolicy::Keep {
+        return Ok(0);
+    }
+    let destination = SafeDestination::new(output)?;
+    let mut removed = 0;
+    for entry in manifest.entries.values() {
+        let artifact = input.join(extract::normalize_entry(&entry.output)?);
+        let restored = destination.resolve(&entry.source)?;
+        if !restored.is_file() || compressor::sha256_file(&restored)? != entry.source_hash {
+            continue;
+        }
+        replace(&artifact, &restored, policy)?;
+        remove_if_present(&parity::parity_path(&artifact))?;
+        removed += 1;
+    }
+    if removed == manifest.entries.len() {
+        for file in [MANIFEST_FILE, INDEX_FILE, SIGNATURE_FILE] {
+            remove_if_present(&input.join(file))?;
+        }
+        durable::sync_dir(input)?;
+    }
+    Ok(removed)
+}
+
+/// Hand `source`'s permissions and times to `replacement`, then delete `source`
+fn replace(source: &Path, replacement: &Path, policy: SourcePolicy) -> Result<()> {
+    if policy == SourcePolicy::Keep {
+        return Ok(());
+    }
+    let meta = fs::metadata(source).at(source)?;
+    let file = File::options().write(true).open(replacement).at(replacement)?;
+    let mut times = FileTimes::new().set_modified(meta.modified().at(source)?);
+    if let Ok(accessed) = meta.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    file.set_times(times).at(replacement)?;
+    file.set_permissions(meta.permissions()).at(replacement)?;
+    durable::sync_file(&file).at(replacement)?;
+    drop(file);
+    fs::remove_file(source).at(source)?;
+    durable::sync_dir(source.parent().unwrap_or(Path::new(""))).at(source)?;
+    Ok(())
+}
+
+fn remove_if_present(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+/// Fail with `Integrity` unless `path` decodes to content hashing to `expected`; the source is kept
+pub(crate) fn check_decodes_to(path: &Path, compressor: &dyn Compressor, expected: String) -> Result<()> {
+    let mut input = File::open(path).at(path)?;
+    let mut hasher = HashingWriter(Sha256::new());
+    compressor.decompress(&mut input, &mut hasher)
+        .map_err(|err| CompressorError::decode(path, None, err))?;
+    let actual = hex::encode(hasher.0.finalize());
+    if actual != expected {
+        return Err(CompressorError::Integrity { path: path.to_path_buf(), expected, actual });
+    }
+    Ok(())
+}
+
+struct HashingWriter(Sha256);
+
+impl Write for HashingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
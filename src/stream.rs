@@ -0,0 +1,122 @@
+use std::{
+    io::{self, BufRead, BufReader, Cursor, Read, Write},
+    sync::{Arc, Mutex},
+};
+
+use crate::compressor::{Compressor, StreamEncoder};
+use crate::error::{CompressorError, Result};
+use crate::registry::{self, Format};
+
+/// Decompressed view of a compressed stream, for code that expects `impl Read` or `impl BufRead`
+pub struct DecompressingReader<'a> {
+    inner: BufReader<Box<dyn Read + 'a>>,
+    compressor: &'a dyn Compressor,
+}
+
+impl<'a> DecompressingReader<'a> {
+    /// Decode `reader`, recognising a registered format by the magic bytes it starts with
+    ///
+    /// Fails with `UnsupportedFormat` when no known format matches. Encrypted streams carry
+    /// no recognisable magic; use [`with_compressor`](Self::with_compressor) for those.
+    pub fn new<R: Read + 'a>(mut reader: R) -> Result<Self> {
+        let mut head = Vec::new();
+        let magic_len = registry::global().magic_len();
+        reader.by_ref().take(magic_len as u64).read_to_end(&mut head)?;
+        let compressor = registry::global().for_magic(&head).map(Format::compressor)
+            .ok_or_else(|| CompressorError::UnsupportedFormat("no known magic bytes at the start of the input".to_string()))?;
+        Self::with_compressor(Cursor::new(head).chain(reader), compressor)
+    }
+
+    /// Decode `reader` with `compressor`
+    pub fn with_compressor<R: Read + 'a>(reader: R, compressor: &'a dyn Compressor) -> Result<Self> {
+        let decoder = compressor.decoder(Box::new(BufReader::new(reader)))?;
+        Ok(DecompressingReader { inner: BufReader::new(decoder), compressor })
+    }
+
+    /// Compressor decoding the stream, as detected or given
+    pub fn compressor(&self) -> &'a dyn Compressor {
+        self.compressor
+    }
+}
+
+impl Read for DecompressingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl BufRead for DecompressingReader<'_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+}
+
+/// Compresses everything written to it into `W`; call `finish` when done
+///
+/// The writer is `Send` when `W` is. Dropping it without `finish` leaves a truncated stream
+/// behind.
+pub struct CompressingWriter<'a, W: Write> {
+    encoder: Box<dyn StreamEncoder + 'a>,
+    inner: Arc<Mutex<W>>,
+}
+
+impl<'a, W: Write + Send + 'a> CompressingWriter<'a, W> {
+    /// Compress into `inner` with the format's streaming encoder
+    ///
+    /// Fails with `UnsupportedFormat` for formats that have none.
+    pub fn new(inner: W, compressor: &'a dyn Compressor, level: i32) -> Result<Self> {
+        let inner = Arc::new(Mutex::new(inner));
+        let encoder = compressor.encoder(Box::new(SharedSink(inner.clone())), level)?;
+        Ok(CompressingWriter { encoder, inner })
+    }
+
+    /// End the stream and return the inner writer
+    pub fn finish(self) -> Result<W> {
+        self.encoder.finish()?;
+        let inner = Arc::into_inner(self.inner)
+            .ok_or_else(|| io::Error::other("encoder kept hold of the output after finishing"))?;
+        Ok(inner.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner()))
+    }
+}
+
+impl<W: Write> Write for CompressingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder.flush()
+    }
+}
+
+/// Bottom of the encoder stack, writing into the inner writer `finish` hands back
+///
+/// Only the encoder stack writes through it, one call at a time, so the lock is never
+/// contended; it is there to make the writer `Send`.
+struct SharedSink<W>(Arc<Mutex<W>>);
+
+impl<W: Write> SharedSink<W> {
+    fn lock(&self) -> std::sync::MutexGuard<'_, W> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<W: Write> Write for SharedSink<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock().flush()
+    }
+}
+
+impl<W: Write + Send> StreamEncoder for SharedSink<W> {
+    fn finish(self: Box<Self>) -> Result<()> {
+        Ok(self.lock().flush()?)
+    }
+}
@@ -0,0 +1,116 @@
+#![cfg(feature = "async")]
+
+use parallel_compressor::async_io::{compress_folder, compress_stream, decompress_stream, AsyncCompressOptions};
+use parallel_compressor::cancel::CancelToken;
+use parallel_compressor::compressor::{Compressor, GzipCompressor, Lz4Compressor, ZstdCompressor};
+use parallel_compressor::error::CompressorError;
+use parallel_compressor::options::{CompressMode, CompressOptions, DecompressOptions, Filters};
+use parallel_compressor::progress::ProgressSink;
+use rayon::ThreadPoolBuilder;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tempfile::tempdir;
+
+fn payload() -> Vec<u8> {
+    (0..50_000u32).flat_map(|i| (i % 251).to_le_bytes()).collect()
+}
+
+// a single-threaded runtime: the work must not need a runtime thread of its own
+#[tokio::test]
+async fn test_stream_round_trip() {
+    let data = payload();
+    let pool = ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+    let formats: [Arc<dyn Compressor>; 3] = [Arc::new(ZstdCompressor), Arc::new(GzipCompressor), Arc::new(Lz4Compressor)];
+    for compressor in formats {
+        let mut compressed = Vec::new();
+        let stats = compress_stream(&mut &data[..], &mut compressed, compressor.clone(), 3, None).await.unwrap();
+        assert_eq!((stats.original_size, stats.compressed_size), (data.len() as u64, compressed.len() as u64));
+
+        let mut restored = Vec::new();
+        let stats = decompress_stream(&mut &compressed[..], &mut restored, compressor, Some(&pool)).await.unwrap();
+        assert_eq!(stats.original_size, data.len() as u64);
+        assert_eq!(restored, data);
+    }
+}
+
+#[tokio::test]
+async fn test_corrupt_stream_is_corrupt_data() {
+    let mut compressed = Vec::new();
+    compress_stream(&mut &payload()[..], &mut compressed, Arc::new(ZstdCompressor), 3, None).await.unwrap();
+    let middle = compressed.len() / 2;
+    compressed[middle] ^= 0xFF;
+    let err = decompress_stream(&mut &compressed[..], &mut Vec::new(), Arc::new(ZstdCompressor), None).await.unwrap_err();
+    assert!(matches!(err, CompressorError::CorruptData { path: None, .. }), "{:?}", err);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_folder_round_trip() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("in");
+    fs::create_dir_all(input.join("nested")).unwrap();
+    for i in 0..12 {
+        fs::write(input.join(format!("nested/{}.txt", i)), format!("file {} ", i).repeat(1000)).unwrap();
+    }
+    fs::write(input.join("top.bin"), payload()).unwrap();
+    fs::write(input.join("skip.log"), "noise").unwrap();
+    let output = dir.path().join("out");
+
+    let pool = Arc::new(ThreadPoolBuilder::new().num_threads(2).build().unwrap());
+    let progress = Arc::new(FinishedFiles::default());
+    let options = AsyncCompressOptions::new()
+        .format(Arc::new(GzipCompressor))
+        .level(6)
+        .thread_pool(pool)
+        .filters(Filters::new().exclude("*.log"))
+        .progress(progress.clone());
+    let report = compress_folder(&input, &output, options.clone()).await.unwrap();
+    assert_eq!(report.files.len(), 13);
+    assert_eq!(progress.0.load(Ordering::SeqCst), 13);
+    assert!(!output.join("skip.log.gz").exists());
+    assert!(report.files.windows(2).all(|w| w[0].source < w[1].source));
+    assert!(output.join("nested/3.txt.gz").is_file());
+
+    let restored = dir.path().join("restored");
+    let restore = DecompressOptions::new().run(&output, &restored).unwrap();
+    assert!(restore.verified);
+    assert_eq!(fs::read(restored.join("top.bin")).unwrap(), payload());
+
+    // a second run refuses to overwrite what the first one wrote
+    let err = compress_folder(&input, &output, options).await.unwrap_err();
+    assert!(matches!(err, CompressorError::OutputConflict(_)), "{:?}", err);
+}
+
+#[derive(Default)]
+struct FinishedFiles(AtomicUsize);
+
+impl ProgressSink for FinishedFiles {
+    fn file_finished(&self, _path: &Path, _size: u64) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[tokio::test]
+async fn test_cancelled_folder_resumes_synchronously() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("in");
+    fs::create_dir(&input).unwrap();
+    for i in 0..4 {
+        fs::write(input.join(format!("{}.txt", i)), format!("file {} ", i).repeat(1000)).unwrap();
+    }
+    let output = dir.path().join("out");
+
+    let token = CancelToken::new();
+    token.cancel();
+    let options = AsyncCompressOptions::new().format(Arc::new(GzipCompressor)).level(6).cancel(&token);
+    let err = compress_folder(&input, &output, options).await.unwrap_err();
+    assert!(matches!(err, CompressorError::Cancelled), "{:?}", err);
+
+    // the journal the async job left is one the synchronous resume accepts
+    let report = CompressOptions::new().format(&GzipCompressor).level(6).mode(CompressMode::Resume).run(&input, &output).unwrap();
+    assert_eq!(report.files.len(), 4);
+    let restored = dir.path().join("restored");
+    DecompressOptions::new().run(&output, &restored).unwrap();
+    assert_eq!(fs::read_to_string(restored.join("2.txt")).unwrap(), "file 2 ".repeat(1000));
+}
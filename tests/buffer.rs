@@ -0,0 +1,52 @@
+use parallel_compressor::buffer::{compress_bytes, compress_into, decompress_bytes, decompress_bytes_with, decompress_into};
+use parallel_compressor::compressor::{Compressor, GzipCompressor, Lz4Compressor, ZstdCompressor};
+use parallel_compressor::error::CompressorError;
+use parallel_compressor::limits::{DecompressLimits, LimitError};
+
+#[test]
+fn test_round_trip_detects_every_format() {
+    let data = b"payload ".repeat(500);
+    let formats: [&dyn Compressor; 3] = [&ZstdCompressor, &GzipCompressor, &Lz4Compressor];
+    for compressor in formats {
+        // switching levels on one thread must not leave the cached context on the old one
+        for level in [1, 9, 3] {
+            let compressed = compress_bytes(&data, compressor, level).unwrap();
+            assert!(compressed.len() < data.len(), "{}", compressor.extension());
+            assert_eq!(decompress_bytes(&compressed, &DecompressLimits::default()).unwrap(), data);
+        }
+    }
+}
+
+#[test]
+fn test_into_variants_append() {
+    let mut compressed = b"header".to_vec();
+    compress_into(b"hello", &mut compressed, &ZstdCompressor, 3).unwrap();
+    assert_eq!(&compressed[..6], b"header");
+
+    let mut restored = b"> ".to_vec();
+    decompress_into(&compressed[6..], &mut restored, &ZstdCompressor, &DecompressLimits::default()).unwrap();
+    assert_eq!(restored, b"> hello");
+}
+
+#[test]
+fn test_limits_and_bad_input() {
+    let compressed = compress_bytes(&vec![0u8; 1 << 20], &ZstdCompressor, 3).unwrap();
+    let limits = DecompressLimits { max_output_bytes: Some(1000), ..Default::default() };
+    let mut output = b"kept".to_vec();
+    let err = decompress_into(&compressed, &mut output, &ZstdCompressor, &limits).unwrap_err();
+    assert!(matches!(err, CompressorError::LimitExceeded(LimitError::OutputTooLarge { limit: 1000 })), "{:?}", err);
+    assert_eq!(output, b"kept");
+
+    let limits = DecompressLimits { max_ratio: Some(10.0), ..Default::default() };
+    let err = decompress_bytes(&compressed, &limits).unwrap_err();
+    assert!(matches!(err, CompressorError::LimitExceeded(LimitError::RatioExceeded { .. })), "{:?}", err);
+
+    let err = decompress_bytes(b"plain text", &DecompressLimits::default()).unwrap_err();
+    assert!(matches!(err, CompressorError::UnsupportedFormat(_)), "{:?}", err);
+
+    let truncated = &compressed[..compressed.len() / 2];
+    let err = decompress_bytes_with(truncated, &ZstdCompressor, &DecompressLimits::default()).unwrap_err();
+    assert!(matches!(err, CompressorError::CorruptData { path: None, .. }), "{:?}", err);
+    // the failed frame must not poison this thread's context for the next buffer
+    assert_eq!(decompress_bytes(&compressed, &DecompressLimits::default()).unwrap().len(), 1 << 20);
+}
@@ -0,0 +1,90 @@
+use parallel_compressor::cancel::{self, CancelToken};
+use parallel_compressor::compressor::{compress_path_with, compress_single_file_with, Compressor, ZstdCompressor};
+use parallel_compressor::options::{CompressOptions, DecompressOptions};
+use parallel_compressor::error::{CompressorError, Result};
+use parallel_compressor::manifest::Manifest;
+use parallel_compressor::progress::NoProgress;
+use std::fs;
+use std::io::{BufRead, Read, Write};
+use std::path::Path;
+use tempfile::tempdir;
+use walkdir::WalkDir;
+
+/// Writes some output, then fails
+struct FailingCompressor;
+
+impl Compressor for FailingCompressor {
+    fn compress(&self, _input: &mut dyn Read, output: &mut dyn Write, _level: i32) -> Result<()> {
+        output.write_all(b"half written")?;
+        Err(CompressorError::InvalidInput("disk on fire".into()))
+    }
+    fn decompress(&self, _input: &mut dyn Read, _output: &mut dyn Write) -> Result<()> {
+        Err(CompressorError::InvalidInput("disk on fire".into()))
+    }
+    fn decoder<'a>(&'a self, _input: Box<dyn BufRead + 'a>) -> Result<Box<dyn Read + 'a>> {
+        Err(CompressorError::InvalidInput("disk on fire".into()))
+    }
+    fn extension(&self) -> &'static str { "fail" }
+}
+
+fn part_files(dir: &Path) -> Vec<String> {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .map(|e| e.path().to_string_lossy().to_string())
+        .filter(|p| p.ends_with(".part"))
+        .collect()
+}
+
+// a single test: it checks that the global token is left alone, which no other test may set
+#[test]
+fn test_cancellation_and_errors_leave_no_part_files() {
+    let token = CancelToken::new();
+    let shared = token.clone();
+    shared.cancel();
+    assert!(token.check().is_err());
+    token.reset();
+    assert!(!shared.is_cancelled());
+
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("in");
+    fs::create_dir_all(&input).unwrap();
+    for i in 0..3 {
+        fs::write(input.join(format!("file{}.txt", i)), vec![b'x'; 100_000]).unwrap();
+    }
+
+    // failing compressor: error surfaces, temporary file is gone
+    let out = dir.path().join("single.fail");
+    let err = compress_single_file_with(&input.join("file0.txt"), &out, &NoProgress, 3, &FailingCompressor).unwrap_err();
+    assert!(err.to_string().contains("disk on fire"));
+    assert!(!out.exists());
+    assert!(part_files(dir.path()).is_empty());
+
+    // cancelled before the first read: nothing half-written survives, manifest lists nothing
+    token.cancel();
+    let output = dir.path().join("out");
+    let err = CompressOptions::new().threads(1).cancel(&token).run(&input, &output).unwrap_err();
+    assert!(matches!(err, CompressorError::Cancelled));
+    assert!(part_files(dir.path()).is_empty());
+    assert!(Manifest::load(&output).unwrap().entries.is_empty());
+
+    // after a reset the same job runs to completion
+    token.reset();
+    CompressOptions::new().threads(1).cancel(&token).run(&input, &output).unwrap();
+    assert_eq!(Manifest::load(&output).unwrap().entries.len(), 3);
+
+    // a restore stops the same way and leaves no partial file behind
+    token.cancel();
+    let restored = dir.path().join("restored");
+    let err = DecompressOptions::new().cancel(&token).run(&output, &restored).unwrap_err();
+    assert!(matches!(err, CompressorError::Cancelled));
+    assert!(fs::read_dir(&restored).unwrap().next().is_none());
+    token.reset();
+
+    // the global token only reaches operations it is handed to
+    cancel::global().cancel();
+    compress_path_with(input.to_str().unwrap(), dir.path().join("again").to_str().unwrap(), 1, 3, &ZstdCompressor).unwrap();
+    let err = CompressOptions::new().threads(1).cancel(cancel::global()).run(&input, dir.path().join("stopped")).unwrap_err();
+    assert!(matches!(err, CompressorError::Cancelled));
+    cancel::global().reset();
+}
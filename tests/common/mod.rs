@@ -0,0 +1,18 @@
+//! Fixtures shared by the integration tests; each test crate uses only some of them
+#![allow(dead_code)]
+
+/// xorshift64 sequence starting from `seed`, for reproducible test data
+pub fn xorshift(seed: u64) -> impl Iterator<Item = u64> {
+    let mut state = seed;
+    std::iter::repeat_with(move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    })
+}
+
+/// `size` incompressible bytes, the same for the same `seed`
+pub fn pseudo_random(size: usize, seed: u64) -> Vec<u8> {
+    xorshift(seed).take(size).map(|state| state as u8).collect()
+}
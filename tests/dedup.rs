@@ -0,0 +1,109 @@
+use parallel_compressor::cancel::CancelToken;
+use parallel_compressor::compressor::{Compressor, ZstdCompressor};
+use parallel_compressor::dedup::{compress_dedup, extract_dedup, Chunker, CHUNK_DIR, DEDUP_INDEX_FILE};
+use parallel_compressor::progress::NoProgress;
+use std::fs;
+use tempfile::tempdir;
+use walkdir::WalkDir;
+
+mod common;
+use common::pseudo_random;
+
+#[test]
+fn test_chunk_boundaries_survive_insertions() {
+    let data = pseudo_random(2 * 1024 * 1024, 42);
+    let mut shifted = b"inserted prefix".to_vec();
+    shifted.extend_from_slice(&data);
+    let chunks: Vec<Vec<u8>> = Chunker::new(&data[..]).map(Result::unwrap).collect();
+    let shifted_chunks: Vec<Vec<u8>> = Chunker::new(&shifted[..]).map(Result::unwrap).collect();
+    assert_eq!(chunks.concat(), data);
+    let shared = shifted_chunks.iter().filter(|c| chunks.contains(c)).count();
+    assert!(shared + 2 >= chunks.len(), "only {} of {} chunks shared", shared, chunks.len());
+}
+
+#[test]
+fn test_dedup_roundtrip_stores_duplicates_once() {
+    let dir = tempdir().unwrap();
+    let input_dir = dir.path().join("input");
+    let archive_dir = dir.path().join("archive");
+    let restored_dir = dir.path().join("restored");
+    fs::create_dir_all(input_dir.join("nested")).unwrap();
+    let data = pseudo_random(1024 * 1024, 7);
+    fs::write(input_dir.join("a.bin"), &data).unwrap();
+    fs::write(input_dir.join("nested/copy.bin"), &data).unwrap();
+    fs::write(input_dir.join("empty.bin"), b"").unwrap();
+
+    let stats = compress_dedup(
+        input_dir.to_str().unwrap(),
+        archive_dir.to_str().unwrap(),
+        3,
+        &ZstdCompressor,
+        &NoProgress,
+        &CancelToken::new(),
+    )
+    .unwrap();
+    assert_eq!(stats.files, 3);
+    assert_eq!(stats.original_size, 2 * data.len() as u64);
+    assert_eq!(stats.unique_size, data.len() as u64);
+    assert!((stats.dedup_ratio() - 0.5).abs() < 1e-9);
+
+    let files = extract_dedup(archive_dir.to_str().unwrap(), restored_dir.to_str().unwrap(), &NoProgress).unwrap();
+    assert_eq!(files, 3);
+    assert_eq!(fs::read(restored_dir.join("a.bin")).unwrap(), data);
+    assert_eq!(fs::read(restored_dir.join("nested/copy.bin")).unwrap(), data);
+    assert!(fs::read(restored_dir.join("empty.bin")).unwrap().is_empty());
+}
+
+#[test]
+fn test_index_keeps_odd_names_and_survives_interrupted_runs() {
+    let dir = tempdir().unwrap();
+    let input_dir = dir.path().join("input");
+    let archive_dir = dir.path().join("archive");
+    fs::create_dir_all(&input_dir).unwrap();
+    let names = ["tab\there.txt", "line\nbreak.txt", "plain.txt"];
+    for name in names {
+        fs::write(input_dir.join(name), name.repeat(100)).unwrap();
+    }
+    let (input, archive) = (input_dir.to_str().unwrap(), archive_dir.to_str().unwrap());
+    compress_dedup(input, archive, 3, &ZstdCompressor, &NoProgress, &CancelToken::new()).unwrap();
+    let index = fs::read(archive_dir.join(DEDUP_INDEX_FILE)).unwrap();
+
+    // a cancelled run into the same archive leaves the finished index as it was
+    fs::write(input_dir.join("new.txt"), "new").unwrap();
+    let token = CancelToken::new();
+    token.cancel();
+    assert!(compress_dedup(input, archive, 3, &ZstdCompressor, &NoProgress, &token).is_err());
+    assert_eq!(fs::read(archive_dir.join(DEDUP_INDEX_FILE)).unwrap(), index);
+
+    let restored = dir.path().join("restored");
+    assert_eq!(extract_dedup(archive, restored.to_str().unwrap(), &NoProgress).unwrap(), 3);
+    for name in names {
+        assert_eq!(fs::read_to_string(restored.join(name)).unwrap(), name.repeat(100));
+    }
+}
+
+#[test]
+fn test_damaged_chunks_leave_no_file_and_are_rewritten() {
+    let dir = tempdir().unwrap();
+    let input_dir = dir.path().join("input");
+    let archive_dir = dir.path().join("archive");
+    fs::create_dir_all(&input_dir).unwrap();
+    fs::write(input_dir.join("a.bin"), pseudo_random(100_000, 3)).unwrap();
+    let (input, archive) = (input_dir.to_str().unwrap(), archive_dir.to_str().unwrap());
+    compress_dedup(input, archive, 3, &ZstdCompressor, &NoProgress, &CancelToken::new()).unwrap();
+
+    // swap the chunk for a valid one holding other data
+    let chunk = WalkDir::new(archive_dir.join(CHUNK_DIR)).into_iter().map(Result::unwrap).find(|e| e.file_type().is_file()).unwrap();
+    let mut other = Vec::new();
+    ZstdCompressor.compress(&mut &b"other data"[..], &mut other, 3).unwrap();
+    fs::write(chunk.path(), other).unwrap();
+
+    let restored = dir.path().join("restored");
+    assert!(extract_dedup(archive, restored.to_str().unwrap(), &NoProgress).is_err());
+    assert_eq!(fs::read_dir(&restored).unwrap().count(), 0);
+
+    // the next run into the archive replaces the chunk instead of trusting its name
+    compress_dedup(input, archive, 3, &ZstdCompressor, &NoProgress, &CancelToken::new()).unwrap();
+    extract_dedup(archive, restored.to_str().unwrap(), &NoProgress).unwrap();
+    assert_eq!(fs::read(restored.join("a.bin")).unwrap(), pseudo_random(100_000, 3));
+}
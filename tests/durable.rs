@@ -0,0 +1,50 @@
+use parallel_compressor::compressor::{compress_path_with, ZstdCompressor};
+use parallel_compressor::manifest::{Manifest, MANIFEST_FILE};
+use parallel_compressor::parity::create_recovery_file;
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn test_folder_stats_include_sync_time() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("in");
+    fs::create_dir_all(&input).unwrap();
+    fs::write(input.join("a.txt"), vec![b'a'; 300_000]).unwrap();
+    fs::write(input.join("b.txt"), vec![b'b'; 200_000]).unwrap();
+    let output = dir.path().join("out");
+
+    let stats = compress_path_with(input.to_str().unwrap(), output.to_str().unwrap(), 1, 3, &ZstdCompressor).unwrap();
+    assert_eq!(stats.original_size, 500_000);
+    let on_disk = fs::metadata(output.join("a.txt.zst")).unwrap().len() + fs::metadata(output.join("b.txt.zst")).unwrap().len();
+    assert_eq!(stats.compressed_size, on_disk);
+    assert!(stats.sync_secs > 0.0);
+    assert!(stats.sync_secs <= stats.duration_secs);
+}
+
+#[test]
+fn test_manifest_and_parity_are_replaced_whole() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("in");
+    fs::create_dir_all(&input).unwrap();
+    fs::write(input.join("a.txt"), vec![b'a'; 50_000]).unwrap();
+    let output = dir.path().join("out");
+    compress_path_with(input.to_str().unwrap(), output.to_str().unwrap(), 1, 3, &ZstdCompressor).unwrap();
+
+    let mut manifest = Manifest::load(&output).unwrap();
+    let mut entry = manifest.entries["a.txt"].clone();
+    entry.source = "b.txt".to_string();
+    manifest.insert(entry);
+    manifest.save(&output).unwrap();
+    assert_eq!(Manifest::load(&output).unwrap().entries.len(), 2);
+    let artifact = output.join("a.txt.zst");
+    let par = create_recovery_file(&artifact, 10).unwrap();
+    let names: Vec<String> = fs::read_dir(&output).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+    assert!(names.iter().all(|name| !name.ends_with(".part")), "{:?}", names);
+    assert!(par.is_file());
+
+    // a `.part` left by a crash mid-save is not taken for a source when compressing in place
+    let tree = input.to_str().unwrap();
+    fs::write(input.join(format!("{}.part", MANIFEST_FILE)), "torn").unwrap();
+    compress_path_with(tree, tree, 1, 3, &ZstdCompressor).unwrap();
+    assert_eq!(Manifest::load(&input).unwrap().entries.keys().collect::<Vec<_>>(), ["a.txt"]);
+}
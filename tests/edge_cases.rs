@@ -1,5 +1,5 @@
-use parallel_compressor::compressor::{compress_single_file, decompress_file, sha256_file};
-use indicatif::MultiProgress;
+use parallel_compressor::compressor::{compress_single_file, decompress_file};
+use parallel_compressor::progress::NoProgress;
 use tempfile::NamedTempFile;
 use std::io::Write;
 
@@ -18,8 +18,7 @@ fn test_empty_file_all_formats() {
         let input_path = input.path();
         let compressed = NamedTempFile::new().unwrap();
         let compressed_path = compressed.path().with_extension(ext);
-        let mp = MultiProgress::new();
-        compress_single_file(input_path, &compressed_path, &mp, 3).unwrap();
+        compress_single_file(input_path, &compressed_path, &NoProgress, 3).unwrap();
         let output = NamedTempFile::new().unwrap();
         decompress_file(compressed_path.to_str().unwrap(), output.path().to_str().unwrap()).unwrap();
         let out_bytes = std::fs::read(output.path()).unwrap();
@@ -36,8 +35,7 @@ fn test_small_file_all_formats() {
         let input_path = input.path();
         let compressed = NamedTempFile::new().unwrap();
         let compressed_path = compressed.path().with_extension(ext);
-        let mp = MultiProgress::new();
-        compress_single_file(input_path, &compressed_path, &mp, 3).unwrap();
+        compress_single_file(input_path, &compressed_path, &NoProgress, 3).unwrap();
         let output = NamedTempFile::new().unwrap();
         decompress_file(compressed_path.to_str().unwrap(), output.path().to_str().unwrap()).unwrap();
         let out_bytes = std::fs::read(output.path()).unwrap();
@@ -54,8 +52,7 @@ fn test_non_utf8_file_all_formats() {
         let input_path = input.path();
         let compressed = NamedTempFile::new().unwrap();
         let compressed_path = compressed.path().with_extension(ext);
-        let mp = MultiProgress::new();
-        compress_single_file(input_path, &compressed_path, &mp, 3).unwrap();
+        compress_single_file(input_path, &compressed_path, &NoProgress, 3).unwrap();
         let output = NamedTempFile::new().unwrap();
         decompress_file(compressed_path.to_str().unwrap(), output.path().to_str().unwrap()).unwrap();
         let out_bytes = std::fs::read(output.path()).unwrap();
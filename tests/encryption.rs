@@ -0,0 +1,108 @@
+use parallel_compressor::compressor::{
+    compress_single_file_with, decompress_file_with, Compressor, GzipCompressor, Lz4Compressor, ZstdCompressor,
+};
+use parallel_compressor::crypto::{read_kdf, EncryptedCompressor, Secret};
+use parallel_compressor::error::CompressorError;
+use parallel_compressor::progress::NoProgress;
+use parallel_compressor::stream::{CompressingWriter, DecompressingReader};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use tempfile::tempdir;
+
+fn key_file(dir: &Path) -> std::path::PathBuf {
+    let path = dir.join("backup.key");
+    fs::write(&path, "00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff\n").unwrap();
+    path
+}
+
+fn make_data(size: usize) -> Vec<u8> {
+    (0..size).map(|i| ((i * 31) ^ (i >> 7)) as u8).collect()
+}
+
+fn roundtrip(compressor: &dyn Compressor, dir: &Path, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let input = dir.join("input.bin");
+    let compressed = dir.join(format!("input.{}", compressor.extension()));
+    let output = dir.join("output.bin");
+    fs::write(&input, data).unwrap();
+    compress_single_file_with(&input, &compressed, &NoProgress, 3, compressor)?;
+    decompress_file_with(compressed.to_str().unwrap(), output.to_str().unwrap(), compressor)?;
+    Ok(fs::read(&output).unwrap())
+}
+
+#[test]
+fn test_key_file_roundtrip_all_formats() {
+    let dir = tempdir().unwrap();
+    let data = make_data(300 * 1024);
+    let inners: Vec<Box<dyn Compressor>> = vec![Box::new(ZstdCompressor), Box::new(GzipCompressor), Box::new(Lz4Compressor)];
+    for inner in inners {
+        let compressor = EncryptedCompressor::new(inner, Secret::from_key_file(&key_file(dir.path())).unwrap());
+        assert!(compressor.extension().ends_with(".enc"));
+        assert_eq!(roundtrip(&compressor, dir.path(), &data).unwrap(), data);
+    }
+}
+
+#[test]
+fn test_streams_round_trip_across_chunk_boundaries() {
+    let dir = tempdir().unwrap();
+    let compressor = EncryptedCompressor::new(Box::new(Lz4Compressor), Secret::from_key_file(&key_file(dir.path())).unwrap());
+    // streams of one to several 64 KiB encrypted chunks
+    for size in [64 * 1024, 64 * 1024 + 1, 5 * 64 * 1024 + 7] {
+        let data = make_data(size);
+        let mut writer = CompressingWriter::new(Vec::new(), &compressor, 3).unwrap();
+        writer.write_all(&data).unwrap();
+        let encrypted = writer.finish().unwrap();
+        let mut restored = Vec::new();
+        DecompressingReader::with_compressor(&encrypted[..], &compressor).unwrap().read_to_end(&mut restored).unwrap();
+        assert_eq!(restored, data, "{} bytes", size);
+    }
+}
+
+#[test]
+fn test_tampering_and_truncation_are_detected() {
+    let dir = tempdir().unwrap();
+    // incompressible enough to span several 64 KiB encrypted chunks
+    let mut state = 1u32;
+    let data: Vec<u8> = (0..400 * 1024).map(|_| { state = state.wrapping_mul(1664525).wrapping_add(1013904223); (state >> 24) as u8 }).collect();
+    let input = dir.path().join("input.bin");
+    let compressed = dir.path().join("input.zst.enc");
+    let output = dir.path().join("output.bin");
+    fs::write(&input, &data).unwrap();
+    let compressor = EncryptedCompressor::new(Box::new(ZstdCompressor), Secret::from_key_file(&key_file(dir.path())).unwrap());
+    compress_single_file_with(&input, &compressed, &NoProgress, 3, &compressor).unwrap();
+    let sealed = fs::read(&compressed).unwrap();
+
+    let mut tampered = sealed.clone();
+    tampered[sealed.len() / 2] ^= 0x01;
+    fs::write(&compressed, &tampered).unwrap();
+    let err = decompress_file_with(compressed.to_str().unwrap(), output.to_str().unwrap(), &compressor).unwrap_err();
+    assert!(format!("{:#}", err).contains("authentication failed"), "{:#}", err);
+
+    // drop the whole final chunk so the stream ends on a chunk boundary
+    let header_len = 12 + 19;
+    let full_chunks = (sealed.len() - header_len) / (65536 + 16);
+    fs::write(&compressed, &sealed[..header_len + full_chunks * (65536 + 16) - (65536 + 16)]).unwrap();
+    let err = decompress_file_with(compressed.to_str().unwrap(), output.to_str().unwrap(), &compressor).unwrap_err();
+    assert!(format!("{:#}", err).contains("authentication failed"), "{:#}", err);
+}
+
+#[test]
+fn test_passphrase_roundtrip_and_wrong_passphrase() {
+    let dir = tempdir().unwrap();
+    let data = make_data(10 * 1024);
+    let compressor = EncryptedCompressor::new(Box::new(ZstdCompressor), Secret::passphrase("correct horse".into()));
+    assert_eq!(roundtrip(&compressor, dir.path(), &data).unwrap(), data);
+
+    let compressed = dir.path().join("input.zst.enc");
+    let wrong = EncryptedCompressor::new(Box::new(ZstdCompressor), Secret::passphrase("battery staple".into()));
+    let output = dir.path().join("wrong.bin");
+    assert!(decompress_file_with(compressed.to_str().unwrap(), output.to_str().unwrap(), &wrong).is_err());
+
+    // the Argon2 costs come from the file, so absurd ones are refused before deriving a key
+    let mut sealed = fs::read(&compressed).unwrap();
+    sealed[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+    fs::write(&compressed, &sealed).unwrap();
+    assert!(matches!(read_kdf(&compressed), Err(CompressorError::CorruptData { .. })));
+    let err = decompress_file_with(compressed.to_str().unwrap(), output.to_str().unwrap(), &compressor).unwrap_err();
+    assert!(format!("{:#}", err).contains("exceed the limits"), "{:#}", err);
+}
@@ -0,0 +1,110 @@
+use parallel_compressor::compressor::{
+    compress_path_with, decompress_file_limited, decompress_file_with, decompress_path_overwrite, decompress_path_with, Compressor,
+    GzipCompressor, Lz4Compressor, ZstdCompressor,
+};
+use parallel_compressor::error::CompressorError;
+use parallel_compressor::limits::DecompressLimits;
+use parallel_compressor::overwrite::OverwritePolicy;
+use parallel_compressor::progress::NoProgress;
+use std::fs;
+use std::io::{self, ErrorKind, Read};
+use tempfile::tempdir;
+
+#[test]
+fn test_missing_input_is_io_with_path() {
+    let dir = tempdir().unwrap();
+    let missing = dir.path().join("missing.zst");
+    let out = dir.path().join("out");
+    let err = decompress_file_with(missing.to_str().unwrap(), out.to_str().unwrap(), &ZstdCompressor).unwrap_err();
+    match err {
+        CompressorError::Io { path, source } => {
+            assert_eq!(path.as_deref(), Some(missing.as_path()));
+            assert_eq!(source.kind(), ErrorKind::NotFound);
+        }
+        other => panic!("expected Io, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_damaged_stream_is_corrupt_data() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("bad.zst");
+    let mut bytes = zstd::encode_all(&b"hello ".repeat(1000)[..], 3).unwrap();
+    bytes.truncate(bytes.len() / 2);
+    fs::write(&input, bytes).unwrap();
+    let out = dir.path().join("bad.out");
+    let err = decompress_file_with(input.to_str().unwrap(), out.to_str().unwrap(), &ZstdCompressor).unwrap_err();
+    assert!(
+        matches!(&err, CompressorError::CorruptData { path: Some(path), offset: Some(_), .. } if *path == input),
+        "{:?}",
+        err
+    );
+}
+
+#[test]
+fn test_format_errors_are_corrupt_data_in_every_format() {
+    let dir = tempdir().unwrap();
+    let data: Vec<u8> = (0..200_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8).collect();
+    // damage in the middle is caught by the checksum; lz4 frames carry none, so its magic is hit
+    let damage: [(&dyn Compressor, bool); 3] = [(&ZstdCompressor, true), (&GzipCompressor, true), (&Lz4Compressor, false)];
+    for (compressor, middle) in damage {
+        let mut bytes = Vec::new();
+        compressor.compress(&mut &data[..], &mut bytes, 3).unwrap();
+        let at = if middle { bytes.len() / 2 } else { 0 };
+        bytes[at] ^= 0x55;
+        let input = dir.path().join(format!("bad.{}", compressor.extension()));
+        fs::write(&input, bytes).unwrap();
+        let out = dir.path().join("bad.out");
+        let (input, out) = (input.to_str().unwrap(), out.to_str().unwrap());
+        let err = decompress_file_limited(input, out, compressor, &DecompressLimits::default(), &NoProgress).unwrap_err();
+        assert!(matches!(err, CompressorError::CorruptData { .. }), "{}: {:?}", compressor.extension(), err);
+    }
+}
+
+struct FailingReader;
+
+impl Read for FailingReader {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::other("disk went away"))
+    }
+}
+
+#[test]
+fn test_failing_input_is_not_corrupt_data() {
+    let compressors: [&dyn Compressor; 3] = [&ZstdCompressor, &GzipCompressor, &Lz4Compressor];
+    for compressor in compressors {
+        let err = compressor.decompress(&mut FailingReader, &mut Vec::new()).unwrap_err();
+        let err = CompressorError::decode("input", None, err);
+        assert!(
+            matches!(&err, CompressorError::Io { source, .. } if source.kind() == ErrorKind::Other),
+            "{}: {:?}",
+            compressor.extension(),
+            err
+        );
+    }
+}
+
+#[test]
+fn test_swapped_artifact_is_integrity_failure() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("in");
+    fs::create_dir_all(&input).unwrap();
+    fs::write(input.join("a.txt"), "a".repeat(1000)).unwrap();
+    let output = dir.path().join("out");
+    compress_path_with(input.to_str().unwrap(), output.to_str().unwrap(), 1, 3, &ZstdCompressor).unwrap();
+    // a valid stream, just not the one the manifest recorded
+    fs::write(output.join("a.txt.zst"), zstd::encode_all(&b"b"[..], 3).unwrap()).unwrap();
+
+    let restored = dir.path().join("restored");
+    let err = decompress_path_with(output.to_str().unwrap(), restored.to_str().unwrap(), &ZstdCompressor).unwrap_err();
+    assert!(matches!(err, CompressorError::Integrity { .. }), "{:?}", err);
+    // nothing is left under the real name or as a leftover part
+    assert_eq!(fs::read_dir(&restored).unwrap().count(), 0);
+
+    // a file being replaced survives a failed restore untouched
+    fs::write(restored.join("a.txt"), "previous").unwrap();
+    let (output, restored_str) = (output.to_str().unwrap(), restored.to_str().unwrap());
+    let err = decompress_path_overwrite(output, restored_str, &ZstdCompressor, OverwritePolicy::Force, &NoProgress).unwrap_err();
+    assert!(matches!(err, CompressorError::Integrity { .. }), "{:?}", err);
+    assert_eq!(fs::read_to_string(restored.join("a.txt")).unwrap(), "previous");
+}
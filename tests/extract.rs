@@ -0,0 +1,113 @@
+use parallel_compressor::cancel::CancelToken;
+use parallel_compressor::compressor::{compress_path_with, decompress_path_with, ZstdCompressor};
+use parallel_compressor::dedup::{compress_dedup, extract_dedup, DEDUP_INDEX_FILE};
+use parallel_compressor::error::CompressorError;
+use parallel_compressor::extract::{normalize_entry, SafeDestination, UnsafeEntry};
+use parallel_compressor::manifest::INDEX_FILE;
+use parallel_compressor::progress::NoProgress;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+/// Replace every occurrence of `from` in the archive's index file with `to`
+fn tamper(index: &Path, from: &str, to: &str) {
+    let text = fs::read_to_string(index).unwrap();
+    fs::write(index, text.replace(from, to)).unwrap();
+}
+
+fn unsafe_entry(err: CompressorError) -> UnsafeEntry {
+    match err {
+        CompressorError::UnsafeEntry(entry) => entry,
+        other => panic!("expected an UnsafeEntry error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_normalize_entry() {
+    assert_eq!(normalize_entry("a/./b/c.txt").unwrap(), PathBuf::from("a/b/c.txt"));
+    assert_eq!(normalize_entry("a/../b.txt").unwrap(), PathBuf::from("b.txt"));
+    assert_eq!(normalize_entry("a\\b.txt").unwrap(), PathBuf::from("a/b.txt"));
+    for bad in ["../etc/passwd", "a/../../x", "..\\..\\x", "/etc/passwd", "\\\\server\\share", "", ".", "a/..", "a\0b"] {
+        assert!(normalize_entry(bad).is_err(), "{:?} accepted", bad);
+    }
+}
+
+#[test]
+fn test_dedup_extract_refuses_escaping_entries() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("in");
+    fs::create_dir_all(&input).unwrap();
+    fs::write(input.join("victim.txt"), b"payload").unwrap();
+    let archive = dir.path().join("archive");
+    compress_dedup(input.to_str().unwrap(), archive.to_str().unwrap(), 3, &ZstdCompressor, &NoProgress, &CancelToken::new()).unwrap();
+    let index = archive.join(DEDUP_INDEX_FILE);
+    let original = fs::read_to_string(&index).unwrap();
+
+    for evil in ["../escaped.txt", "sub/../../escaped.txt", "/tmp/rustzip-absolute.txt"] {
+        fs::write(&index, &original).unwrap();
+        tamper(&index, "victim.txt", evil);
+        let out = dir.path().join("out");
+        let err = extract_dedup(archive.to_str().unwrap(), out.to_str().unwrap(), &NoProgress).unwrap_err();
+        assert!(matches!(unsafe_entry(err), UnsafeEntry::Escapes(_) | UnsafeEntry::Absolute(_)));
+        assert!(!dir.path().join("escaped.txt").exists());
+    }
+    assert!(!Path::new("/tmp/rustzip-absolute.txt").exists());
+
+    // a chunk hash is a path component too
+    fs::write(&index, &original).unwrap();
+    let hash = original.lines().nth(1).unwrap().split('\t').nth(3).unwrap().split(':').next().unwrap().to_string();
+    tamper(&index, &format!("{}:", hash), "../../../../etc/passwd:");
+    assert!(extract_dedup(archive.to_str().unwrap(), dir.path().join("out2").to_str().unwrap(), &NoProgress).is_err());
+}
+
+#[test]
+fn test_folder_decompress_refuses_escaping_sources() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("in");
+    fs::create_dir_all(input.join("docs")).unwrap();
+    fs::write(input.join("docs/readme.txt"), b"hello").unwrap();
+    let compressed = dir.path().join("compressed");
+    compress_path_with(input.to_str().unwrap(), compressed.to_str().unwrap(), 1, 3, &ZstdCompressor).unwrap();
+
+    let out = dir.path().join("out");
+    assert_eq!(decompress_path_with(compressed.to_str().unwrap(), out.to_str().unwrap(), &ZstdCompressor).unwrap(), 1);
+    assert_eq!(fs::read(out.join("docs/readme.txt")).unwrap(), b"hello");
+
+    tamper(&compressed.join(INDEX_FILE), "\tdocs/readme.txt\t", "\t../../pwned.txt\t");
+    let err = decompress_path_with(compressed.to_str().unwrap(), dir.path().join("out2").to_str().unwrap(), &ZstdCompressor)
+        .unwrap_err();
+    assert!(matches!(unsafe_entry(err), UnsafeEntry::Escapes(_)));
+    assert!(!dir.path().join("pwned.txt").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlinks_in_destination_are_not_followed() {
+    use std::os::unix::fs::symlink;
+    let dir = tempdir().unwrap();
+    let outside = dir.path().join("outside");
+    fs::create_dir_all(&outside).unwrap();
+    fs::write(outside.join("target.txt"), b"untouched").unwrap();
+    let dest_root = dir.path().join("dest");
+    fs::create_dir_all(&dest_root).unwrap();
+    // symlink-then-file: a directory link and a file link planted in the destination
+    symlink(&outside, dest_root.join("link")).unwrap();
+    symlink(outside.join("target.txt"), dest_root.join("file.txt")).unwrap();
+
+    let dest = SafeDestination::new(&dest_root).unwrap();
+    let err = dest.create_file("link/target.txt").unwrap_err();
+    assert!(matches!(unsafe_entry(err), UnsafeEntry::Symlink { .. }));
+    let err = dest.create_file("file.txt").unwrap_err();
+    assert!(matches!(unsafe_entry(err), UnsafeEntry::Symlink { .. }));
+    assert_eq!(fs::read(outside.join("target.txt")).unwrap(), b"untouched");
+
+    // special files are never replaced
+    let _socket = std::os::unix::net::UnixListener::bind(dest_root.join("socket")).unwrap();
+    let err = dest.create_file("socket").unwrap_err();
+    assert!(matches!(unsafe_entry(err), UnsafeEntry::NotRegularFile { .. }));
+
+    // ordinary nested files still work, and replace regular files
+    dest.create_file("a/b/c.txt").unwrap();
+    assert!(dest_root.join("a/b/c.txt").is_file());
+    dest.create_file("a/b/c.txt").unwrap();
+}
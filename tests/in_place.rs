@@ -0,0 +1,48 @@
+use parallel_compressor::compressor::{compress_path_overwrite, compress_path_resume, decompress_path_overwrite, ZstdCompressor};
+use parallel_compressor::manifest::{Manifest, INDEX_FILE};
+use parallel_compressor::overwrite::OverwritePolicy;
+use parallel_compressor::progress::NoProgress;
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn test_in_place_round_trip_ignores_own_outputs() {
+    let dir = tempdir().unwrap();
+    let tree = dir.path().join("tree");
+    fs::create_dir_all(tree.join("sub")).unwrap();
+    fs::write(tree.join("a.txt"), "a".repeat(2000)).unwrap();
+    fs::write(tree.join("sub/b.log"), "b".repeat(2000)).unwrap();
+    let tree_str = tree.to_str().unwrap();
+
+    compress_path_overwrite(tree_str, tree_str, 1, 3, &ZstdCompressor, OverwritePolicy::NoClobber, &NoProgress).unwrap();
+    assert!(tree.join("a.txt.zst").is_file() && tree.join("sub/b.log.zst").is_file());
+    let sources: Vec<String> = Manifest::load(&tree).unwrap().entries.into_keys().collect();
+    assert_eq!(sources, ["a.txt", "sub/b.log"]);
+
+    // a second run sees the same two sources, not its artifacts or manifest
+    let stats = compress_path_overwrite(tree_str, tree_str, 1, 3, &ZstdCompressor, OverwritePolicy::Force, &NoProgress).unwrap();
+    assert_eq!(stats.original_size, 4000);
+    assert!(!tree.join("a.txt.zst.zst").exists());
+    assert!(!tree.join(format!("{}.zst", INDEX_FILE)).exists());
+
+    fs::remove_file(tree.join("a.txt")).unwrap();
+    let restored = decompress_path_overwrite(tree_str, tree_str, &ZstdCompressor, OverwritePolicy::SkipExisting, &NoProgress).unwrap();
+    assert_eq!(restored, 1);
+    assert_eq!(fs::read_to_string(tree.join("a.txt")).unwrap(), "a".repeat(2000));
+}
+
+#[test]
+fn test_in_place_resume_keeps_foreign_part_files() {
+    let dir = tempdir().unwrap();
+    let tree = dir.path().join("tree");
+    fs::create_dir_all(&tree).unwrap();
+    fs::write(tree.join("a.txt"), "a".repeat(2000)).unwrap();
+    fs::write(tree.join("download.part"), "not ours").unwrap();
+    fs::write(tree.join("a.txt.zst.part"), "stale").unwrap();
+    let tree_str = tree.to_str().unwrap();
+
+    compress_path_resume(tree_str, tree_str, 1, 3, &ZstdCompressor, &NoProgress).unwrap();
+    assert!(!tree.join("a.txt.zst.part").exists());
+    assert_eq!(fs::read_to_string(tree.join("download.part")).unwrap(), "not ours");
+    assert!(tree.join("download.part.zst").is_file());
+}
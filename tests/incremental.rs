@@ -0,0 +1,61 @@
+use parallel_compressor::compressor::{compress_path_incremental, compress_path_with, ZstdCompressor};
+use parallel_compressor::manifest::{check_listing, read_listing, Manifest};
+use parallel_compressor::progress::NoProgress;
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn test_incremental_detects_changes() {
+    let dir = tempdir().unwrap();
+    let input_dir = dir.path().join("input");
+    let output_dir = dir.path().join("output");
+    fs::create_dir(&input_dir).unwrap();
+    for i in 0..3 {
+        fs::write(input_dir.join(format!("file{}.txt", i)), format!("testdata{}", i)).unwrap();
+    }
+    let (input, output) = (input_dir.to_str().unwrap(), output_dir.to_str().unwrap());
+    compress_path_with(input, output, 2, 3, &ZstdCompressor).unwrap();
+    assert_eq!(Manifest::load(&output_dir).unwrap().entries.len(), 3);
+
+    let summary = compress_path_incremental(input, output, 2, 3, &ZstdCompressor, false, &NoProgress).unwrap();
+    assert_eq!(summary.unchanged.len(), 3);
+    assert!(summary.added.is_empty() && summary.modified.is_empty() && summary.removed.is_empty());
+
+    fs::write(input_dir.join("file0.txt"), "changed contents").unwrap();
+    fs::write(input_dir.join("file3.txt"), "new file").unwrap();
+    fs::remove_file(input_dir.join("file1.txt")).unwrap();
+    let summary = compress_path_incremental(input, output, 2, 3, &ZstdCompressor, false, &NoProgress).unwrap();
+    assert_eq!(summary.added, vec!["file3.txt"]);
+    assert_eq!(summary.modified, vec!["file0.txt"]);
+    assert_eq!(summary.removed, vec!["file1.txt"]);
+    assert_eq!(summary.unchanged, vec!["file2.txt"]);
+    assert!(output_dir.join("file1.txt.zst").exists());
+
+    let summary = compress_path_incremental(input, output, 2, 3, &ZstdCompressor, true, &NoProgress).unwrap();
+    assert_eq!(summary.removed, vec!["file1.txt"]);
+    assert!(!output_dir.join("file1.txt.zst").exists());
+    let manifest = Manifest::load(&output_dir).unwrap();
+    assert_eq!(manifest.entries.keys().collect::<Vec<_>>(), vec!["file0.txt", "file2.txt", "file3.txt"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_names_with_tabs_and_newlines_survive_the_index() {
+    let dir = tempdir().unwrap();
+    let input_dir = dir.path().join("input");
+    let output_dir = dir.path().join("output");
+    fs::create_dir(&input_dir).unwrap();
+    let name = "tab\there\nnew\\line.txt";
+    fs::write(input_dir.join(name), "awkward name").unwrap();
+    let (input, output) = (input_dir.to_str().unwrap(), output_dir.to_str().unwrap());
+    compress_path_with(input, output, 1, 3, &ZstdCompressor).unwrap();
+
+    let manifest = Manifest::load(&output_dir).unwrap();
+    assert_eq!(manifest.entries[name].output, format!("{}.zst", name));
+    let listing = read_listing(&output_dir).unwrap();
+    assert_eq!(listing[0].1, format!("{}.zst", name));
+    assert_eq!(check_listing(&output_dir, &listing).unwrap().verified.len(), 1);
+
+    let summary = compress_path_incremental(input, output, 1, 3, &ZstdCompressor, false, &NoProgress).unwrap();
+    assert_eq!(summary.unchanged, vec![name]);
+}
@@ -1,5 +1,5 @@
 use parallel_compressor::compressor::{compress_single_file, decompress_file, sha256_file};
-use indicatif::MultiProgress;
+use parallel_compressor::progress::NoProgress;
 use std::io::Write;
 use tempfile::NamedTempFile;
 
@@ -28,8 +28,7 @@ fn test_compress_decompress_roundtrip_all_formats() {
         let input_path = input.path();
         let compressed = NamedTempFile::new().unwrap();
         let compressed_path = compressed.path().with_extension(ext);
-        let mp = MultiProgress::new();
-        compress_single_file(input_path, &compressed_path, &mp, 3).unwrap();
+        compress_single_file(input_path, &compressed_path, &NoProgress, 3).unwrap();
         let output = NamedTempFile::new().unwrap();
         decompress_file(compressed_path.to_str().unwrap(), output.path().to_str().unwrap()).unwrap();
         let out_bytes = std::fs::read(output.path()).unwrap();
@@ -1,8 +1,8 @@
-use indicatif::MultiProgress;
 use tempfile::tempdir;
 use std::fs::{self, File};
 use std::io::Write;
-use parallel_compressor::compressor::{compress_path, decompress_file, sha256_file};
+use parallel_compressor::compressor::{compress_path, decompress_file, decompress_path_with, sha256_file, ZstdCompressor};
+use parallel_compressor::progress::NoProgress;
 
 #[test]
 fn test_folder_compress_and_manifest() {
@@ -15,9 +15,13 @@ fn test_folder_compress_and_manifest() {
         let mut f = File::create(input_dir.join(format!("file{}.txt", i))).unwrap();
         write!(f, "testdata{}", i).unwrap();
     }
-    compress_path(input_dir.to_str().unwrap(), output_dir.to_str().unwrap(), 2, 3).unwrap();
+    let report = compress_path(input_dir.to_str().unwrap(), output_dir.to_str().unwrap(), 2, 3, &NoProgress).unwrap();
+    assert_eq!(report.files.len(), 3);
+    assert_eq!(report.verified, 3);
+    assert_eq!(report.stats.original_size, 27);
     // Check manifest exists
     let manifest = output_dir.join("manifest-sha256.txt");
+    assert_eq!(report.manifest.as_deref(), Some(manifest.as_path()));
     assert!(manifest.exists());
     // Check hashes in manifest match actual files
     let manifest_str = fs::read_to_string(&manifest).unwrap();
@@ -28,4 +32,25 @@ fn test_folder_compress_and_manifest() {
         let file_path = output_dir.join(file);
         assert_eq!(sha256_file(&file_path).unwrap(), hash);
     }
+    // the folder carries a full manifest, so it restores like any other compressed folder
+    let restored = dir.path().join("restored");
+    assert_eq!(decompress_path_with(output_dir.to_str().unwrap(), restored.to_str().unwrap(), &ZstdCompressor).unwrap(), 3);
+    assert_eq!(fs::read(restored.join("file1.txt")).unwrap(), b"testdata1");
+}
+
+#[test]
+fn test_decompress_report() {
+    let dir = tempdir().unwrap();
+    let input_dir = dir.path().join("input");
+    let output_dir = dir.path().join("output");
+    fs::create_dir(&input_dir).unwrap();
+    fs::write(input_dir.join("file.txt"), "x".repeat(5000)).unwrap();
+    compress_path(input_dir.to_str().unwrap(), output_dir.to_str().unwrap(), 1, 3, &NoProgress).unwrap();
+
+    let out = dir.path().join("file.txt");
+    let report = decompress_file(output_dir.join("file.txt.zst").to_str().unwrap(), out.to_str().unwrap()).unwrap();
+    assert_eq!(report.decompressed_size, 5000);
+    assert!(report.compressed_size > 0 && report.compressed_size < 5000);
+    // the manifest lists artifacts, not restored names
+    assert!(!report.verified);
 }
@@ -0,0 +1,89 @@
+use parallel_compressor::cancel::CancelToken;
+use parallel_compressor::compressor::{compress_path_with, decompress_file_limited, Compressor, GzipCompressor, ZstdCompressor};
+use parallel_compressor::dedup::compress_dedup;
+use parallel_compressor::error::CompressorError;
+use parallel_compressor::limits::{DecompressLimits, LimitError};
+use parallel_compressor::options::DecompressOptions;
+use parallel_compressor::progress::NoProgress;
+use std::fs;
+use tempfile::tempdir;
+
+fn write_compressed(path: &std::path::Path, compressor: &dyn Compressor, data: &[u8]) {
+    let mut compressed = Vec::new();
+    compressor.compress(&mut &data[..], &mut compressed, 9).unwrap();
+    fs::write(path, compressed).unwrap();
+}
+
+#[test]
+fn test_output_and_ratio_limits_stop_a_bomb() {
+    let dir = tempdir().unwrap();
+    let output = dir.path().join("bomb.out");
+    let zeros = vec![0u8; 64 * 1024 * 1024];
+    let compressors: [&dyn Compressor; 2] = [&ZstdCompressor, &GzipCompressor];
+    for compressor in compressors {
+        let input = dir.path().join(format!("bomb.{}", compressor.extension()));
+        write_compressed(&input, compressor, &zeros);
+        let (input, out) = (input.to_str().unwrap(), output.to_str().unwrap());
+
+        let limits = DecompressLimits { max_output_bytes: Some(1024 * 1024), ..Default::default() };
+        let err = decompress_file_limited(input, out, compressor, &limits, &NoProgress).unwrap_err();
+        assert!(matches!(err, CompressorError::LimitExceeded(LimitError::OutputTooLarge { limit: 1048576 })));
+        assert!(!output.exists());
+
+        let limits = DecompressLimits { max_ratio: Some(100.0), ..Default::default() };
+        let err = decompress_file_limited(input, out, compressor, &limits, &NoProgress).unwrap_err();
+        assert!(matches!(err, CompressorError::LimitExceeded(LimitError::RatioExceeded { .. })));
+
+        // generous limits let it through
+        let limits = DecompressLimits { max_output_bytes: Some(zeros.len() as u64), ..Default::default() };
+        decompress_file_limited(input, out, compressor, &limits, &NoProgress).unwrap();
+        assert_eq!(fs::metadata(&output).unwrap().len(), zeros.len() as u64);
+    }
+}
+
+#[test]
+fn test_window_limit() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("big.zst");
+    let output = dir.path().join("big.out");
+    let data: Vec<u8> = (0..4 * 1024 * 1024u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+    write_compressed(&input, &ZstdCompressor, &data);
+    let (input, out) = (input.to_str().unwrap(), output.to_str().unwrap());
+
+    let limits = DecompressLimits { max_window_log: Some(20), ..Default::default() };
+    let err = decompress_file_limited(input, out, &ZstdCompressor, &limits, &NoProgress).unwrap_err();
+    assert!(matches!(err, CompressorError::LimitExceeded(LimitError::WindowTooLarge { max_window_log: 20 })));
+
+    let limits = DecompressLimits { max_window_log: Some(23), ..Default::default() };
+    decompress_file_limited(input, out, &ZstdCompressor, &limits, &NoProgress).unwrap();
+    assert_eq!(fs::read(&output).unwrap(), data);
+}
+
+#[test]
+fn test_limits_apply_to_folders_and_dedup_archives() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("small.txt"), "tiny").unwrap();
+    fs::write(input.join("zeros.bin"), vec![0u8; 4 * 1024 * 1024]).unwrap();
+    let folder = dir.path().join("folder");
+    let archive = dir.path().join("archive");
+    compress_path_with(input.to_str().unwrap(), folder.to_str().unwrap(), 1, 3, &ZstdCompressor).unwrap();
+    compress_dedup(input.to_str().unwrap(), archive.to_str().unwrap(), 3, &ZstdCompressor, &NoProgress, &CancelToken::new()).unwrap();
+
+    for compressed in [&folder, &archive] {
+        let name = compressed.file_name().unwrap().to_string_lossy();
+        let limits = DecompressLimits { max_output_bytes: Some(1024 * 1024), ..Default::default() };
+        let err = DecompressOptions::new().limits(limits).run(compressed, dir.path().join(format!("capped-{}", name))).unwrap_err();
+        assert!(matches!(err, CompressorError::LimitExceeded(LimitError::OutputTooLarge { .. })), "{:?}", err);
+
+        let limits = DecompressLimits { max_ratio: Some(50.0), ..Default::default() };
+        let err = DecompressOptions::new().limits(limits).run(compressed, dir.path().join(format!("ratio-{}", name))).unwrap_err();
+        assert!(matches!(err, CompressorError::LimitExceeded(LimitError::RatioExceeded { .. })), "{:?}", err);
+
+        // the limit is per file, so one just above the largest lets everything through
+        let limits = DecompressLimits { max_output_bytes: Some(4 * 1024 * 1024), ..Default::default() };
+        let restored = dir.path().join(format!("restored-{}", name));
+        assert_eq!(DecompressOptions::new().limits(limits).run(compressed, &restored).unwrap().files, 2);
+    }
+}
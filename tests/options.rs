@@ -0,0 +1,122 @@
+use parallel_compressor::compressor::{Compressor, GzipCompressor, Lz4Compressor, ZstdCompressor};
+use parallel_compressor::error::{CompressorError, Result};
+use parallel_compressor::options::{CompressOptions, DecompressOptions, Filters, HashCheck};
+use parallel_compressor::overwrite::OverwritePolicy;
+use parallel_compressor::source::SourcePolicy;
+use rayon::ThreadPoolBuilder;
+use std::fs;
+use std::io::{BufRead, Read, Write};
+use std::sync::Mutex;
+use tempfile::tempdir;
+
+#[test]
+fn test_file_round_trip_detects_format() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("notes.txt");
+    fs::write(&input, "notes ".repeat(1000)).unwrap();
+
+    let report = CompressOptions::new()
+        .format(&GzipCompressor)
+        .level(6)
+        .threads(1)
+        .hash(HashCheck::Verify)
+        .run(&input, dir.path())
+        .unwrap();
+    let artifact = dir.path().join("notes.txt.gz");
+    assert_eq!(report.files.len(), 1);
+    assert_eq!(report.files[0].output, artifact);
+    assert_eq!(report.verified, 1);
+    assert!(report.manifest.is_none());
+
+    let restored = dir.path().join("restored.txt");
+    let report = DecompressOptions::new().run(&artifact, &restored).unwrap();
+    assert_eq!((report.files, report.decompressed_size), (1, 6000));
+    assert_eq!(fs::read(&restored).unwrap(), fs::read(&input).unwrap());
+}
+
+#[test]
+fn test_folder_filters_and_source_removal() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("in");
+    fs::create_dir_all(input.join("logs")).unwrap();
+    fs::write(input.join("a.txt"), "a".repeat(100)).unwrap();
+    fs::write(input.join("logs/b.log"), "b".repeat(100)).unwrap();
+    fs::write(input.join("logs/c.tmp"), "c".repeat(100)).unwrap();
+    let output = dir.path().join("out");
+
+    let report = CompressOptions::new()
+        .format(&Lz4Compressor)
+        .filters(Filters::new().include("*.txt").include("logs/**").exclude("*.tmp"))
+        .remove_source(SourcePolicy::RemoveVerified)
+        .run(&input, &output)
+        .unwrap();
+    assert_eq!(report.files.len(), 2);
+    assert_eq!(report.stats.original_size, 200);
+    assert_eq!(report.removed_sources, 2);
+    assert!(output.join("logs/b.log.lz4").is_file());
+    assert!(!output.join("logs/c.tmp.lz4").exists());
+    assert!(input.join("logs/c.tmp").is_file() && !input.join("a.txt").exists());
+
+    let restored = dir.path().join("restored");
+    let report = DecompressOptions::new().run(&output, &restored).unwrap();
+    assert_eq!((report.files, report.skipped_files), (2, 0));
+    assert!(report.verified);
+    assert_eq!(fs::read_to_string(restored.join("logs/b.log")).unwrap(), "b".repeat(100));
+
+    let report = DecompressOptions::new().overwrite(OverwritePolicy::SkipExisting).run(&output, &restored).unwrap();
+    assert_eq!((report.files, report.skipped_files), (0, 2));
+}
+
+#[test]
+fn test_bad_pattern_is_invalid_input() {
+    let dir = tempdir().unwrap();
+    let err = CompressOptions::new()
+        .filters(Filters::new().exclude("a[b"))
+        .run(dir.path(), dir.path().join("out"))
+        .unwrap_err();
+    assert!(matches!(err, CompressorError::InvalidInput(_)), "{:?}", err);
+}
+
+/// zstd that records the size of the rayon pool each file was compressed on
+#[derive(Default)]
+struct PoolProbe {
+    pool_sizes: Mutex<Vec<usize>>,
+}
+
+impl Compressor for PoolProbe {
+    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write, level: i32) -> Result<()> {
+        self.pool_sizes.lock().unwrap().push(rayon::current_num_threads());
+        ZstdCompressor.compress(input, output, level)
+    }
+    fn decompress(&self, input: &mut dyn Read, output: &mut dyn Write) -> Result<()> {
+        ZstdCompressor.decompress(input, output)
+    }
+    fn decoder<'a>(&'a self, input: Box<dyn BufRead + 'a>) -> Result<Box<dyn Read + 'a>> {
+        ZstdCompressor.decoder(input)
+    }
+    fn extension(&self) -> &'static str { "zst" }
+}
+
+#[test]
+fn test_each_run_uses_its_own_pool() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("in");
+    fs::create_dir_all(&input).unwrap();
+    for name in ["a", "b", "c", "d"] {
+        fs::write(input.join(name), name.repeat(1000)).unwrap();
+    }
+
+    // a later run with another thread count must not reuse the first run's pool
+    for threads in [1, 3] {
+        let probe = PoolProbe::default();
+        let output = dir.path().join(format!("out{}", threads));
+        CompressOptions::new().format(&probe).threads(threads).run(&input, &output).unwrap();
+        assert_eq!(*probe.pool_sizes.lock().unwrap(), [threads; 4]);
+    }
+
+    let pool = ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+    let probe = PoolProbe::default();
+    let output = dir.path().join("shared");
+    CompressOptions::new().format(&probe).threads(8).thread_pool(&pool).run(&input, &output).unwrap();
+    assert_eq!(*probe.pool_sizes.lock().unwrap(), [2; 4]);
+}
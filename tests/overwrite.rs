@@ -0,0 +1,99 @@
+use parallel_compressor::cancel::CancelToken;
+use parallel_compressor::compressor::{compress_path_overwrite, compress_path_with, decompress_path_overwrite, ZstdCompressor};
+use parallel_compressor::dedup::compress_dedup;
+use parallel_compressor::error::CompressorError;
+use parallel_compressor::options::DecompressOptions;
+use parallel_compressor::overwrite::{plan_outputs, OutputConflict, OverwritePolicy};
+use parallel_compressor::progress::NoProgress;
+use std::fs;
+use std::path::PathBuf;
+use tempfile::tempdir;
+
+#[test]
+fn test_same_stem_inputs_get_distinct_outputs() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("in");
+    fs::create_dir_all(&input).unwrap();
+    fs::write(input.join("a.txt"), "text").unwrap();
+    fs::write(input.join("a.log"), "log").unwrap();
+    let output = dir.path().join("out");
+
+    compress_path_with(input.to_str().unwrap(), output.to_str().unwrap(), 1, 3, &ZstdCompressor).unwrap();
+    assert!(output.join("a.txt.zst").is_file());
+    assert!(output.join("a.log.zst").is_file());
+
+    let restored = dir.path().join("restored");
+    decompress_path_overwrite(output.to_str().unwrap(), restored.to_str().unwrap(), &ZstdCompressor, OverwritePolicy::NoClobber, &NoProgress).unwrap();
+    assert_eq!(fs::read_to_string(restored.join("a.txt")).unwrap(), "text");
+    assert_eq!(fs::read_to_string(restored.join("a.log")).unwrap(), "log");
+}
+
+#[test]
+fn test_existing_outputs_follow_policy() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("in");
+    fs::create_dir_all(&input).unwrap();
+    fs::write(input.join("a.txt"), "a".repeat(1000)).unwrap();
+    fs::write(input.join("b.txt"), "b".repeat(1000)).unwrap();
+    let output = dir.path().join("out");
+    fs::create_dir_all(&output).unwrap();
+    fs::write(output.join("b.txt.zst"), b"keep me").unwrap();
+    let (input, output) = (input.to_str().unwrap(), output.to_str().unwrap());
+
+    let err = compress_path_with(input, output, 1, 3, &ZstdCompressor).unwrap_err();
+    assert!(matches!(err, CompressorError::OutputConflict(OutputConflict::Exists { existing }) if existing.len() == 1));
+    // refused before any work: nothing else was written
+    assert!(!PathBuf::from(output).join("a.txt.zst").exists());
+    assert_eq!(fs::read(PathBuf::from(output).join("b.txt.zst")).unwrap(), b"keep me");
+
+    let stats = compress_path_overwrite(input, output, 1, 3, &ZstdCompressor, OverwritePolicy::SkipExisting, &NoProgress).unwrap();
+    assert_eq!(stats.skipped_files, 1);
+    assert!(PathBuf::from(output).join("a.txt.zst").is_file());
+    assert_eq!(fs::read(PathBuf::from(output).join("b.txt.zst")).unwrap(), b"keep me");
+
+    let stats = compress_path_overwrite(input, output, 1, 3, &ZstdCompressor, OverwritePolicy::Force, &NoProgress).unwrap();
+    assert_eq!(stats.skipped_files, 0);
+    assert_eq!(zstd::decode_all(&fs::read(PathBuf::from(output).join("b.txt.zst")).unwrap()[..]).unwrap(), "b".repeat(1000).as_bytes());
+}
+
+#[test]
+fn test_plan_rejects_collisions_and_input_overwrites() {
+    let jobs = vec![
+        (PathBuf::from("in/a"), PathBuf::from("out/a.zst")),
+        (PathBuf::from("in/b"), PathBuf::from("out/a.zst")),
+    ];
+    assert!(matches!(plan_outputs(jobs, OverwritePolicy::Force), Err(OutputConflict::Collision { .. })));
+
+    let jobs = vec![
+        (PathBuf::from("in/a"), PathBuf::from("in/a.zst")),
+        (PathBuf::from("in/a.zst"), PathBuf::from("in/a.zst.zst")),
+    ];
+    assert!(matches!(plan_outputs(jobs, OverwritePolicy::Force), Err(OutputConflict::OverwritesInput { .. })));
+}
+
+#[test]
+fn test_dedup_extraction_follows_policy() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("in");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("a.txt"), "new a").unwrap();
+    fs::write(input.join("b.txt"), "new b").unwrap();
+    let archive = dir.path().join("archive");
+    compress_dedup(input.to_str().unwrap(), archive.to_str().unwrap(), 3, &ZstdCompressor, &NoProgress, &CancelToken::new()).unwrap();
+    let restored = dir.path().join("restored");
+    fs::create_dir(&restored).unwrap();
+    fs::write(restored.join("a.txt"), "mine").unwrap();
+
+    let err = DecompressOptions::new().run(&archive, &restored).unwrap_err();
+    assert!(matches!(err, CompressorError::OutputConflict(OutputConflict::Exists { .. })));
+    assert_eq!(fs::read_to_string(restored.join("a.txt")).unwrap(), "mine");
+    assert!(!restored.join("b.txt").exists());
+
+    let report = DecompressOptions::new().overwrite(OverwritePolicy::SkipExisting).run(&archive, &restored).unwrap();
+    assert_eq!((report.files, report.skipped_files), (1, 1));
+    assert_eq!(fs::read_to_string(restored.join("a.txt")).unwrap(), "mine");
+    assert_eq!(fs::read_to_string(restored.join("b.txt")).unwrap(), "new b");
+
+    DecompressOptions::new().overwrite(OverwritePolicy::Force).run(&archive, &restored).unwrap();
+    assert_eq!(fs::read_to_string(restored.join("a.txt")).unwrap(), "new a");
+}
@@ -0,0 +1,81 @@
+use parallel_compressor::compressor::{compress_path_with, ZstdCompressor};
+use parallel_compressor::parity::{create_recovery_file, parity_path, protect_path, repair_file, repair_path};
+use std::fs;
+use tempfile::tempdir;
+
+mod common;
+
+fn pseudo_random(size: usize) -> Vec<u8> {
+    common::pseudo_random(size, 0x2545_f491_4f6c_dd1d)
+}
+
+#[test]
+fn test_repair_rebuilds_damaged_blocks() {
+    let dir = tempdir().unwrap();
+    let artifact = dir.path().join("data.zst");
+    let data = pseudo_random(3 * 1024 * 1024 + 123);
+    fs::write(&artifact, &data).unwrap();
+    create_recovery_file(&artifact, 10).unwrap();
+    assert!(parity_path(&artifact).exists());
+    assert!(repair_file(&artifact).unwrap().was_intact());
+
+    // a contiguous burst of bit rot plus a flipped byte near the end
+    let mut damaged = data.clone();
+    damaged[100_000..300_000].fill(0xAA);
+    let last = damaged.len() - 10;
+    damaged[last] ^= 0xFF;
+    fs::write(&artifact, &damaged).unwrap();
+    let report = repair_file(&artifact).unwrap();
+    assert!(report.damaged_blocks > 1);
+    assert_eq!(fs::read(&artifact).unwrap(), data);
+
+    // truncation is repaired as well
+    fs::write(&artifact, &data[..data.len() - 5000]).unwrap();
+    let report = repair_file(&artifact).unwrap();
+    assert!(report.resized);
+    assert_eq!(fs::read(&artifact).unwrap(), data);
+}
+
+#[test]
+fn test_repair_fails_when_damage_exceeds_redundancy() {
+    let dir = tempdir().unwrap();
+    let artifact = dir.path().join("data.zst");
+    let data = pseudo_random(64 * 1024);
+    fs::write(&artifact, &data).unwrap();
+    create_recovery_file(&artifact, 5).unwrap();
+    let mut damaged = data.clone();
+    damaged[..32 * 1024].fill(0);
+    fs::write(&artifact, &damaged).unwrap();
+    assert!(repair_file(&artifact).is_err());
+}
+
+#[test]
+fn test_protect_and_repair_folder() {
+    let dir = tempdir().unwrap();
+    let input_dir = dir.path().join("input");
+    let output_dir = dir.path().join("output");
+    fs::create_dir(&input_dir).unwrap();
+    for i in 0..3 {
+        fs::write(input_dir.join(format!("file{}.bin", i)), pseudo_random(20_000 + i)).unwrap();
+    }
+    compress_path_with(input_dir.to_str().unwrap(), output_dir.to_str().unwrap(), 2, 3, &ZstdCompressor).unwrap();
+    assert_eq!(protect_path(&output_dir, 20).unwrap(), 3);
+    // unchanged artifacts keep their recovery data
+    assert_eq!(protect_path(&output_dir, 20).unwrap(), 0);
+    // asking for a different redundancy regenerates them all
+    let par = parity_path(&output_dir.join("file0.bin.zst"));
+    let before = fs::metadata(&par).unwrap().len();
+    assert_eq!(protect_path(&output_dir, 40).unwrap(), 3);
+    assert!(fs::metadata(&par).unwrap().len() > before);
+    assert_eq!(protect_path(&output_dir, 40).unwrap(), 0);
+
+    let artifact = output_dir.join("file1.bin.zst");
+    let original = fs::read(&artifact).unwrap();
+    let mut damaged = original.clone();
+    damaged[1000] ^= 0x55;
+    fs::write(&artifact, &damaged).unwrap();
+    let reports = repair_path(&output_dir).unwrap();
+    assert_eq!(reports.len(), 3);
+    assert_eq!(reports.iter().filter(|(_, r)| !r.was_intact()).count(), 1);
+    assert_eq!(fs::read(&artifact).unwrap(), original);
+}
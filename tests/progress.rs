@@ -0,0 +1,51 @@
+use parallel_compressor::compressor::{compress_path_overwrite, decompress_path_overwrite, ZstdCompressor};
+use parallel_compressor::overwrite::OverwritePolicy;
+use parallel_compressor::progress::{ChannelProgress, ProgressEvent};
+use std::fs;
+use std::sync::mpsc;
+use tempfile::tempdir;
+
+#[test]
+fn test_channel_reports_every_file() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("in");
+    fs::create_dir_all(&input).unwrap();
+    fs::write(input.join("a.txt"), "a".repeat(3000)).unwrap();
+    fs::write(input.join("b.txt"), "b".repeat(1000)).unwrap();
+    let output = dir.path().join("out");
+
+    let (tx, rx) = mpsc::channel();
+    let (input_str, output_str) = (input.to_str().unwrap(), output.to_str().unwrap());
+    compress_path_overwrite(input_str, output_str, 1, 3, &ZstdCompressor, OverwritePolicy::NoClobber, &ChannelProgress::new(tx)).unwrap();
+    let events: Vec<ProgressEvent> = rx.iter().collect();
+
+    assert_eq!(events.first(), Some(&ProgressEvent::JobStarted { files: 2, bytes: 4000 }));
+    assert_eq!(events.last(), Some(&ProgressEvent::JobFinished { error: None }));
+    let finished: Vec<_> = events.iter().filter_map(|e| match e {
+        ProgressEvent::FileFinished { path, size } => Some((path.clone(), *size)),
+        _ => None,
+    }).collect();
+    assert_eq!(finished, [(input.join("a.txt"), 3000), (input.join("b.txt"), 1000)]);
+    assert!(events.contains(&ProgressEvent::FileProgress { path: input.join("a.txt"), done: 3000 }));
+}
+
+#[test]
+fn test_channel_reports_failure() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("in");
+    fs::create_dir_all(&input).unwrap();
+    fs::write(input.join("a.txt"), "a".repeat(3000)).unwrap();
+    let output = dir.path().join("out");
+    let (input_str, output_str) = (input.to_str().unwrap(), output.to_str().unwrap());
+    compress_path_overwrite(input_str, output_str, 1, 3, &ZstdCompressor, OverwritePolicy::NoClobber, &ChannelProgress::new(mpsc::channel().0)).unwrap();
+    fs::write(output.join("a.txt.zst"), b"garbage").unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let restored = dir.path().join("restored");
+    let progress = ChannelProgress::new(tx);
+    assert!(decompress_path_overwrite(output_str, restored.to_str().unwrap(), &ZstdCompressor, OverwritePolicy::NoClobber, &progress).is_err());
+    drop(progress);
+    let events: Vec<ProgressEvent> = rx.iter().collect();
+    assert!(events.iter().any(|e| matches!(e, ProgressEvent::FileFailed { path, .. } if *path == output.join("a.txt.zst"))));
+    assert!(matches!(events.last(), Some(ProgressEvent::JobFinished { error: Some(_) })));
+}
@@ -0,0 +1,59 @@
+use age::secrecy::ExposeSecret;
+use parallel_compressor::compressor::{compress_path_with, compress_single_file_with, decompress_file_with, ZstdCompressor};
+use parallel_compressor::manifest::Manifest;
+use parallel_compressor::progress::NoProgress;
+use parallel_compressor::recipients::{read_identity_file, read_recipients_file, RecipientCompressor};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn test_recipients_roundtrip_and_age_compatibility() {
+    let dir = tempdir().unwrap();
+    let alice = age::x25519::Identity::generate();
+    let bob = age::x25519::Identity::generate();
+    let recipients = dir.path().join("recipients.txt");
+    fs::write(&recipients, format!("# backup readers\n{}\n\n{}\n", alice.to_public(), bob.to_public())).unwrap();
+    let bob_key = dir.path().join("bob.key");
+    fs::write(&bob_key, format!("{}\n", bob.to_string().expose_secret())).unwrap();
+
+    let data = b"offsite backup payload ".repeat(5000);
+    let input = dir.path().join("input.txt");
+    let compressed = dir.path().join("input.zst.age");
+    fs::write(&input, &data).unwrap();
+    let writer = RecipientCompressor::for_recipients(Box::new(ZstdCompressor), read_recipients_file(&recipients).unwrap());
+    compress_single_file_with(&input, &compressed, &NoProgress, 3, &writer).unwrap();
+
+    // bob decrypts through the library
+    let reader = RecipientCompressor::for_identities(Box::new(ZstdCompressor), read_identity_file(&bob_key).unwrap());
+    let output = dir.path().join("output.txt");
+    decompress_file_with(compressed.to_str().unwrap(), output.to_str().unwrap(), &reader).unwrap();
+    assert_eq!(fs::read(&output).unwrap(), data);
+
+    // alice decrypts it as a plain age file, leaving an ordinary zstd stream
+    let zst = age::decrypt(&alice, &fs::read(&compressed).unwrap()).unwrap();
+    assert_eq!(zstd::decode_all(&zst[..]).unwrap(), data);
+
+    // someone else cannot
+    let mallory = dir.path().join("mallory.key");
+    fs::write(&mallory, format!("{}\n", age::x25519::Identity::generate().to_string().expose_secret())).unwrap();
+    let reader = RecipientCompressor::for_identities(Box::new(ZstdCompressor), read_identity_file(&mallory).unwrap());
+    assert!(decompress_file_with(compressed.to_str().unwrap(), output.to_str().unwrap(), &reader).is_err());
+}
+
+#[test]
+fn test_manifest_records_encrypted_artifacts() {
+    let dir = tempdir().unwrap();
+    let input_dir = dir.path().join("input");
+    let output_dir = dir.path().join("output");
+    fs::create_dir(&input_dir).unwrap();
+    fs::write(input_dir.join("a.txt"), "secret a").unwrap();
+    let identity = age::x25519::Identity::generate();
+    let compressor = RecipientCompressor::for_recipients(Box::new(ZstdCompressor), vec![identity.to_public()]);
+    compress_path_with(input_dir.to_str().unwrap(), output_dir.to_str().unwrap(), 2, 3, &compressor).unwrap();
+
+    let manifest = Manifest::load(&output_dir).unwrap();
+    let entry = &manifest.entries["a.txt"];
+    assert_eq!(entry.output, "a.txt.zst.age");
+    assert_eq!(entry.encryption.as_deref(), Some("age-x25519"));
+    assert!(output_dir.join("a.txt.zst.age").exists());
+}
@@ -0,0 +1,128 @@
+use parallel_compressor::buffer::{compress_bytes, decompress_bytes};
+use parallel_compressor::compressor::{Compressor, GzipCompressor, Lz4Compressor, ZstdCompressor};
+use parallel_compressor::crypto::{EncryptedCompressor, Secret};
+use parallel_compressor::error::{CompressorError, Result};
+use parallel_compressor::limits::DecompressLimits;
+use parallel_compressor::options::{CompressOptions, DecompressOptions};
+use parallel_compressor::registry::{self, Format, FormatRegistry};
+use parallel_compressor::stream::DecompressingReader;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use tempfile::tempdir;
+
+const MAGIC: &[u8] = b"XOR!";
+
+/// Toy third-party format: a magic header, then every byte XORed with 0x5A
+struct XorCompressor;
+
+struct XorWriter<'a>(&'a mut dyn Write);
+
+impl Write for XorWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write_all(&buf.iter().map(|b| b ^ 0x5A).collect::<Vec<_>>())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+struct XorReader<R>(R);
+
+impl<R: Read> Read for XorReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.0.read(buf)?;
+        buf[..n].iter_mut().for_each(|b| *b ^= 0x5A);
+        Ok(n)
+    }
+}
+
+impl Compressor for XorCompressor {
+    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write, _level: i32) -> Result<()> {
+        output.write_all(MAGIC)?;
+        io::copy(input, &mut XorWriter(output))?;
+        Ok(())
+    }
+    fn decompress(&self, input: &mut dyn Read, output: &mut dyn Write) -> Result<()> {
+        io::copy(&mut self.decoder(Box::new(io::BufReader::new(input)))?, output)?;
+        Ok(())
+    }
+    fn decoder<'a>(&'a self, mut input: Box<dyn BufRead + 'a>) -> Result<Box<dyn Read + 'a>> {
+        let mut magic = [0u8; 4];
+        input.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(CompressorError::InvalidInput("not xor data".into()));
+        }
+        Ok(Box::new(XorReader(input)))
+    }
+    fn extension(&self) -> &'static str { "xor" }
+    fn magic(&self) -> &'static [u8] { MAGIC }
+}
+
+#[test]
+fn test_builtins_by_name_extension_and_magic() {
+    let registry = FormatRegistry::with_builtins();
+    let names: Vec<&str> = registry.formats().map(Format::name).collect();
+    assert_eq!(names, ["zstd", "gzip", "lz4"]);
+    assert_eq!(registry.get("ZST").unwrap().name(), "zstd");
+    assert_eq!(registry.get("gz").unwrap().extension(), "gz");
+    assert_eq!(registry.for_extension("lz4").unwrap().name(), "lz4");
+    assert_eq!(registry.for_magic(&[0x1F, 0x8B, 0x08, 0x00]).unwrap().name(), "gzip");
+    assert!(registry.get("brotli").is_none() && registry.for_magic(b"plain").is_none());
+
+    let gzip = registry.get("gzip").unwrap();
+    assert_eq!((gzip.compressor().default_level(), gzip.check_level(9).unwrap()), (6, 9));
+    for level in [-1, 10, 22] {
+        assert!(matches!(gzip.check_level(level), Err(CompressorError::InvalidInput(_))));
+    }
+    assert!(registry.get("lz4").unwrap().check_level(1).is_err());
+    assert!(registry.help().contains("gzip (gz): levels 0 to 9, default 6"));
+}
+
+#[test]
+fn test_capabilities_and_level_clamping() {
+    assert_eq!(ZstdCompressor.level_range(), Some(zstd::compression_level_range()));
+    assert_eq!((GzipCompressor.level_range(), GzipCompressor.default_level()), (Some(0..=9), 6));
+    assert!(ZstdCompressor.supports_dictionaries() && !GzipCompressor.supports_dictionaries());
+    assert!(ZstdCompressor.has_checksum() && GzipCompressor.has_checksum() && !Lz4Compressor.has_checksum());
+
+    assert_eq!([-1, 4, 22].map(|level| GzipCompressor.clamp_level(level)), [0, 4, 9]);
+    assert_eq!(Lz4Compressor.clamp_level(9), Lz4Compressor.default_level());
+    assert_eq!(XorCompressor.level_range(), None);
+
+    // out-of-range levels are clamped rather than panicking inside flate2
+    let compressed = compress_bytes(b"clamped", &GzipCompressor, 22).unwrap();
+    assert_eq!(decompress_bytes(&compressed, &DecompressLimits::default()).unwrap(), b"clamped");
+
+    let encrypted = EncryptedCompressor::new(Box::new(GzipCompressor), Secret::passphrase("levels".into()));
+    assert_eq!((encrypted.level_range(), encrypted.default_level()), (Some(0..=9), 6));
+    assert!(encrypted.has_checksum());
+}
+
+#[test]
+fn test_registered_format_is_detected() {
+    let format = registry::register(Format::new("xor", || Box::new(XorCompressor)).alias("x")).unwrap();
+    assert_eq!(registry::global().get("X").unwrap().extension(), "xor");
+
+    // names, aliases and extensions are unique across the registry
+    for clash in [Format::new("x", || Box::new(XorCompressor)), Format::new("zstd2", || Box::new(ZstdCompressor))] {
+        let err = registry::register(clash).err().unwrap();
+        assert!(matches!(err, CompressorError::InvalidInput(_)), "{:?}", err);
+    }
+
+    let mut compressed = Vec::new();
+    format.create().compress(&mut &b"third party"[..], &mut compressed, 0).unwrap();
+    assert_eq!(decompress_bytes(&compressed, &DecompressLimits::default()).unwrap(), b"third party");
+    let mut restored = String::new();
+    DecompressingReader::new(&compressed[..]).unwrap().read_to_string(&mut restored).unwrap();
+    assert_eq!(restored, "third party");
+
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("note.txt");
+    fs::write(&input, "by extension").unwrap();
+    CompressOptions::new().format(format.compressor()).run(&input, dir.path()).unwrap();
+    let restored = dir.path().join("restored.txt");
+    DecompressOptions::new().run(dir.path().join("note.txt.xor"), &restored).unwrap();
+    assert_eq!(fs::read_to_string(restored).unwrap(), "by extension");
+}
@@ -0,0 +1,100 @@
+use parallel_compressor::compressor::{compress_path_resume, compress_path_with, Compressor, ZstdCompressor};
+use parallel_compressor::error::{CompressorError, Result};
+use parallel_compressor::journal::JOURNAL_FILE;
+use parallel_compressor::manifest::{INDEX_FILE, MANIFEST_FILE};
+use parallel_compressor::options::{CompressMode, CompressOptions};
+use parallel_compressor::overwrite::OverwritePolicy;
+use parallel_compressor::progress::NoProgress;
+use std::fs;
+use std::io::{BufRead, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tempfile::tempdir;
+
+/// zstd that dies on its `fail_at`-th file, like a job killed part-way
+struct DyingCompressor {
+    calls: AtomicUsize,
+    fail_at: usize,
+}
+
+impl Compressor for DyingCompressor {
+    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write, level: i32) -> Result<()> {
+        if self.calls.fetch_add(1, Ordering::SeqCst) + 1 == self.fail_at {
+            return Err(CompressorError::InvalidInput("killed".into()));
+        }
+        ZstdCompressor.compress(input, output, level)
+    }
+    fn decompress(&self, input: &mut dyn Read, output: &mut dyn Write) -> Result<()> {
+        ZstdCompressor.decompress(input, output)
+    }
+    fn decoder<'a>(&'a self, input: Box<dyn BufRead + 'a>) -> Result<Box<dyn Read + 'a>> {
+        ZstdCompressor.decoder(input)
+    }
+    fn extension(&self) -> &'static str { "zst" }
+}
+
+#[test]
+fn test_resume_matches_uninterrupted_run() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("in");
+    fs::create_dir_all(input.join("sub")).unwrap();
+    for name in ["a.txt", "b.txt", "c.txt", "sub/d.txt", "sub/e.txt"] {
+        fs::write(input.join(name), name.repeat(5000)).unwrap();
+    }
+    let input_str = input.to_str().unwrap();
+
+    let reference = dir.path().join("reference");
+    compress_path_with(input_str, reference.to_str().unwrap(), 1, 3, &ZstdCompressor).unwrap();
+    assert!(!reference.join(JOURNAL_FILE).exists());
+
+    let output = dir.path().join("out");
+    let dying = DyingCompressor { calls: AtomicUsize::new(0), fail_at: 4 };
+    assert!(compress_path_with(input_str, output.to_str().unwrap(), 1, 3, &dying).is_err());
+    assert!(output.join(JOURNAL_FILE).exists());
+    assert!(!output.join(INDEX_FILE).exists());
+
+    // a different level would not reproduce the same artifacts
+    assert!(compress_path_resume(input_str, output.to_str().unwrap(), 1, 9, &ZstdCompressor, &NoProgress).is_err());
+    fs::write(output.join("sub/d.txt.zst.part"), b"stale").unwrap();
+
+    let skipped = compress_path_resume(input_str, output.to_str().unwrap(), 1, 3, &ZstdCompressor, &NoProgress).unwrap();
+    assert_eq!(skipped, 3);
+    assert!(!output.join("sub/d.txt.zst.part").exists());
+    assert!(!output.join(JOURNAL_FILE).exists());
+    for file in [MANIFEST_FILE, INDEX_FILE] {
+        assert_eq!(fs::read(output.join(file)).unwrap(), fs::read(reference.join(file)).unwrap(), "{}", file);
+    }
+}
+
+#[test]
+fn test_resume_twice_after_a_torn_journal_line() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("in");
+    fs::create_dir_all(&input).unwrap();
+    for name in ["a.txt", "b.txt", "c.txt", "d.txt", "e.txt"] {
+        fs::write(input.join(name), name.repeat(5000)).unwrap();
+    }
+    let input_str = input.to_str().unwrap();
+    let reference = dir.path().join("reference");
+    compress_path_with(input_str, reference.to_str().unwrap(), 1, 3, &ZstdCompressor).unwrap();
+
+    let output = dir.path().join("out");
+    let out_str = output.to_str().unwrap();
+    let dying = DyingCompressor { calls: AtomicUsize::new(0), fail_at: 3 };
+    assert!(compress_path_with(input_str, out_str, 1, 3, &dying).is_err());
+    // the crash hit while an entry was being journaled
+    let mut journal = fs::OpenOptions::new().append(true).open(output.join(JOURNAL_FILE)).unwrap();
+    journal.write_all(b"0123abcd\tc.t").unwrap();
+    drop(journal);
+
+    // the first resume dies too, after journaling one more entry
+    let dying = DyingCompressor { calls: AtomicUsize::new(0), fail_at: 2 };
+    assert!(compress_path_resume(input_str, out_str, 1, 3, &dying, &NoProgress).is_err());
+    // skipping existing outputs would keep whatever the dead job left half-done
+    let skip = CompressOptions::new().level(3).mode(CompressMode::Resume).overwrite(OverwritePolicy::SkipExisting);
+    assert!(matches!(skip.run(&input, &output), Err(CompressorError::InvalidInput(_))));
+    let skipped = compress_path_resume(input_str, out_str, 1, 3, &ZstdCompressor, &NoProgress).unwrap();
+    assert_eq!(skipped, 3);
+    for file in [MANIFEST_FILE, INDEX_FILE] {
+        assert_eq!(fs::read(output.join(file)).unwrap(), fs::read(reference.join(file)).unwrap(), "{}", file);
+    }
+}
@@ -0,0 +1,78 @@
+use parallel_compressor::cancel::CancelToken;
+use parallel_compressor::compressor::{Compressor, GzipCompressor, ZstdCompressor};
+use parallel_compressor::salvage::salvage_file_with;
+use std::fs;
+use tempfile::tempdir;
+
+mod common;
+
+fn text(seed: u8, size: usize) -> Vec<u8> {
+    common::xorshift(0x9E37_79B9_7F4A_7C15 ^ seed as u64).take(size).map(|state| b'a' + (state % 16) as u8).collect()
+}
+
+/// Three independently compressed frames back to back, plus where the second one starts
+fn three_frames(compressor: &dyn Compressor) -> (Vec<Vec<u8>>, Vec<u8>, usize) {
+    let parts: Vec<Vec<u8>> = (0..3).map(|i| text(i, 200_000)).collect();
+    let mut stream = Vec::new();
+    let mut second = 0;
+    for (i, part) in parts.iter().enumerate() {
+        if i == 1 {
+            second = stream.len();
+        }
+        compressor.compress(&mut &part[..], &mut stream, 3).unwrap();
+    }
+    (parts, stream, second)
+}
+
+#[test]
+fn test_salvage_truncated_zstd() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("data.zst");
+    let output = dir.path().join("data.out");
+    let data: Vec<u8> = (0..2_000_000u32).flat_map(|i| (i % 251).to_le_bytes()).collect();
+    let mut compressed = Vec::new();
+    ZstdCompressor.compress(&mut &data[..], &mut compressed, 1).unwrap();
+    fs::write(&input, &compressed[..compressed.len() / 2]).unwrap();
+
+    let report = salvage_file_with(input.to_str().unwrap(), output.to_str().unwrap(), &ZstdCompressor, &CancelToken::new()).unwrap();
+    assert!(report.truncated);
+    assert!(!report.is_complete());
+    let recovered = fs::read(&output).unwrap();
+    assert_eq!(recovered.len() as u64, report.recovered_bytes);
+    assert!(!recovered.is_empty());
+    assert_eq!(recovered, data[..recovered.len()]);
+}
+
+#[test]
+fn test_salvage_resyncs_after_corrupt_frame() {
+    let dir = tempdir().unwrap();
+    let compressors: [&dyn Compressor; 2] = [&ZstdCompressor, &GzipCompressor];
+    for compressor in compressors {
+        let input = dir.path().join(format!("data.{}", compressor.extension()));
+        let output = dir.path().join("data.out");
+        let (parts, mut stream, second) = three_frames(compressor);
+        // wreck the middle of the second frame
+        stream[second + 40..second + 200].fill(0x5A);
+        fs::write(&input, &stream).unwrap();
+
+        let report = salvage_file_with(input.to_str().unwrap(), output.to_str().unwrap(), compressor, &CancelToken::new()).unwrap();
+        assert_eq!(report.lost_ranges.len(), 1, "{}", compressor.extension());
+        assert!(!report.truncated);
+        let recovered = fs::read(&output).unwrap();
+        assert!(recovered.starts_with(&parts[0]));
+        assert!(recovered.ends_with(&parts[2]));
+    }
+}
+
+#[test]
+fn test_salvage_intact_file_is_complete() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("data.zst");
+    let output = dir.path().join("data.out");
+    let (parts, stream, _) = three_frames(&ZstdCompressor);
+    fs::write(&input, &stream).unwrap();
+
+    let report = salvage_file_with(input.to_str().unwrap(), output.to_str().unwrap(), &ZstdCompressor, &CancelToken::new()).unwrap();
+    assert!(report.is_complete());
+    assert_eq!(fs::read(&output).unwrap(), parts.concat());
+}
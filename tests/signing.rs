@@ -0,0 +1,107 @@
+use parallel_compressor::compressor::{compress_path_with, ZstdCompressor};
+use ed25519_dalek::Signer;
+use parallel_compressor::error::CompressorError;
+use parallel_compressor::manifest::{check_listing, INDEX_FILE};
+use parallel_compressor::options::DecompressOptions;
+use parallel_compressor::signing::{
+    generate_keypair, read_signed_listing, read_signed_manifest, read_signing_key, read_verifying_key, sign_manifest,
+    verify_artifact, SIGNATURE_FILE,
+};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn test_signed_manifest_detects_rewrites() {
+    let dir = tempdir().unwrap();
+    let input_dir = dir.path().join("input");
+    let output_dir = dir.path().join("output");
+    fs::create_dir(&input_dir).unwrap();
+    for i in 0..3 {
+        fs::write(input_dir.join(format!("file{}.txt", i)), format!("testdata{}", i)).unwrap();
+    }
+    compress_path_with(input_dir.to_str().unwrap(), output_dir.to_str().unwrap(), 2, 3, &ZstdCompressor).unwrap();
+
+    let secret = dir.path().join("signing.key");
+    let public = generate_keypair(&secret).unwrap();
+    sign_manifest(&output_dir, &read_signing_key(&secret).unwrap()).unwrap();
+    let trusted = read_verifying_key(&public).unwrap();
+
+    let listing = read_signed_listing(&output_dir, &trusted).unwrap();
+    assert_eq!(listing.len(), 3);
+    assert!(check_listing(&output_dir, &listing).unwrap().is_ok());
+    verify_artifact(&output_dir.join("file1.txt.zst"), &trusted).unwrap();
+
+    // swapping an artifact and rewriting its manifest line breaks the signature
+    let manifest_path = output_dir.join("manifest-sha256.txt");
+    let original = fs::read_to_string(&manifest_path).unwrap();
+    fs::write(output_dir.join("file1.txt.zst"), zstd::encode_all(&b"evil"[..], 3).unwrap()).unwrap();
+    let forged_hash = parallel_compressor::compressor::sha256_file(&output_dir.join("file1.txt.zst")).unwrap();
+    let forged: String = original
+        .lines()
+        .map(|line| if line.ends_with("file1.txt.zst") { format!("{}  file1.txt.zst\n", forged_hash) } else { format!("{}\n", line) })
+        .collect();
+    fs::write(&manifest_path, forged).unwrap();
+    assert!(read_signed_listing(&output_dir, &trusted).is_err());
+    assert!(verify_artifact(&output_dir.join("file1.txt.zst"), &trusted).is_err());
+
+    // the untouched manifest still flags the swapped artifact
+    fs::write(&manifest_path, original).unwrap();
+    assert!(verify_artifact(&output_dir.join("file1.txt.zst"), &trusted).is_err());
+
+    // a trusted decompression checks the bytes it decodes and keeps nothing from them
+    let restored = dir.path().join("file1.txt");
+    let err = DecompressOptions::new().trusted_key(&trusted).run(output_dir.join("file1.txt.zst"), &restored).unwrap_err();
+    assert!(matches!(err, CompressorError::Integrity { .. }), "{:?}", err);
+    assert!(!restored.exists());
+    // bytes after the stream are covered too
+    let mut padded = fs::read(output_dir.join("file2.txt.zst")).unwrap();
+    padded.extend_from_slice(b"trailing");
+    fs::write(output_dir.join("file2.txt.zst"), padded).unwrap();
+    let err = DecompressOptions::new().trusted_key(&trusted).run(output_dir.join("file2.txt.zst"), &restored).unwrap_err();
+    assert!(matches!(err, CompressorError::Integrity { .. } | CompressorError::CorruptData { .. }), "{:?}", err);
+    assert!(!restored.exists());
+    DecompressOptions::new().trusted_key(&trusted).run(output_dir.join("file0.txt.zst"), &restored).unwrap();
+    assert_eq!(fs::read_to_string(&restored).unwrap(), "testdata0");
+
+    // a different key is never trusted
+    let other = generate_keypair(&dir.path().join("other.key")).unwrap();
+    assert!(read_signed_listing(&output_dir, &read_verifying_key(&other).unwrap()).is_err());
+}
+
+#[test]
+fn test_trusted_restore_uses_only_the_signed_index() {
+    let dir = tempdir().unwrap();
+    let input_dir = dir.path().join("input");
+    let output_dir = dir.path().join("output");
+    fs::create_dir(&input_dir).unwrap();
+    for i in 0..2 {
+        fs::write(input_dir.join(format!("file{}.txt", i)), format!("testdata{}", i)).unwrap();
+    }
+    compress_path_with(input_dir.to_str().unwrap(), output_dir.to_str().unwrap(), 2, 3, &ZstdCompressor).unwrap();
+    let secret = dir.path().join("signing.key");
+    let trusted = read_verifying_key(&generate_keypair(&secret).unwrap()).unwrap();
+    let signing_key = read_signing_key(&secret).unwrap();
+    sign_manifest(&output_dir, &signing_key).unwrap();
+
+    assert_eq!(read_signed_manifest(&output_dir, &trusted).unwrap().entries.len(), 2);
+    let restored = dir.path().join("restored");
+    let report = DecompressOptions::new().trusted_key(&trusted).run(&output_dir, &restored).unwrap();
+    assert_eq!(report.files, 2);
+    assert_eq!(fs::read_to_string(restored.join("file1.txt")).unwrap(), "testdata1");
+
+    // the listing is untouched, but the index now restores file1 under another name
+    let index_path = output_dir.join(INDEX_FILE);
+    let index = fs::read_to_string(&index_path).unwrap();
+    fs::write(&index_path, index.replace("\tfile1.txt\t", "\tplanted.txt\t")).unwrap();
+    assert!(read_signed_listing(&output_dir, &trusted).is_err());
+    let elsewhere = dir.path().join("elsewhere");
+    let err = DecompressOptions::new().trusted_key(&trusted).run(&output_dir, &elsewhere).unwrap_err();
+    assert!(matches!(err, CompressorError::Signature(_)), "{:?}", err);
+    assert!(!elsewhere.join("planted.txt").exists());
+
+    // a signature over the listing alone vouches for nothing a restore reads from the index
+    let listing = fs::read(output_dir.join("manifest-sha256.txt")).unwrap();
+    fs::write(output_dir.join(SIGNATURE_FILE), format!("{}\n", hex::encode(signing_key.sign(&listing).to_bytes()))).unwrap();
+    assert!(read_signed_listing(&output_dir, &trusted).is_ok());
+    assert!(matches!(read_signed_manifest(&output_dir, &trusted), Err(CompressorError::Signature(_))));
+}
@@ -0,0 +1,92 @@
+use parallel_compressor::compressor::{compress_path_with, decompress_file_with, decompress_path_with, sha256_file, ZstdCompressor};
+use parallel_compressor::error::CompressorError;
+use parallel_compressor::manifest::{Manifest, INDEX_FILE, MANIFEST_FILE};
+use parallel_compressor::source::{self, SourcePolicy};
+use std::fs::{self, File, FileTimes};
+use std::time::{Duration, SystemTime};
+use tempfile::tempdir;
+
+#[test]
+fn test_single_file_replaced_with_metadata() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("notes.txt");
+    fs::write(&input, "notes ".repeat(1000)).unwrap();
+    let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    File::options().write(true).open(&input).unwrap().set_times(FileTimes::new().set_modified(mtime)).unwrap();
+    let hash = sha256_file(&input).unwrap();
+
+    let artifact = dir.path().join("notes.txt.zst");
+    compress_path_with(input.to_str().unwrap(), artifact.to_str().unwrap(), 1, 3, &ZstdCompressor).unwrap();
+    source::replace_compressed(&input, &artifact, &ZstdCompressor, SourcePolicy::RemoveVerified).unwrap();
+    assert!(!input.exists());
+    assert_eq!(fs::metadata(&artifact).unwrap().modified().unwrap(), mtime);
+
+    decompress_file_with(artifact.to_str().unwrap(), input.to_str().unwrap(), &ZstdCompressor).unwrap();
+    source::replace_decompressed(&artifact, &input, &ZstdCompressor, SourcePolicy::RemoveVerified).unwrap();
+    assert!(!artifact.exists());
+    assert_eq!(sha256_file(&input).unwrap(), hash);
+    assert_eq!(fs::metadata(&input).unwrap().modified().unwrap(), mtime);
+}
+
+#[test]
+fn test_failed_verification_keeps_source() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("data.bin");
+    fs::write(&input, "data".repeat(1000)).unwrap();
+    let artifact = dir.path().join("data.bin.zst");
+    fs::write(&artifact, zstd::encode_all(&b"something else"[..], 3).unwrap()).unwrap();
+
+    assert!(source::replace_compressed(&input, &artifact, &ZstdCompressor, SourcePolicy::RemoveVerified).is_err());
+    assert!(input.exists());
+}
+
+#[test]
+fn test_folder_round_trip_removes_sources() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("in");
+    fs::create_dir_all(input.join("sub")).unwrap();
+    for name in ["a.txt", "b.txt", "sub/c.txt"] {
+        fs::write(input.join(name), name.repeat(500)).unwrap();
+    }
+    let output = dir.path().join("out");
+    compress_path_with(input.to_str().unwrap(), output.to_str().unwrap(), 1, 3, &ZstdCompressor).unwrap();
+    // edited after compression: its artifact is stale, so the source stays
+    fs::write(input.join("b.txt"), "changed").unwrap();
+
+    let removed = source::replace_compressed_folder(&input, &output, &ZstdCompressor, SourcePolicy::Remove).unwrap();
+    assert_eq!(removed, 2);
+    assert!(!input.join("a.txt").exists() && !input.join("sub/c.txt").exists());
+    assert!(input.join("b.txt").exists());
+
+    let restored = dir.path().join("restored");
+    decompress_path_with(output.to_str().unwrap(), restored.to_str().unwrap(), &ZstdCompressor).unwrap();
+    assert_eq!(source::replace_decompressed_folder(&output, &restored, &Manifest::load(&output).unwrap(), SourcePolicy::Remove).unwrap(), 3);
+    for file in ["a.txt.zst", "b.txt.zst", "sub/c.txt.zst", MANIFEST_FILE, INDEX_FILE] {
+        assert!(!output.join(file).exists(), "{}", file);
+    }
+    assert_eq!(fs::read_to_string(restored.join("sub/c.txt")).unwrap(), "sub/c.txt".repeat(500));
+}
+
+#[test]
+fn test_sources_stay_unless_their_artifact_matches_the_manifest() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("in");
+    fs::create_dir_all(&input).unwrap();
+    for name in ["a.txt", "b.txt"] {
+        fs::write(input.join(name), name.repeat(500)).unwrap();
+    }
+    let output = dir.path().join("out");
+    compress_path_with(input.to_str().unwrap(), output.to_str().unwrap(), 1, 3, &ZstdCompressor).unwrap();
+
+    // a missing artifact leaves its source alone
+    fs::remove_file(output.join("a.txt.zst")).unwrap();
+    // a damaged one stops the run before its source is touched
+    fs::write(output.join("b.txt.zst"), b"not what the manifest says").unwrap();
+    let err = source::replace_compressed_folder(&input, &output, &ZstdCompressor, SourcePolicy::Remove).unwrap_err();
+    assert!(matches!(err, CompressorError::Integrity { .. }));
+    assert!(input.join("a.txt").exists() && input.join("b.txt").exists());
+
+    fs::remove_file(output.join("b.txt.zst")).unwrap();
+    assert_eq!(source::replace_compressed_folder(&input, &output, &ZstdCompressor, SourcePolicy::Remove).unwrap(), 0);
+    assert!(input.join("a.txt").exists() && input.join("b.txt").exists());
+}
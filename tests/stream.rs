@@ -0,0 +1,109 @@
+use parallel_compressor::buffer::{compress_bytes, decompress_bytes};
+use parallel_compressor::compressor::{Compressor, GzipCompressor, Lz4Compressor, ZstdCompressor};
+use parallel_compressor::crypto::{EncryptedCompressor, Secret};
+use parallel_compressor::error::{CompressorError, Result};
+use parallel_compressor::limits::DecompressLimits;
+use parallel_compressor::recipients::RecipientCompressor;
+use parallel_compressor::stream::{CompressingWriter, DecompressingReader};
+use std::io::{BufRead, Read, Write};
+
+/// zstd without an `encoder` of its own
+struct PlainZstd;
+
+impl Compressor for PlainZstd {
+    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write, level: i32) -> Result<()> {
+        ZstdCompressor.compress(input, output, level)
+    }
+    fn decompress(&self, input: &mut dyn Read, output: &mut dyn Write) -> Result<()> {
+        ZstdCompressor.decompress(input, output)
+    }
+    fn decoder<'a>(&'a self, input: Box<dyn BufRead + 'a>) -> Result<Box<dyn Read + 'a>> {
+        ZstdCompressor.decoder(input)
+    }
+    fn extension(&self) -> &'static str { "zst" }
+    fn magic(&self) -> &'static [u8] { ZstdCompressor.magic() }
+}
+
+fn lines() -> Vec<u8> {
+    (0..2000).map(|i| format!("line {}\n", i)).collect::<String>().into_bytes()
+}
+
+#[test]
+fn test_writer_and_reader_round_trip_every_format() {
+    let data = lines();
+    let formats: [&dyn Compressor; 3] = [&ZstdCompressor, &GzipCompressor, &Lz4Compressor];
+    for compressor in formats {
+        let mut writer = CompressingWriter::new(b"prefix".to_vec(), compressor, 3).unwrap();
+        for chunk in data.chunks(777) {
+            writer.write_all(chunk).unwrap();
+        }
+        let compressed = writer.finish().unwrap();
+        assert_eq!(&compressed[..6], b"prefix");
+        assert_eq!(decompress_bytes(&compressed[6..], &DecompressLimits::default()).unwrap(), data);
+
+        // `impl BufRead` consumers such as `lines` work on the detected format
+        let reader = DecompressingReader::new(&compressed[6..]).unwrap();
+        assert_eq!(reader.compressor().extension(), compressor.extension());
+        let read: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+        assert_eq!((read.len(), read[1999].as_str()), (2000, "line 1999"));
+    }
+
+    // streams from elsewhere in the library read back the same way
+    let mut restored = Vec::new();
+    let compressed = compress_bytes(&data, &GzipCompressor, 6).unwrap();
+    DecompressingReader::new(&compressed[..]).unwrap().read_to_end(&mut restored).unwrap();
+    assert_eq!(restored, data);
+}
+
+#[test]
+fn test_writer_moves_to_another_thread() {
+    let data = lines();
+    let mut writer = CompressingWriter::new(Vec::new(), &ZstdCompressor, 3).unwrap();
+    writer.write_all(&data[..100]).unwrap();
+    let worker = std::thread::spawn(move || {
+        writer.write_all(&data[100..]).unwrap();
+        writer.finish().unwrap()
+    });
+    let compressed = worker.join().unwrap();
+    assert_eq!(decompress_bytes(&compressed, &DecompressLimits::default()).unwrap(), lines());
+}
+
+#[test]
+fn test_format_without_encoder_is_refused() {
+    let err = CompressingWriter::new(Vec::new(), &PlainZstd, 3).err().unwrap();
+    assert!(matches!(err, CompressorError::UnsupportedFormat(_)), "{:?}", err);
+    // its streams still decode
+    let mut restored = Vec::new();
+    let compressed = zstd::encode_all(&lines()[..], 3).unwrap();
+    DecompressingReader::with_compressor(&compressed[..], &PlainZstd).unwrap().read_to_end(&mut restored).unwrap();
+    assert_eq!(restored, lines());
+}
+
+#[test]
+fn test_encrypted_streams_need_their_compressor() {
+    let data = lines();
+    let passphrase = EncryptedCompressor::new(Box::new(ZstdCompressor), Secret::passphrase("correct horse".into()));
+    let identity = age::x25519::Identity::generate();
+    let age = RecipientCompressor::for_recipients(Box::new(GzipCompressor), vec![identity.to_public()]);
+    let age_reader = RecipientCompressor::for_identities(Box::new(GzipCompressor), vec![identity]);
+
+    for (writer, reader) in [(&passphrase as &dyn Compressor, &passphrase as &dyn Compressor), (&age, &age_reader)] {
+        let mut encoder = CompressingWriter::new(Vec::new(), writer, 3).unwrap();
+        encoder.write_all(&data).unwrap();
+        let encrypted = encoder.finish().unwrap();
+
+        let err = DecompressingReader::new(&encrypted[..]).err().unwrap();
+        assert!(matches!(err, CompressorError::UnsupportedFormat(_)), "{:?}", err);
+        let mut restored = Vec::new();
+        DecompressingReader::with_compressor(&encrypted[..], reader).unwrap().read_to_end(&mut restored).unwrap();
+        assert_eq!(restored, data);
+    }
+}
+
+#[test]
+fn test_short_input_is_unsupported() {
+    for input in [&b""[..], b"\x28\xB5"] {
+        let err = DecompressingReader::new(input).err().unwrap();
+        assert!(matches!(err, CompressorError::UnsupportedFormat(_)), "{:?}", err);
+    }
+}
@@ -1,5 +1,5 @@
 use parallel_compressor::compressor::{compress_single_file, decompress_file};
-use indicatif::MultiProgress;
+use parallel_compressor::progress::NoProgress;
 use tempfile::NamedTempFile;
 use std::io::Write;
 
@@ -23,8 +23,7 @@ fn test_size(data: &[u8]) {
         let input_path = input.path();
         let compressed = NamedTempFile::new().unwrap();
         let compressed_path = compressed.path().with_extension(ext);
-        let mp = MultiProgress::new();
-        compress_single_file(input_path, &compressed_path, &mp, 3).unwrap();
+        compress_single_file(input_path, &compressed_path, &NoProgress, 3).unwrap();
         let output = NamedTempFile::new().unwrap();
         decompress_file(compressed_path.to_str().unwrap(), output.path().to_str().unwrap()).unwrap();
         let out_bytes = std::fs::read(output.path()).unwrap();
@@ -40,7 +39,7 @@ fn test_256kb_file() {
 
 #[test]
 fn test_1mb_file() {
-    let data = make_data(1 * 1024 * 1024);
+    let data = make_data(1024 * 1024);
     test_size(&data);
 }
 